@@ -3,10 +3,14 @@ use std::env;
 use std::fs;
 use std::path::Path;
 
-fn generate_module_constants(config_path: &str, dest_path: &Path, constants_types: HashMap<&str, &str>) {
-    let config_content = fs::read_to_string(config_path)
-        .expect("Failed to read extractor.config.toml");
-    
+fn generate_module_constants(
+    config_path: &str,
+    dest_path: &Path,
+    constants_types: HashMap<&str, &str>,
+) {
+    let config_content =
+        fs::read_to_string(config_path).expect("Failed to read extractor.config.toml");
+
     let mut constants = String::new();
 
     for line in config_content.lines() {
@@ -19,12 +23,8 @@ fn generate_module_constants(config_path: &str, dest_path: &Path, constants_type
         if let Some((key, value)) = line.split_once('=') {
             let key = key.trim();
             let value = value.trim();
-            // wrap in quotes se stringa
-            let value_code = if value.starts_with('"') {
-                value.to_string()
-            } else {
-                value.to_string()
-            };
+            // string values keep their quotes, everything else is emitted as-is
+            let value_code = value.to_string();
             let value_type = constants_types.get(key).unwrap_or(&"usize");
 
             constants.push_str(&format!(
@@ -34,7 +34,7 @@ fn generate_module_constants(config_path: &str, dest_path: &Path, constants_type
         }
     }
 
-    fs::write(&dest_path, constants).expect("Failed to write build_constants.rs");
+    fs::write(dest_path, constants).expect("Failed to write build_constants.rs");
     println!("cargo:rerun-if-changed={}", config_path);
 }
 
@@ -43,9 +43,17 @@ fn main() {
         ("DEFAULT_INSERT_BATCH_SIZE", "usize"),
         ("DEFAULT_INSERT_BATCH_TIMEOUT_MS", "usize"),
         ("DEFAULT_FLUSH_INTERVAL_MS", "u64"),
-        ("DEFAULT_PDFIUM_LIB_PATH", "&'static str"),
+        ("DEFAULT_PDFIUM_LIB_PATH", "&str"),
         ("DEFAULT_MAX_TOKENS", "usize"),
         ("DEFAULT_TOKENS_MIN_LENGTH", "usize"),
+        ("DEFAULT_CONTENT_MODE", "&str"),
+        ("DEFAULT_MAX_CONTENT_BYTES", "usize"),
+        ("DEFAULT_CHANNEL_CAPACITY", "usize"),
+        ("DEFAULT_RETRY_MAX_ATTEMPTS", "u32"),
+        ("DEFAULT_RETRY_BASE_DELAY_MS", "u64"),
+        ("DEFAULT_TIMEOUT_MS", "u64"),
+        ("DEFAULT_ISOLATION", "&str"),
+        ("DEFAULT_CHILD_COMMAND", "&str"),
     ]);
 
     let out_dir = env::var("OUT_DIR").unwrap();
@@ -53,18 +61,75 @@ fn main() {
     let extractor_config_path = "build/extractor.config.toml";
     let extractor_output_path = Path::new(&out_dir).join("extractor_constants.rs");
 
-    generate_module_constants(extractor_config_path, &extractor_output_path, extractor_constant_types_map);
+    generate_module_constants(
+        extractor_config_path,
+        &extractor_output_path,
+        extractor_constant_types_map,
+    );
 
     let storage_constant_types_map = HashMap::from([
         ("DEFAULT_STORAGE_WORKER_RECEIVE_TIMEOUT_MS", "u64"),
-        ("DEFAULT_STORAGE_DB_JOURNAL_MODE", "&'static str"),
-        ("DEFAULT_STORAGE_DB_CACHE_SIZE", "&'static str"),
-        ("DEFAULT_STORAGE_DB_TEMP_STORE", "&'static str"),
-        ("DEFAULT_STORAGE_DB_LOCKING_MODE", "&'static str"),
-        ("DEFAULT_STORAGE_DB_PATH", "&'static str"),
+        ("DEFAULT_STORAGE_DB_JOURNAL_MODE", "&str"),
+        ("DEFAULT_STORAGE_DB_CACHE_SIZE", "&str"),
+        ("DEFAULT_STORAGE_DB_TEMP_STORE", "&str"),
+        ("DEFAULT_STORAGE_DB_LOCKING_MODE", "&str"),
+        ("DEFAULT_STORAGE_DB_PATH", "&str"),
+        ("DEFAULT_STORAGE_TRIGRAM_INDEX", "bool"),
+        ("DEFAULT_STORAGE_FUZZY_MIN_SIMILARITY", "f64"),
+        ("DEFAULT_STORAGE_CHANNEL_CAPACITY", "usize"),
     ]);
     let storage_config_path = "build/storage.config.toml";
     let storage_output_path = Path::new(&out_dir).join("storage_constants.rs");
 
-    generate_module_constants(storage_config_path, &storage_output_path, storage_constant_types_map);
+    generate_module_constants(
+        storage_config_path,
+        &storage_output_path,
+        storage_constant_types_map,
+    );
+
+    let api_constant_types_map = HashMap::from([
+        ("DEFAULT_API_SERVER_ADDRESS", "&str"),
+        ("DEFAULT_API_SEARCH_LIMIT", "usize"),
+        ("DEFAULT_API_SEARCH_MAX_LIMIT", "usize"),
+    ]);
+    let api_config_path = "build/api.config.toml";
+    let api_output_path = Path::new(&out_dir).join("api_constants.rs");
+
+    generate_module_constants(api_config_path, &api_output_path, api_constant_types_map);
+
+    let scaling_constant_types_map = HashMap::from([
+        ("DEFAULT_AUTOSCALE_INTERVAL_MS", "u64"),
+        ("DEFAULT_AUTOSCALE_QUEUE_HIGH_WATER", "usize"),
+        ("DEFAULT_AUTOSCALE_MAX_LOAD", "f64"),
+    ]);
+    let scaling_config_path = "build/scaling.config.toml";
+    let scaling_output_path = Path::new(&out_dir).join("scaling_constants.rs");
+
+    generate_module_constants(
+        scaling_config_path,
+        &scaling_output_path,
+        scaling_constant_types_map,
+    );
+
+    let watcher_constant_types_map = HashMap::from([
+        ("DEFAULT_WATCH_DEBOUNCE_MS", "u64"),
+        ("DEFAULT_WATCH_MAX_DELAY_MS", "u64"),
+    ]);
+    let watcher_config_path = "build/watcher.config.toml";
+    let watcher_output_path = Path::new(&out_dir).join("watcher_constants.rs");
+
+    generate_module_constants(
+        watcher_config_path,
+        &watcher_output_path,
+        watcher_constant_types_map,
+    );
+
+    let jobs_constant_types_map = HashMap::from([
+        ("DEFAULT_JOBS_RETENTION_SECS", "i64"),
+        ("DEFAULT_JOBS_MAX_FINISHED", "usize"),
+    ]);
+    let jobs_config_path = "build/jobs.config.toml";
+    let jobs_output_path = Path::new(&out_dir).join("jobs_constants.rs");
+
+    generate_module_constants(jobs_config_path, &jobs_output_path, jobs_constant_types_map);
 }
@@ -1,17 +1,401 @@
-use crate::engine::scanner::ScannerChannelTx;
+pub mod server;
+pub mod constants {
+    include!(concat!(env!("OUT_DIR"), "/api_constants.rs"));
+}
+
+use std::env;
+
+use once_cell::sync::Lazy;
+
+use crate::{
+    engine::{
+        EngineError, Sender,
+        jobs::{ScanJob, ScanJobs, ScanProgress},
+        lanes::Priority,
+        scaling::{AutoscalePolicy, ScalableStage, StageStatus},
+        scanner::{ScannerChannelTx, commands::ScannerCommand},
+        unbounded_channel,
+        watcher::{WatcherChannelTx, WatcherCommand},
+    },
+    entities::{
+        container::Container,
+        document::Document,
+        document::DocumentError,
+        duplicates::DuplicateReport,
+        failure::{FailedDocument, FailureKind},
+        find::PathHit,
+        listing::DocumentListing,
+        reconcile::{ReconcileCandidate, ReconcileSummary},
+        search::{SearchHit, SearchResults},
+        stats::IndexStats,
+    },
+    storage::{
+        PruneSummary, StorageChannelTx, StorageError, commands::StorageCommand, query::SearchMode,
+    },
+};
+
+pub static API_SERVER_ADDRESS: Lazy<&'static str> = Lazy::new(|| {
+    Box::leak(
+        env::var("API_SERVER_ADDRESS")
+            .unwrap_or_else(|_| constants::DEFAULT_API_SERVER_ADDRESS.into())
+            .into_boxed_str(),
+    )
+});
+
+static API_SEARCH_LIMIT: Lazy<usize> = Lazy::new(|| {
+    env::var("API_SEARCH_LIMIT")
+        .ok()
+        .and_then(|s| s.parse().ok())
+        .unwrap_or(constants::DEFAULT_API_SEARCH_LIMIT)
+});
+
+static API_SEARCH_MAX_LIMIT: Lazy<usize> = Lazy::new(|| {
+    env::var("API_SEARCH_MAX_LIMIT")
+        .ok()
+        .and_then(|s| s.parse().ok())
+        .unwrap_or(constants::DEFAULT_API_SEARCH_MAX_LIMIT)
+});
+
+#[derive(Debug)]
+pub enum ApiError {
+    ChannelSendError,
+    ChannelRecvError,
+    NotFound,
+    StorageError(StorageError),
+    EngineError(EngineError),
+}
+
+impl From<StorageError> for ApiError {
+    fn from(err: StorageError) -> Self {
+        match err {
+            StorageError::DocumentError(DocumentError::NotFound) => ApiError::NotFound,
+            err => ApiError::StorageError(err),
+        }
+    }
+}
 
+#[derive(Debug, Clone)]
 pub struct Api {
     channel_scanner_tx: ScannerChannelTx,
+    channel_storage_tx: StorageChannelTx,
+    /// Set when the engine watches the scanned folders.
+    channel_watcher_tx: Option<WatcherChannelTx>,
+    jobs: ScanJobs,
+    stages: Vec<ScalableStage>,
 }
 
 impl Api {
-    pub fn new(channel_scanner_tx: ScannerChannelTx) -> Self {
-        Api { channel_scanner_tx }
+    pub fn new(channel_scanner_tx: ScannerChannelTx, channel_storage_tx: StorageChannelTx) -> Self {
+        Api {
+            channel_scanner_tx,
+            channel_storage_tx,
+            channel_watcher_tx: None,
+            jobs: ScanJobs::default(),
+            stages: Vec::new(),
+        }
+    }
+
+    /// Makes `scan_path` watch the folders it scans.
+    pub fn set_watcher_tx(&mut self, channel_watcher_tx: Option<WatcherChannelTx>) {
+        self.channel_watcher_tx = channel_watcher_tx;
+    }
+
+    /// Registry the scan jobs are created in, shared with the watcher.
+    pub fn set_jobs(&mut self, jobs: ScanJobs) {
+        self.jobs = jobs;
+    }
+
+    /// Makes the stage resizable through `scale_stage` and `autoscale_stage`.
+    pub fn add_stage(&mut self, stage: ScalableStage) {
+        self.stages.push(stage);
+    }
+
+    fn stage(&self, name: &str) -> Result<&ScalableStage, ApiError> {
+        self.stages
+            .iter()
+            .find(|stage| stage.get_name() == name)
+            .ok_or(ApiError::NotFound)
+    }
+
+    pub fn stages(&self) -> Vec<StageStatus> {
+        self.stages.iter().map(ScalableStage::get_status).collect()
     }
 
-    pub fn scan_path(&self, path: String) -> Result<(), String> {
+    /// Sets the stage's worker count and turns its autoscaler off.
+    pub fn scale_stage(&self, name: &str, workers: usize) -> Result<StageStatus, ApiError> {
+        let stage = self.stage(name)?;
+
+        stage.set_autoscale(None).map_err(ApiError::EngineError)?;
+        stage.scale(workers).map_err(ApiError::EngineError)?;
+
+        Ok(stage.get_status())
+    }
+
+    /// Hands the stage's worker count to the autoscaler, or takes it back
+    /// with `None`.
+    pub fn autoscale_stage(
+        &self,
+        name: &str,
+        policy: Option<AutoscalePolicy>,
+    ) -> Result<StageStatus, ApiError> {
+        let stage = self.stage(name)?;
+
+        stage.set_autoscale(policy).map_err(ApiError::EngineError)?;
+
+        Ok(stage.get_status())
+    }
+
+    fn request<T>(
+        &self,
+        command: impl FnOnce(Sender<Result<T, StorageError>>) -> StorageCommand,
+    ) -> Result<T, ApiError> {
+        let (resp_tx, resp_rx) = unbounded_channel::<Result<T, StorageError>>();
+
+        self.channel_storage_tx
+            .send(command(resp_tx))
+            .map_err(|_| ApiError::ChannelSendError)?;
+
+        let result = resp_rx.recv().map_err(|_| ApiError::ChannelRecvError)?;
+
+        result.map_err(ApiError::from)
+    }
+
+    /// Queues a scan of `path` and returns its job, which tracks progress
+    /// until every document found has been stored or given up on.
+    pub fn scan_path(&self, path: String) -> Result<ScanJob, ApiError> {
+        self.scan_path_with_priority(path, Priority::Normal)
+    }
+
+    /// Like `scan_path`, in the lane of `priority`. A higher priority scan
+    /// and its documents go ahead of lower priority work already queued,
+    /// including a walk in progress. With a watcher the folder is watched
+    /// from then on.
+    pub fn scan_path_with_priority(
+        &self,
+        path: String,
+        priority: Priority,
+    ) -> Result<ScanJob, ApiError> {
+        if let Some(channel_watcher_tx) = &self.channel_watcher_tx {
+            channel_watcher_tx
+                .send(WatcherCommand::Watch(path.clone()))
+                .map_err(|_| ApiError::ChannelSendError)?;
+        }
+
+        let job = self.jobs.create(path, priority);
+
         self.channel_scanner_tx
-            .send(path)
-            .map_err(|e| format!("Failed to send scan command: {}", e))
+            .send(ScannerCommand::Scan(job.clone()))
+            .map_err(|_| ApiError::ChannelSendError)?;
+
+        Ok(job)
+    }
+
+    /// Rescans the documents whose extraction failed, as a new scan job.
+    /// Permanent failures are retried too when `include_permanent` is set.
+    pub fn retry_failed(&self, include_permanent: bool) -> Result<ScanJob, ApiError> {
+        let paths = self.request(|resp_tx| StorageCommand::ListRetryableFailures {
+            include_permanent,
+            resp_tx,
+        })?;
+        let job = self
+            .jobs
+            .create("retry-failed".to_string(), Priority::Normal);
+
+        self.channel_scanner_tx
+            .send(ScannerCommand::Rescan {
+                job: job.clone(),
+                paths,
+            })
+            .map_err(|_| ApiError::ChannelSendError)?;
+
+        Ok(job)
+    }
+
+    pub fn scan_job(&self, id: &str) -> Result<ScanJob, ApiError> {
+        self.jobs.get(id).ok_or(ApiError::NotFound)
+    }
+
+    pub fn scan_jobs(&self) -> Vec<ScanProgress> {
+        self.jobs.list()
+    }
+
+    /// Stops the watcher after sending its pending changes. Call it before
+    /// `finish_scans`.
+    pub fn finish_watching(&self) -> Result<(), ApiError> {
+        match &self.channel_watcher_tx {
+            Some(channel_watcher_tx) => channel_watcher_tx
+                .send(WatcherCommand::Shutdown)
+                .map_err(|_| ApiError::ChannelSendError),
+            None => Ok(()),
+        }
+    }
+
+    /// Lets the scanner finish the queued scans and stop. The extractor
+    /// workers exit once every scanned document has been flushed to storage.
+    pub fn finish_scans(&self) -> Result<(), ApiError> {
+        self.channel_scanner_tx
+            .send(ScannerCommand::Shutdown)
+            .map_err(|_| ApiError::ChannelSendError)
+    }
+
+    /// Stops the storage thread after everything sent before has been
+    /// persisted. Call it once the extractor workers have returned.
+    pub fn shutdown_storage(&self) -> Result<(), ApiError> {
+        self.request(|resp_tx| StorageCommand::Shutdown {
+            resp_tx: Some(resp_tx),
+        })
+    }
+
+    pub fn search(
+        &self,
+        query: &str,
+        limit: usize,
+        offset: usize,
+    ) -> Result<Vec<SearchHit>, ApiError> {
+        self.request(|resp_tx| StorageCommand::Search {
+            query: query.to_string(),
+            limit,
+            offset,
+            facets: false,
+            mode: SearchMode::Exact,
+            collapse: false,
+            resp_tx,
+        })
+        .map(SearchResults::into_hits)
+    }
+
+    /// Like [`Api::search`], plus the total match count and, when `facets` is
+    /// set, counts per extension, container type, container path and status.
+    /// `SearchMode::Fuzzy` matches text terms by trigram overlap. With
+    /// `collapse`, identical documents are returned as one hit, see
+    /// [`SearchHit::get_copies`]; facets still count every document.
+    pub fn search_results(
+        &self,
+        query: &str,
+        limit: usize,
+        offset: usize,
+        facets: bool,
+        mode: SearchMode,
+        collapse: bool,
+    ) -> Result<SearchResults, ApiError> {
+        self.request(|resp_tx| StorageCommand::Search {
+            query: query.to_string(),
+            limit,
+            offset,
+            facets,
+            mode,
+            collapse,
+            resp_tx,
+        })
+    }
+
+    /// Locate documents by path or file name substrings and globs, see
+    /// [`PathHit::find`].
+    pub fn find(&self, pattern: &str, limit: usize) -> Result<Vec<PathHit>, ApiError> {
+        self.request(|resp_tx| StorageCommand::Find {
+            pattern: pattern.to_string(),
+            limit,
+            resp_tx,
+        })
+    }
+
+    /// Rows of `documents_view` in path order, filtered by `query` when given.
+    pub fn list_documents(
+        &self,
+        query: Option<&str>,
+        limit: Option<usize>,
+        offset: usize,
+    ) -> Result<Vec<DocumentListing>, ApiError> {
+        self.request(|resp_tx| StorageCommand::ListDocuments {
+            query: query.map(String::from),
+            limit,
+            offset,
+            resp_tx,
+        })
+    }
+
+    /// Documents whose extraction failed, most recent first.
+    pub fn extraction_failures(
+        &self,
+        kind: Option<FailureKind>,
+        limit: usize,
+        offset: usize,
+    ) -> Result<Vec<FailedDocument>, ApiError> {
+        self.request(|resp_tx| StorageCommand::ListFailures {
+            kind,
+            limit,
+            offset,
+            resp_tx,
+        })
+    }
+
+    /// Groups of documents with identical content across folders and
+    /// archives, largest waste first.
+    pub fn duplicates(&self, limit: usize, offset: usize) -> Result<DuplicateReport, ApiError> {
+        self.request(|resp_tx| StorageCommand::Duplicates {
+            limit,
+            offset,
+            resp_tx,
+        })
+    }
+
+    pub fn get_document(&self, id: i64) -> Result<Document, ApiError> {
+        self.request(|resp_tx| StorageCommand::GetDocument { id, resp_tx })
+    }
+
+    pub fn delete_document(&self, id: i64) -> Result<(), ApiError> {
+        self.request(|resp_tx| StorageCommand::DeleteDocument { id, resp_tx })
+    }
+
+    pub fn list_containers(&self) -> Result<Vec<Container>, ApiError> {
+        self.request(|resp_tx| StorageCommand::ListContainers { resp_tx })
+    }
+
+    pub fn stats(&self) -> Result<IndexStats, ApiError> {
+        self.request(|resp_tx| StorageCommand::Stats { resp_tx })
+    }
+
+    pub fn purge_path(&self, path: &str) -> Result<usize, ApiError> {
+        self.request(|resp_tx| StorageCommand::PurgePath {
+            path: path.to_string(),
+            resp_tx,
+        })
+    }
+
+    /// Marks deleted the documents under `root`, or in the whole index, whose
+    /// file or archive no longer exists. `prune` then removes them.
+    ///
+    /// The files are looked for on the caller's thread, between listing the
+    /// documents and marking the missing ones, so the storage thread never
+    /// waits on the file system.
+    pub fn reconcile(&self, root: Option<&str>) -> Result<ReconcileSummary, ApiError> {
+        let candidates = self.request(|resp_tx| StorageCommand::ListReconcileCandidates {
+            root: root.map(String::from),
+            resp_tx,
+        })?;
+
+        let (missing, vanished) = ReconcileCandidate::find_missing(&candidates);
+        let vanished_containers = vanished.len();
+
+        let deleted = self.request(|resp_tx| StorageCommand::MarkDocumentsDeleted {
+            ids: missing,
+            vanished_containers: vanished,
+            resp_tx,
+        })?;
+
+        Ok(ReconcileSummary::new(
+            candidates.len(),
+            deleted,
+            vanished_containers,
+        ))
+    }
+
+    /// Removes the deleted documents and the containers left empty under
+    /// `root`, or in the whole index.
+    pub fn prune(&self, root: Option<&str>) -> Result<PruneSummary, ApiError> {
+        self.request(|resp_tx| StorageCommand::Prune {
+            root: root.map(String::from),
+            resp_tx,
+        })
     }
 }
@@ -0,0 +1,641 @@
+use std::str::FromStr;
+
+use serde_json::{Value, json};
+use tiny_http::{Header, Method, Request, Response};
+use tracing::{error, info, warn};
+
+use crate::{
+    api::{API_SEARCH_LIMIT, API_SEARCH_MAX_LIMIT, Api, ApiError},
+    engine::{
+        EngineError,
+        jobs::ScanProgress,
+        lanes::Priority,
+        scaling::{AutoscalePolicy, StageStatus},
+    },
+    entities::{
+        document::Document,
+        duplicates::DuplicateGroup,
+        failure::{FailedDocument, FailureKind},
+        find::PathHit,
+        search::{SearchError, SearchFacets, SearchHit},
+        stats::IndexStats,
+    },
+    metrics::metrics,
+    storage::{StorageError, query::SearchMode},
+};
+
+const LOG_TARGET: &str = "api_server";
+
+#[derive(Debug)]
+pub enum ServerError {
+    BindError(String),
+}
+
+#[derive(Debug, Clone)]
+pub struct Server {
+    api: Api,
+    address: String,
+    metrics_enabled: bool,
+}
+
+impl Server {
+    pub fn new(api: Api, address: String) -> Self {
+        Server {
+            api,
+            address,
+            metrics_enabled: false,
+        }
+    }
+
+    pub fn get_address(&self) -> &str {
+        &self.address
+    }
+
+    /// Serves [`metrics()`] on `GET /metrics` in the Prometheus text format.
+    pub fn set_metrics_enabled(&mut self, enabled: bool) {
+        self.metrics_enabled = enabled;
+    }
+
+    pub fn run(&self) -> Result<(), ServerError> {
+        let server = tiny_http::Server::http(&self.address)
+            .map_err(|e| ServerError::BindError(e.to_string()))?;
+
+        info!(target: LOG_TARGET, "Listening on http://{}", self.address);
+
+        for mut request in server.incoming_requests() {
+            let (status, body, content_type) = if self.metrics_enabled
+                && *request.method() == Method::Get
+                && request.url() == "/metrics"
+            {
+                (200, metrics().render(), "text/plain; version=0.0.4")
+            } else {
+                let (status, body) = self.handle(&mut request);
+                (status, body.to_string(), "application/json")
+            };
+
+            info!(
+                target: LOG_TARGET,
+                status = status,
+                "{} {}",
+                request.method(),
+                request.url()
+            );
+
+            let header = Header::from_bytes("Content-Type", content_type)
+                .expect("Invalid content type header");
+            let response = Response::from_string(body)
+                .with_status_code(status)
+                .with_header(header);
+
+            if let Err(e) = request.respond(response) {
+                warn!(target: LOG_TARGET, "Failed to send response: {:?}", e);
+            }
+        }
+
+        Ok(())
+    }
+
+    fn handle(&self, request: &mut Request) -> (u16, Value) {
+        let url = request.url().to_string();
+        let (path, query) = url.split_once('?').unwrap_or((&url, ""));
+        let params = parse_query(query);
+        let segments: Vec<&str> = path.split('/').filter(|s| !s.is_empty()).collect();
+
+        match (request.method(), segments.as_slice()) {
+            (Method::Post, ["scan"]) => self.handle_scan(request),
+            (Method::Get, ["search"]) => self.handle_search(&params),
+            (Method::Get, ["find"]) => self.handle_find(&params),
+            (Method::Get, ["documents", id]) => self.handle_document(id),
+            (Method::Get, ["stats"]) => self.handle_stats(),
+            (Method::Get, ["failures"]) => self.handle_failures(&params),
+            (Method::Get, ["duplicates"]) => self.handle_duplicates(&params),
+            (Method::Post, ["retry-failed"]) => self.handle_retry_failed(&params),
+            (Method::Post, ["reconcile"]) => self.handle_reconcile(&params),
+            (Method::Get, ["jobs"]) => self.handle_jobs(),
+            (Method::Get, ["jobs", id]) => self.handle_job(id),
+            (Method::Get, ["stages"]) => self.handle_stages(),
+            (Method::Post, ["stages", name]) => self.handle_scale_stage(request, name),
+            _ => error_json(404, "Not found"),
+        }
+    }
+
+    fn handle_scan(&self, request: &mut Request) -> (u16, Value) {
+        let mut body = String::new();
+        if let Err(e) = request.as_reader().read_to_string(&mut body) {
+            return error_json(400, &format!("Failed to read request body: {}", e));
+        }
+
+        let value = serde_json::from_str::<Value>(&body).ok();
+        let path = value
+            .as_ref()
+            .and_then(|value| value.get("path").and_then(Value::as_str).map(String::from));
+
+        let Some(path) = path else {
+            return error_json(400, "Expected a JSON body with a \"path\" field");
+        };
+
+        let priority = match value.as_ref().and_then(|value| value.get("priority")) {
+            None | Some(Value::Null) => Priority::Normal,
+            Some(priority) => match priority.as_str().map(Priority::from_str) {
+                Some(Ok(priority)) => priority,
+                _ => return error_json(400, "Invalid \"priority\", expected low, normal or high"),
+            },
+        };
+
+        match self.api.scan_path_with_priority(path.clone(), priority) {
+            Ok(job) => (
+                202,
+                json!({
+                    "status": "queued",
+                    "path": path,
+                    "priority": priority.to_str(),
+                    "job_id": job.get_id(),
+                }),
+            ),
+            Err(e) => api_error_json(e),
+        }
+    }
+
+    fn handle_search(&self, params: &[(String, String)]) -> (u16, Value) {
+        let Some(query) = get_param(params, "q") else {
+            return error_json(400, "Missing \"q\" parameter");
+        };
+
+        let limit = match parse_param(params, "limit", *API_SEARCH_LIMIT) {
+            Ok(limit) => limit.min(*API_SEARCH_MAX_LIMIT),
+            Err(e) => return e,
+        };
+        let offset = match parse_param(params, "offset", 0) {
+            Ok(offset) => offset,
+            Err(e) => return e,
+        };
+        let facets = matches!(get_param(params, "facets"), Some("true") | Some("1"));
+        let collapse = matches!(get_param(params, "collapse"), Some("true") | Some("1"));
+        let mode = match get_param(params, "mode").map(SearchMode::from_str) {
+            Some(Ok(mode)) => mode,
+            Some(Err(())) => return error_json(400, "Invalid \"mode\" parameter"),
+            None => SearchMode::Exact,
+        };
+
+        match self
+            .api
+            .search_results(query, limit, offset, facets, mode, collapse)
+        {
+            Ok(results) => {
+                let mut body = json!({
+                    "query": query,
+                    "mode": mode.to_str(),
+                    "collapse": collapse,
+                    "limit": limit,
+                    "offset": offset,
+                    "total": results.get_total(),
+                    "hits": results.get_hits().iter().map(search_hit_json).collect::<Vec<_>>(),
+                });
+
+                if let Some(facets) = results.get_facets() {
+                    body["facets"] = facets_json(facets);
+                }
+
+                (200, body)
+            }
+            Err(e) => api_error_json(e),
+        }
+    }
+
+    fn handle_find(&self, params: &[(String, String)]) -> (u16, Value) {
+        let Some(pattern) = get_param(params, "q") else {
+            return error_json(400, "Missing \"q\" parameter");
+        };
+
+        let limit = match parse_param(params, "limit", *API_SEARCH_LIMIT) {
+            Ok(limit) => limit.min(*API_SEARCH_MAX_LIMIT),
+            Err(e) => return e,
+        };
+
+        match self.api.find(pattern, limit) {
+            Ok(hits) => (
+                200,
+                json!({
+                    "query": pattern,
+                    "limit": limit,
+                    "hits": hits.iter().map(path_hit_json).collect::<Vec<_>>(),
+                }),
+            ),
+            Err(e) => api_error_json(e),
+        }
+    }
+
+    fn handle_document(&self, id: &str) -> (u16, Value) {
+        let Ok(id) = id.parse::<i64>() else {
+            return error_json(400, "Invalid document id");
+        };
+
+        match self.api.get_document(id) {
+            Ok(document) => (200, document_json(&document)),
+            Err(e) => api_error_json(e),
+        }
+    }
+
+    fn handle_failures(&self, params: &[(String, String)]) -> (u16, Value) {
+        let kind = match get_param(params, "kind").map(FailureKind::from_str) {
+            Some(Ok(kind)) => Some(kind),
+            Some(Err(())) => return error_json(400, "Invalid \"kind\" parameter"),
+            None => None,
+        };
+        let limit = match parse_param(params, "limit", *API_SEARCH_LIMIT) {
+            Ok(limit) => limit.min(*API_SEARCH_MAX_LIMIT),
+            Err(e) => return e,
+        };
+        let offset = match parse_param(params, "offset", 0) {
+            Ok(offset) => offset,
+            Err(e) => return e,
+        };
+
+        match self.api.extraction_failures(kind, limit, offset) {
+            Ok(failures) => (
+                200,
+                json!({
+                    "limit": limit,
+                    "offset": offset,
+                    "failures": failures.iter().map(failed_document_json).collect::<Vec<_>>(),
+                }),
+            ),
+            Err(e) => api_error_json(e),
+        }
+    }
+
+    fn handle_duplicates(&self, params: &[(String, String)]) -> (u16, Value) {
+        let limit = match parse_param(params, "limit", *API_SEARCH_LIMIT) {
+            Ok(limit) => limit.min(*API_SEARCH_MAX_LIMIT),
+            Err(e) => return e,
+        };
+        let offset = match parse_param(params, "offset", 0) {
+            Ok(offset) => offset,
+            Err(e) => return e,
+        };
+
+        match self.api.duplicates(limit, offset) {
+            Ok(report) => (
+                200,
+                json!({
+                    "limit": limit,
+                    "offset": offset,
+                    "total_groups": report.get_total_groups(),
+                    "wasted_bytes": report.get_wasted_bytes(),
+                    "groups": report.get_groups().iter().map(duplicate_group_json).collect::<Vec<_>>(),
+                }),
+            ),
+            Err(e) => api_error_json(e),
+        }
+    }
+
+    fn handle_retry_failed(&self, params: &[(String, String)]) -> (u16, Value) {
+        let include_permanent = matches!(get_param(params, "all"), Some("true") | Some("1"));
+
+        match self.api.retry_failed(include_permanent) {
+            Ok(job) => (202, json!({ "status": "queued", "job_id": job.get_id() })),
+            Err(e) => api_error_json(e),
+        }
+    }
+
+    fn handle_reconcile(&self, params: &[(String, String)]) -> (u16, Value) {
+        let prune = matches!(get_param(params, "prune"), Some("true") | Some("1"));
+
+        let root = get_param(params, "path");
+        let summary = match self.api.reconcile(root) {
+            Ok(summary) => summary,
+            Err(e) => return api_error_json(e),
+        };
+
+        let mut body = json!({
+            "checked": summary.get_checked(),
+            "deleted": summary.get_deleted(),
+            "vanished_containers": summary.get_vanished_containers(),
+        });
+
+        if prune {
+            match self.api.prune(root) {
+                Ok(pruned) => {
+                    body["pruned"] = json!({
+                        "documents": pruned.get_documents(),
+                        "containers": pruned.get_containers(),
+                    });
+                }
+                Err(e) => return api_error_json(e),
+            }
+        }
+
+        (200, body)
+    }
+
+    fn handle_jobs(&self) -> (u16, Value) {
+        let jobs: Vec<Value> = self
+            .api
+            .scan_jobs()
+            .iter()
+            .map(scan_progress_json)
+            .collect();
+        (200, json!({ "jobs": jobs }))
+    }
+
+    fn handle_job(&self, id: &str) -> (u16, Value) {
+        match self.api.scan_job(id) {
+            Ok(job) => (200, scan_progress_json(&job.get_progress())),
+            Err(e) => api_error_json(e),
+        }
+    }
+
+    fn handle_stages(&self) -> (u16, Value) {
+        let stages: Vec<Value> = self.api.stages().iter().map(stage_status_json).collect();
+        (200, json!({ "stages": stages }))
+    }
+
+    fn handle_scale_stage(&self, request: &mut Request, name: &str) -> (u16, Value) {
+        let mut body = String::new();
+        if let Err(e) = request.as_reader().read_to_string(&mut body) {
+            return error_json(400, &format!("Failed to read request body: {}", e));
+        }
+
+        let Ok(body) = serde_json::from_str::<Value>(&body) else {
+            return error_json(400, "Expected a JSON body");
+        };
+
+        let result = if let Some(workers) = body.get("workers").and_then(Value::as_u64) {
+            self.api.scale_stage(name, workers as usize)
+        } else if let Some(autoscale) = body.get("autoscale") {
+            let policy = match autoscale {
+                Value::Object(_) => {
+                    let param = |key: &str| autoscale.get(key).and_then(Value::as_u64);
+                    let (Some(min_workers), Some(max_workers)) =
+                        (param("min_workers"), param("max_workers"))
+                    else {
+                        return error_json(
+                            400,
+                            "Expected \"min_workers\" and \"max_workers\" in \"autoscale\"",
+                        );
+                    };
+
+                    let mut policy =
+                        AutoscalePolicy::new(min_workers as usize, max_workers as usize);
+                    if let Some(high_water) = param("queue_high_water") {
+                        policy.set_queue_high_water(high_water as usize);
+                    }
+                    if let Some(max_load) = autoscale.get("max_load").and_then(Value::as_f64) {
+                        policy.set_max_load(max_load);
+                    }
+                    Some(policy)
+                }
+                Value::Null | Value::Bool(false) => None,
+                _ => return error_json(400, "Invalid \"autoscale\" field"),
+            };
+
+            self.api.autoscale_stage(name, policy)
+        } else {
+            return error_json(400, "Expected a \"workers\" or \"autoscale\" field");
+        };
+
+        match result {
+            Ok(status) => (200, stage_status_json(&status)),
+            Err(e) => api_error_json(e),
+        }
+    }
+
+    fn handle_stats(&self) -> (u16, Value) {
+        match self.api.stats() {
+            Ok(stats) => (200, stats_json(&stats)),
+            Err(e) => api_error_json(e),
+        }
+    }
+}
+
+fn error_json(status: u16, message: &str) -> (u16, Value) {
+    (status, json!({ "error": message }))
+}
+
+fn api_error_json(err: ApiError) -> (u16, Value) {
+    match err {
+        ApiError::NotFound => error_json(404, "Not found"),
+        ApiError::EngineError(EngineError::StageClosed) => {
+            error_json(409, "The pipeline is shutting down")
+        }
+        ApiError::StorageError(StorageError::SearchError(
+            e @ (SearchError::QueryError(_) | SearchError::TrigramIndexDisabled),
+        )) => error_json(400, &format!("Invalid search query: {:?}", e)),
+        // The details (SQL, file paths) stay in the server log.
+        e => {
+            error!(target: LOG_TARGET, "Request failed: {:?}", e);
+            error_json(500, "Internal server error")
+        }
+    }
+}
+
+fn search_hit_json(hit: &SearchHit) -> Value {
+    json!({
+        "id": hit.get_document_id(),
+        "path": hit.get_path(),
+        "container_type": hit.get_container_type().to_str(),
+        "extension": hit.get_extension(),
+        "rank": hit.get_rank(),
+        "snippet": hit.get_snippet(),
+        "hash": hit.get_hash(),
+        "copies": hit.get_copies(),
+    })
+}
+
+fn path_hit_json(hit: &PathHit) -> Value {
+    json!({
+        "id": hit.get_document_id(),
+        "path": hit.get_path(),
+        "filename": hit.get_filename(),
+    })
+}
+
+fn facets_json(facets: &SearchFacets) -> Value {
+    fn counts<'a>(entries: impl Iterator<Item = (&'a str, i64)>) -> Value {
+        Value::Array(
+            entries
+                .map(|(value, count)| json!({ "value": value, "count": count }))
+                .collect(),
+        )
+    }
+
+    json!({
+        "extension": counts(
+            facets
+                .get_extensions()
+                .iter()
+                .map(|(extension, count)| (extension.as_deref().unwrap_or(""), *count))
+        ),
+        "container_type": counts(
+            facets
+                .get_container_types()
+                .iter()
+                .map(|(container_type, count)| (container_type.to_str(), *count))
+        ),
+        "container_path": counts(
+            facets
+                .get_container_paths()
+                .iter()
+                .map(|(path, count)| (path.as_str(), *count))
+        ),
+        "status": counts(
+            facets
+                .get_statuses()
+                .iter()
+                .map(|(status, count)| (status.to_str(), *count))
+        ),
+    })
+}
+
+fn document_json(document: &Document) -> Value {
+    json!({
+        "id": document.get_id(),
+        "path": document.get_path(),
+        "filename": document.get_filename(),
+        "extension": document.get_extension(),
+        "status": document.get_status_str(),
+        "container_id": document.get_container_id(),
+        "truncated": document.is_truncated(),
+    })
+}
+
+fn failed_document_json(failed: &FailedDocument) -> Value {
+    let failure = failed.get_failure();
+
+    json!({
+        "id": failed.get_document_id(),
+        "path": failed.get_path(),
+        "kind": failure.get_kind().to_str(),
+        "message": failure.get_message(),
+        "extractor": failure.get_extractor(),
+        "failed_at": failure.get_failed_at(),
+        "attempts": failure.get_attempts(),
+        "permanent": failure.is_permanent(),
+    })
+}
+
+fn stage_status_json(status: &StageStatus) -> Value {
+    json!({
+        "name": status.get_name(),
+        "workers": status.get_workers(),
+        "queue_depth": status.get_queue_depth(),
+        "autoscale": status.get_autoscale().map(|policy| json!({
+            "min_workers": policy.get_min_workers(),
+            "max_workers": policy.get_max_workers(),
+            "queue_high_water": policy.get_queue_high_water(),
+            "max_load": policy.get_max_load(),
+        })),
+    })
+}
+
+fn scan_progress_json(progress: &ScanProgress) -> Value {
+    json!({
+        "id": progress.get_id(),
+        "path": progress.get_path(),
+        "priority": progress.get_priority().to_str(),
+        "state": progress.get_state().to_str(),
+        "percent": progress.get_percent(),
+        "total": progress.get_total(),
+        "done": progress.get_done(),
+        "discovered": progress.get_discovered(),
+        "archive_members": progress.get_archive_members(),
+        "extracted": progress.get_extracted(),
+        "failed": progress.get_failed(),
+        "skipped": progress.get_skipped(),
+        "unchanged": progress.get_unchanged(),
+        "committed": progress.get_committed(),
+        "batches": progress.get_batches(),
+        "started_at": progress.get_started_at(),
+        "finished_at": progress.get_finished_at(),
+    })
+}
+
+fn duplicate_group_json(group: &DuplicateGroup) -> Value {
+    json!({
+        "hash": group.get_hash(),
+        "size": group.get_size(),
+        "wasted_bytes": group.get_wasted_bytes(),
+        "copies": group.get_copies().iter().map(|copy| json!({
+            "document_id": copy.get_document_id(),
+            "path": copy.get_path(),
+            "container_type": copy.get_container_type().to_str(),
+        })).collect::<Vec<_>>(),
+    })
+}
+
+fn stats_json(stats: &IndexStats) -> Value {
+    let by_status: serde_json::Map<String, Value> = stats
+        .get_documents_by_status()
+        .iter()
+        .map(|(status, count)| (status.to_str().to_string(), json!(count)))
+        .collect();
+
+    json!({
+        "documents": stats.get_documents(),
+        "indexed_documents": stats.get_indexed_documents(),
+        "containers": stats.get_containers(),
+        "folders": stats.get_folders(),
+        "archives": stats.get_archives(),
+        "documents_by_status": by_status,
+    })
+}
+
+fn get_param<'a>(params: &'a [(String, String)], key: &str) -> Option<&'a str> {
+    params
+        .iter()
+        .find(|(k, _)| k == key)
+        .map(|(_, v)| v.as_str())
+}
+
+fn parse_param(
+    params: &[(String, String)],
+    key: &str,
+    default: usize,
+) -> Result<usize, (u16, Value)> {
+    match get_param(params, key) {
+        Some(value) => value
+            .parse()
+            .map_err(|_| error_json(400, &format!("Invalid \"{}\" parameter", key))),
+        None => Ok(default),
+    }
+}
+
+fn parse_query(query: &str) -> Vec<(String, String)> {
+    query
+        .split('&')
+        .filter(|pair| !pair.is_empty())
+        .map(|pair| {
+            let (key, value) = pair.split_once('=').unwrap_or((pair, ""));
+            (percent_decode(key), percent_decode(value))
+        })
+        .collect()
+}
+
+fn percent_decode(input: &str) -> String {
+    let bytes = input.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+
+    while i < bytes.len() {
+        match bytes[i] {
+            b'+' => decoded.push(b' '),
+            b'%' if i + 2 < bytes.len() => {
+                let byte = std::str::from_utf8(&bytes[i + 1..i + 3])
+                    .ok()
+                    .and_then(|hex| u8::from_str_radix(hex, 16).ok());
+
+                match byte {
+                    Some(byte) => {
+                        decoded.push(byte);
+                        i += 2;
+                    }
+                    None => decoded.push(b'%'),
+                }
+            }
+            byte => decoded.push(byte),
+        }
+        i += 1;
+    }
+
+    String::from_utf8_lossy(&decoded).to_string()
+}
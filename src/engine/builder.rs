@@ -0,0 +1,207 @@
+use crate::{
+    api::Api,
+    engine::{
+        Engine, EngineError, EngineTask,
+        decompressor::DecompressorEngine,
+        extractor::{
+            EXTRACTOR_CHANNEL_CAPACITY, Extractor, commands::ExtractorCommand, formats::FormatType,
+        },
+        jobs::ScanJobs,
+        lanes::lane_channel,
+        scaling::{AutoscalePolicy, ScalableStage},
+        scanner::ScannedDocument,
+        scanner::{FiltersMode, Scanner, commands::ScannerCommand, filters::Filter},
+        unbounded_channel,
+        watcher::{Watcher, WatcherCommand},
+    },
+    storage::{STORAGE_DATABASE_PATH, StorageEngine},
+};
+
+const DEFAULT_EXTRACTOR_WORKERS: usize = 16;
+
+const DEFAULT_DECOMPRESSOR_WORKERS: usize = 2;
+
+/// Configuration of an `Engine`, see `Engine::builder`.
+#[derive(Debug, Clone)]
+pub struct EngineBuilder {
+    database_path: String,
+    extraction: bool,
+    extractor_workers: usize,
+    decompressor_workers: usize,
+    incremental: bool,
+    watch: bool,
+    autoscale: Option<AutoscalePolicy>,
+    filters: Vec<Filter>,
+    filters_mode: FiltersMode,
+    formats: Option<Vec<FormatType>>,
+}
+
+impl Default for EngineBuilder {
+    fn default() -> Self {
+        EngineBuilder {
+            database_path: STORAGE_DATABASE_PATH.to_string(),
+            extraction: true,
+            extractor_workers: DEFAULT_EXTRACTOR_WORKERS,
+            decompressor_workers: DEFAULT_DECOMPRESSOR_WORKERS,
+            incremental: true,
+            watch: false,
+            autoscale: None,
+            filters: Vec::new(),
+            filters_mode: FiltersMode::And,
+            formats: None,
+        }
+    }
+}
+
+impl EngineBuilder {
+    pub fn database_path(mut self, path: &str) -> Self {
+        self.database_path = path.to_string();
+        self
+    }
+
+    /// Without extraction only storage runs: the `Api` can query the index
+    /// but not scan.
+    pub fn extraction(mut self, enabled: bool) -> Self {
+        self.extraction = enabled;
+        self
+    }
+
+    pub fn extractor_workers(mut self, workers: usize) -> Self {
+        self.extractor_workers = workers;
+        self
+    }
+
+    /// Workers of the stage that reads archives, at least one.
+    pub fn decompressor_workers(mut self, workers: usize) -> Self {
+        self.decompressor_workers = workers;
+        self
+    }
+
+    /// When enabled (the default), scans skip the files that did not change
+    /// since they were indexed and replace the ones that did.
+    pub fn incremental(mut self, enabled: bool) -> Self {
+        self.incremental = enabled;
+        self
+    }
+
+    /// Watches the folders scanned through the `Api` and indexes their
+    /// changes as they happen. Needs extraction.
+    pub fn watch(mut self, enabled: bool) -> Self {
+        self.watch = enabled;
+        self
+    }
+
+    /// Starts the extractor autoscaler with this policy.
+    pub fn autoscale(mut self, policy: AutoscalePolicy) -> Self {
+        self.autoscale = Some(policy);
+        self
+    }
+
+    pub fn filter(mut self, filter: Filter) -> Self {
+        self.filters.push(filter);
+        self
+    }
+
+    pub fn filters_mode(mut self, mode: FiltersMode) -> Self {
+        self.filters_mode = mode;
+        self
+    }
+
+    /// Only scan documents of these formats. Every format is enabled unless
+    /// this is called.
+    pub fn formats(mut self, formats: Vec<FormatType>) -> Self {
+        self.formats = Some(formats);
+        self
+    }
+
+    /// Opens the database and starts the stages.
+    pub fn start(self) -> Result<Engine, EngineError> {
+        let mut storage = StorageEngine::new(self.database_path);
+        storage.initialize().map_err(EngineError::StorageError)?;
+        let storage_handle = storage.run()?;
+
+        let (scanner_tx, scanner_rx) = lane_channel::<ScannerCommand>(0);
+        let mut scanner_handles = Vec::new();
+        let mut watcher_handles = Vec::new();
+        let mut watcher_tx = None;
+        // Shared with the watcher, so its rescans show up with the others.
+        let jobs = ScanJobs::default();
+        let mut stages = Vec::new();
+
+        if self.extraction {
+            let (extractor_tx, extractor_rx) =
+                lane_channel::<ExtractorCommand>(*EXTRACTOR_CHANNEL_CAPACITY);
+            // Unbounded: extractor workers must never wait on the
+            // decompressor, which waits on them.
+            let (decompressor_tx, decompressor_rx) = unbounded_channel::<ScannedDocument>();
+
+            let mut scanner = Scanner::new(scanner_tx.clone(), scanner_rx, extractor_tx.clone());
+            scanner.set_filters_mode(self.filters_mode);
+            for filter in self.filters {
+                scanner.add_filter(filter);
+            }
+            scanner.set_formats(self.formats);
+            if self.incremental {
+                scanner.set_database_tx(Some(storage.get_channel_tx().clone()));
+            }
+
+            let extractor = Extractor::new(
+                storage.get_channel_tx().clone(),
+                scanner.clone(),
+                extractor_tx.clone(),
+                extractor_rx,
+                decompressor_tx.clone(),
+            );
+            let decompressor = DecompressorEngine::new(
+                decompressor_tx,
+                decompressor_rx,
+                extractor_tx,
+                storage.get_channel_tx().clone(),
+                scanner.clone(),
+            );
+
+            scanner_handles = scanner.init().map_err(EngineError::ScannerError)?;
+
+            let extractor = ScalableStage::start("extractor", extractor, self.extractor_workers)?;
+            if let Some(policy) = self.autoscale {
+                extractor.set_autoscale(Some(policy))?;
+            }
+            stages.push(extractor);
+
+            stages.push(ScalableStage::start(
+                "decompressor",
+                decompressor,
+                self.decompressor_workers.max(1),
+            )?);
+
+            if self.watch {
+                let (channel_tx, channel_rx) = unbounded_channel::<WatcherCommand>();
+                let mut watcher = Watcher::new(
+                    channel_tx,
+                    channel_rx,
+                    scanner_tx.clone(),
+                    storage.get_channel_tx().clone(),
+                    jobs.clone(),
+                );
+
+                watcher_handles = watcher.init().map_err(EngineError::WatcherError)?;
+                watcher_tx = Some(watcher.get_channel_tx().clone());
+            }
+        }
+
+        let mut api = Api::new(scanner_tx, storage.get_channel_tx().clone());
+        api.set_watcher_tx(watcher_tx);
+        api.set_jobs(jobs);
+        for stage in &stages {
+            api.add_stage(stage.clone());
+        }
+
+        Ok(Engine {
+            api,
+            storage_handle,
+            scanner_handles,
+            watcher_handles,
+            stages,
+        })
+    }
+}
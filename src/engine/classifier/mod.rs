@@ -1,16 +1,14 @@
 pub mod workers;
 
-use std::thread::JoinHandle;
-
 use crate::{
-    engine::{EngineError, PipelineStage, Sender, classifier::workers::ClassifierWorker},
-    entities::document::Document,
+    engine::{Sender, classifier::workers::ClassifierWorker},
     storage::commands::StorageCommand,
 };
 
 //const LOG_TARGET: &str = "classifier";
 
 #[derive(Debug)]
+#[allow(dead_code)]
 pub struct Classifier {
     database_tx: Sender<StorageCommand>,
     workers: Vec<ClassifierWorker>,
@@ -24,9 +22,3 @@ impl Default for Classifier {
         }
     }
 }
-
-impl PipelineStage for Classifier {
-    fn add_worker(&mut self) -> Result<JoinHandle<()>, EngineError> {
-        todo!()
-    }
-}
@@ -1,15 +1,28 @@
-use std::thread::{self, JoinHandle};
+use std::{
+    sync::{
+        Arc,
+        atomic::{AtomicBool, Ordering},
+    },
+    thread::{self, JoinHandle},
+    time::{Duration, Instant},
+};
 
-use crossbeam::channel;
-use tracing::{info, warn};
+use tracing::{error, info};
 
 use crate::{
     engine::{
-        EngineError, PipelineStage, Receiver, Sender,
-        extractor::{ExtractorChannelTx, commands::ExtractorCommand},
-        scanner::ScannedDocument,
+        ChannelRecvTimeoutError, EngineError, PipelineStage, Receiver, Sender,
+        extractor::{
+            ExtractorChannelTx,
+            commands::ExtractorCommand,
+            formats::{DataExtracted, archive::zip::ZipExtractor},
+            isolation,
+        },
+        scanner::{ScannedDocument, Scanner},
+        unbounded_channel,
     },
-    entities::container::{Container, ContainerType},
+    entities::container::Container,
+    metrics::metrics,
     storage::{StorageChannelTx, StorageError, commands::StorageCommand},
 };
 
@@ -18,11 +31,20 @@ pub type DecompressorChannelRx = Receiver<ScannedDocument>;
 
 const LOG_TARGET: &str = "decompressor";
 
+const WORKER_RECEIVE_TIMEOUT_MS: u64 = 200;
+
+/// Stage that reads the archives handed over by the extractor workers: it
+/// saves each archive as a container and sends its members back to the
+/// extractor. An archive that cannot be read goes back as
+/// `ExtractorCommand::ArchiveFailed`, to be retried or stored as failed.
 pub struct DecompressorEngine {
     channel_tx: DecompressorChannelTx,
     channel_rx: DecompressorChannelRx,
     channel_extractor_tx: ExtractorChannelTx,
     channel_storage_tx: StorageChannelTx,
+    scanner: Scanner,
+    /// Stop flag of each running worker, in start order.
+    workers: Vec<Arc<AtomicBool>>,
 }
 
 impl DecompressorEngine {
@@ -31,96 +53,204 @@ impl DecompressorEngine {
         channel_rx: DecompressorChannelRx,
         channel_extractor_tx: ExtractorChannelTx,
         channel_storage_tx: StorageChannelTx,
+        scanner: Scanner,
     ) -> Self {
         DecompressorEngine {
             channel_tx,
             channel_rx,
             channel_extractor_tx,
             channel_storage_tx,
+            scanner,
+            workers: Vec::new(),
         }
     }
-}
-
-impl PipelineStage for DecompressorEngine {
-    fn add_worker(&mut self) -> Result<JoinHandle<()>, EngineError> {
-        info!(target: LOG_TARGET, "Starting decompressor worker");
 
-        let channel_rx = self.channel_rx.clone();
-        let channel_extractor_tx = self.channel_extractor_tx.clone();
-        let channel_storage_tx = self.channel_storage_tx.clone();
+    pub fn get_channel_tx(&self) -> &DecompressorChannelTx {
+        &self.channel_tx
+    }
 
-        let handle = thread::spawn(move || {
-            while let Ok(scanned) = channel_rx.recv() {
-                let container = Container::from_document(&scanned.document, ContainerType::Archive);
+    fn decompress(
+        mut scanned: ScannedDocument,
+        scanner: &Scanner,
+        channel_extractor_tx: &ExtractorChannelTx,
+        channel_storage_tx: &StorageChannelTx,
+    ) {
+        let metrics = metrics();
+        let tracker = scanner.get_tracker();
+        let job = scanned.job.clone();
+        let attempts = scanned
+            .failure
+            .take()
+            .map_or(0, |failure| failure.get_attempts())
+            + 1;
+        let started = Instant::now();
 
-                let (tx, rx) = channel::unbounded();
+        let zip_extractor = ZipExtractor::new(scanner.clone());
+        let archive_document = scanned.document.clone();
+        let (archive, documents) = match isolation::guard("ZipExtractor", move || {
+            zip_extractor.extract(archive_document)
+        }) {
+            Ok(DataExtracted::ArchiveDocuments { archive, documents }) => (archive, documents),
+            Ok(_) => {
+                if let Some(job) = &job {
+                    job.skipped();
+                }
+                return;
+            }
+            Err(mut failure) => {
+                error!(target: LOG_TARGET, "Failed to read ZIP archive: {} ({})", failure.get_message(), scanned.document.get_path());
+                metrics.get_archives_failed().inc();
+                metrics.get_documents_failed().with("zip").inc();
 
-                channel_storage_tx
-                    .send(StorageCommand::SaveArchive {
-                        archive: container.clone(),
-                        resp_tx: Some(tx),
-                    })
-                    .expect("Failed to send document to storage");
+                failure.set_attempts(attempts);
+                scanned.failure = Some(Box::new(failure));
 
-                let _ = rx.recv().expect("Failed to receive storage response");
+                tracker.enqueued();
+                if let Err(e) = channel_extractor_tx.send(ExtractorCommand::ArchiveFailed(scanned))
+                {
+                    tracker.completed();
+                    error!(target: LOG_TARGET, "Failed to send failed archive to extractor: {:?}", e);
+                    if let Some(job) = &job {
+                        job.failed(1);
+                    }
+                }
+                return;
+            }
+        };
 
-                let mut documents = Vec::new();
+        info!(target: LOG_TARGET, "Read {} documents from ZIP archive", documents.len());
 
-                let file = std::fs::File::open(container.get_path()).map_err(EngineError::IoError);
+        let (resp_tx, resp_rx) = unbounded_channel::<Result<Container, StorageError>>();
+        let saved = channel_storage_tx
+            .send(StorageCommand::SaveArchive {
+                archive,
+                members: documents.len(),
+                resp_tx: Some(resp_tx),
+            })
+            .map_err(|e| format!("{:?}", e))
+            .and_then(|_| resp_rx.recv().map_err(|e| format!("{:?}", e)))
+            .and_then(|result| result.map_err(|e| format!("{:?}", e)));
 
-                if let Err(e) = file {
-                    warn!(target: LOG_TARGET, "Failed to open archive file: {:?}", e);
-                    continue;
+        let archive = match saved {
+            Ok(archive) => archive,
+            Err(e) => {
+                error!(target: LOG_TARGET, "Failed to save archive: {}", e);
+                metrics.get_archives_failed().inc();
+                metrics.get_documents_failed().with("zip").inc();
+                if let Some(job) = &job {
+                    job.failed(1);
                 }
+                return;
+            }
+        };
 
-                let file = file.unwrap();
+        info!(target: LOG_TARGET, "Archive saved with ID: {}", archive.get_id());
 
-                let archive = zip::ZipArchive::new(file).map_err(EngineError::ZipError);
+        // Members are counted before the archive itself is done, so the job
+        // cannot complete in between.
+        if let Some(job) = &job {
+            job.archive_members(documents.len());
+        }
 
-                if let Err(e) = archive {
-                    warn!(target: LOG_TARGET, "Failed to read ZIP archive: {:?}", e);
-                    continue;
-                }
+        for member in documents {
+            let mut document = member.document;
+            document.set_container_id(archive.get_id());
 
-                let mut archive = archive.unwrap();
+            let member = ScannedDocument {
+                container_type: member.container_type,
+                document,
+                job: job.clone(),
+                failure: None,
+            };
 
-                for i in 0..archive.len() {
-                    let file = archive.by_index(i).map_err(EngineError::ZipError);
+            // The extractor never waits on this stage, so blocking on a full
+            // lane cannot deadlock.
+            tracker.enqueued();
+            if let Err(e) = channel_extractor_tx.send(ExtractorCommand::ProcessDocument(member)) {
+                tracker.completed();
+                error!(target: LOG_TARGET, "Failed to send archive member to extractor: {:?}", e);
+                if let Some(job) = &job {
+                    job.failed(1);
+                }
+            }
+        }
 
-                    if let Err(e) = file {
-                        warn!(target: LOG_TARGET, "Failed to read file in ZIP archive: {:?}", e);
-                        continue;
-                    }
+        if let Some(job) = &job {
+            job.skipped();
+        }
 
-                    let file = file.unwrap();
+        metrics.get_archives_decompressed().inc();
+        metrics.get_documents_extracted().with("zip").inc();
+        metrics
+            .get_extraction_seconds()
+            .with("zip")
+            .observe_duration(started.elapsed());
+    }
+}
 
-                    let outpath = match file.enclosed_name() {
-                        Some(path) => path.to_owned(),
-                        None => continue,
-                    };
+impl PipelineStage for DecompressorEngine {
+    fn add_worker(&mut self) -> Result<JoinHandle<()>, EngineError> {
+        info!(target: LOG_TARGET, "Starting decompressor worker");
 
-                    let file_path = outpath.to_string_lossy().to_string();
+        let channel_rx = self.channel_rx.clone();
+        let channel_extractor_tx = self.channel_extractor_tx.clone();
+        let channel_storage_tx = self.channel_storage_tx.clone();
+        let scanner = self.scanner.clone();
+        let retiring = Arc::new(AtomicBool::new(false));
+        self.workers.push(retiring.clone());
 
-                    let mut doc = scanned.document.clone();
-                    doc.set_filename(file_path);
-                    doc.set_status(crate::entities::document::DocumentStatus::Extracted);
+        let handle = thread::spawn(move || {
+            let tracker = scanner.get_tracker().clone();
 
-                    documents.push(ScannedDocument {
-                        container_type: ContainerType::Archive,
-                        document: doc,
-                    });
+            loop {
+                if retiring.load(Ordering::SeqCst) {
+                    info!(target: LOG_TARGET, "Decompressor worker retired");
+                    break;
+                }
+                if tracker.is_drained() {
+                    info!(target: LOG_TARGET, "Pipeline drained, stopping decompressor worker");
+                    break;
                 }
 
-                if let Err(e) =
-                    channel_extractor_tx.send(ExtractorCommand::ProcessCompressedDocuments {
-                        container: container,
-                        documents: documents,
-                    })
+                let scanned = match channel_rx
+                    .recv_timeout(Duration::from_millis(WORKER_RECEIVE_TIMEOUT_MS))
                 {
-                    info!(target: LOG_TARGET, "Failed to send to extractor: {:?}", e);
-                }
+                    Ok(scanned) => scanned,
+                    Err(ChannelRecvTimeoutError::Timeout) => continue,
+                    Err(ChannelRecvTimeoutError::Disconnected) => break,
+                };
+
+                metrics()
+                    .get_queue_depth()
+                    .with(LOG_TARGET)
+                    .set(channel_rx.len() as i64);
+
+                let _completion = tracker.completion();
+                Self::decompress(
+                    scanned,
+                    &scanner,
+                    &channel_extractor_tx,
+                    &channel_storage_tx,
+                );
             }
         });
         Ok(handle)
     }
+
+    fn remove_worker(&mut self) -> Result<(), EngineError> {
+        if let Some(retiring) = self.workers.pop() {
+            info!(target: LOG_TARGET, "Retiring decompressor worker");
+            retiring.store(true, Ordering::SeqCst);
+        }
+
+        Ok(())
+    }
+
+    fn get_worker_count(&self) -> usize {
+        self.workers.len()
+    }
+
+    fn get_queue_depth(&self) -> usize {
+        self.channel_rx.len()
+    }
 }
@@ -1,9 +1,19 @@
-use crate::{engine::scanner::ScannedDocument, entities::container::Container};
+use crate::engine::{
+    lanes::{Prioritized, Priority},
+    scanner::ScannedDocument,
+};
 
 pub enum ExtractorCommand {
     ProcessDocument(ScannedDocument),
-    ProcessCompressedDocuments {
-        container: Container,
-        documents: Vec<ScannedDocument>,
-    },
-}
\ No newline at end of file
+    /// An archive the decompressor could not read, carrying its failure.
+    ArchiveFailed(ScannedDocument),
+}
+
+impl Prioritized for ExtractorCommand {
+    fn priority(&self) -> Priority {
+        match self {
+            ExtractorCommand::ProcessDocument(scanned)
+            | ExtractorCommand::ArchiveFailed(scanned) => scanned.priority(),
+        }
+    }
+}
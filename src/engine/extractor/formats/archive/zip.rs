@@ -1,8 +1,9 @@
-use tracing::info;
+use chrono::NaiveDate;
+use tracing::{info, warn};
 
 use crate::{
     engine::{
-        extractor::formats::DataExtracted,
+        extractor::{formats::DataExtracted, hash},
         scanner::{ScannedDocument, Scanner},
     },
     entities::{
@@ -13,6 +14,20 @@ use crate::{
 
 const LOG_TARGET: &str = "extractor_zip";
 
+fn zip_timestamp(datetime: zip::DateTime) -> Option<i64> {
+    NaiveDate::from_ymd_opt(
+        datetime.year() as i32,
+        datetime.month() as u32,
+        datetime.day() as u32,
+    )?
+    .and_hms_opt(
+        datetime.hour() as u32,
+        datetime.minute() as u32,
+        datetime.second() as u32,
+    )
+    .map(|datetime| datetime.and_utc().timestamp())
+}
+
 #[derive(Debug, Clone)]
 pub struct ZipExtractor {
     scanner: Scanner,
@@ -35,7 +50,7 @@ impl ZipExtractor {
         let mut documents = Vec::new();
 
         for i in 0..archive.len() {
-            let file = archive.by_index(i)?;
+            let mut file = archive.by_index(i)?;
             let outpath = match file.enclosed_name() {
                 Some(path) => path.to_owned(),
                 None => continue,
@@ -48,10 +63,20 @@ impl ZipExtractor {
                 let mut doc = Document::from_path(&outpath);
                 doc.set_status(DocumentStatus::Extracted);
                 doc.set_filename(file_path);
+                doc.set_size(Some(file.size() as i64));
+                doc.set_modified(file.last_modified().and_then(zip_timestamp));
+                match hash::hash_reader(&mut file) {
+                    Ok(hash) => doc.set_hash(Some(hash)),
+                    Err(e) => {
+                        warn!(target: LOG_TARGET, "Failed to hash {}: {}", doc.get_filename(), e)
+                    }
+                }
 
                 documents.push(ScannedDocument {
                     container_type: ContainerType::Archive,
                     document: doc,
+                    job: None,
+                    failure: None,
                 });
             } else {
                 continue;
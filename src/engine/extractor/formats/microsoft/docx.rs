@@ -1,4 +1,4 @@
-use crate::engine::extractor::formats::FileExtractor;
+use crate::engine::extractor::formats::{ExtractedText, FileExtractor};
 use crate::entities::document::Document;
 
 use quick_xml::Reader;
@@ -13,7 +13,7 @@ use zip::ZipArchive;
 pub struct DocxExtractor;
 
 impl FileExtractor for DocxExtractor {
-    fn extract(document: Document) -> Result<String, Box<dyn std::error::Error>> {
+    fn extract(document: Document) -> Result<ExtractedText, Box<dyn std::error::Error>> {
         let file = File::open(document.get_path())?;
         let mut zip = ZipArchive::new(file)?;
 
@@ -38,6 +38,6 @@ impl FileExtractor for DocxExtractor {
             buf.clear();
         }
 
-        Self::token_distribution(BufReader::new(text.as_bytes()))
+        Self::content(BufReader::new(text.as_bytes()))
     }
 }
@@ -1,9 +1,15 @@
-use std::{io::BufReader, str::FromStr};
+use std::{
+    io::{BufReader, Read},
+    str::FromStr,
+};
 
 use once_cell::sync::Lazy;
 
 use crate::{
-    engine::{extractor::{constants, tokens::TextTokensDistribution}, scanner::ScannedDocument},
+    engine::{
+        extractor::{constants, tokens::TextTokensDistribution},
+        scanner::ScannedDocument,
+    },
     entities::{container::Container, document::Document},
 };
 
@@ -19,6 +25,52 @@ static EXTRACTOR_MAX_TOKENS: Lazy<usize> = Lazy::new(|| {
         .unwrap_or(constants::DEFAULT_MAX_TOKENS)
 });
 
+/// Bytes of text kept in `ContentMode::FullText`, no limit when 0.
+static EXTRACTOR_MAX_CONTENT_BYTES: Lazy<usize> = Lazy::new(|| {
+    std::env::var("EXTRACTOR_MAX_CONTENT_BYTES")
+        .ok()
+        .and_then(|s| s.parse().ok())
+        .unwrap_or(constants::DEFAULT_MAX_CONTENT_BYTES)
+});
+
+pub static EXTRACTOR_CONTENT_MODE: Lazy<ContentMode> = Lazy::new(|| {
+    std::env::var("EXTRACTOR_CONTENT_MODE")
+        .ok()
+        .and_then(|s| ContentMode::from_str(&s).ok())
+        .unwrap_or_else(|| {
+            ContentMode::from_str(constants::DEFAULT_CONTENT_MODE).unwrap_or(ContentMode::Tokens)
+        })
+});
+
+/// What ends up in `index_documents.content`: the top-N token list, or the
+/// full extracted text (needed for phrase queries, `NEAR` and snippets).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ContentMode {
+    Tokens,
+    FullText,
+}
+
+impl FromStr for ContentMode {
+    type Err = ();
+
+    fn from_str(input: &str) -> Result<ContentMode, Self::Err> {
+        match input.to_lowercase().as_str() {
+            "tokens" => Ok(ContentMode::Tokens),
+            "full" | "fulltext" | "full_text" => Ok(ContentMode::FullText),
+            _ => Err(()),
+        }
+    }
+}
+
+impl ContentMode {
+    pub fn to_str(&self) -> &str {
+        match self {
+            ContentMode::Tokens => "tokens",
+            ContentMode::FullText => "full",
+        }
+    }
+}
+
 #[derive(Debug, Clone, PartialEq, Eq, Hash)]
 pub enum Archive {
     Zip,
@@ -37,6 +89,18 @@ pub enum FormatType {
     Unknown,
 }
 
+impl FormatType {
+    pub fn to_str(&self) -> &'static str {
+        match self {
+            FormatType::Pdf => "pdf",
+            FormatType::Docx => "docx",
+            FormatType::Text => "text",
+            FormatType::Archive(Archive::Zip) => "zip",
+            FormatType::Unknown => "unknown",
+        }
+    }
+}
+
 impl FromStr for FormatType {
     type Err = ();
 
@@ -65,15 +129,80 @@ pub enum DataExtracted {
     },
 }
 
+/// Text of a PDF, DOCX or text document.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Default)]
+pub struct ExtractedText {
+    content: String,
+    truncated: bool,
+}
+
+impl ExtractedText {
+    pub fn new(content: String, truncated: bool) -> Self {
+        ExtractedText { content, truncated }
+    }
+
+    pub fn get_content(&self) -> &str {
+        &self.content
+    }
+
+    pub fn into_content(self) -> String {
+        self.content
+    }
+
+    /// Whether the text was cut at `EXTRACTOR_MAX_CONTENT_BYTES`.
+    pub fn is_truncated(&self) -> bool {
+        self.truncated
+    }
+}
+
 pub trait FileExtractor {
-    fn extract(document: Document) -> Result<String, Box<dyn std::error::Error>>;
+    fn extract(document: Document) -> Result<ExtractedText, Box<dyn std::error::Error>>;
 
     fn token_distribution(
         reader: BufReader<impl std::io::Read>,
-    ) -> Result<String, Box<dyn std::error::Error>> {
+    ) -> Result<ExtractedText, Box<dyn std::error::Error>> {
         let dist = TextTokensDistribution::from_buffer(reader);
         let content = dist.export_string_nth(*EXTRACTOR_MAX_TOKENS);
 
-        Ok(content)
+        Ok(ExtractedText::new(content, false))
+    }
+
+    fn full_text(
+        mut reader: BufReader<impl std::io::Read>,
+    ) -> Result<ExtractedText, Box<dyn std::error::Error>> {
+        let limit = *EXTRACTOR_MAX_CONTENT_BYTES;
+        let mut bytes = Vec::new();
+
+        if limit == 0 {
+            reader.read_to_end(&mut bytes)?;
+        } else {
+            // One byte past the limit tells a cut text from one that fits.
+            reader.take(limit as u64 + 1).read_to_end(&mut bytes)?;
+        }
+
+        let truncated = limit > 0 && bytes.len() > limit;
+        if truncated {
+            bytes.truncate(limit);
+            // Drop a character the cut went through.
+            if let Err(e) = std::str::from_utf8(&bytes)
+                && e.error_len().is_none()
+            {
+                bytes.truncate(e.valid_up_to());
+            }
+        }
+
+        Ok(ExtractedText::new(
+            String::from_utf8_lossy(&bytes).to_string(),
+            truncated,
+        ))
+    }
+
+    fn content(
+        reader: BufReader<impl std::io::Read>,
+    ) -> Result<ExtractedText, Box<dyn std::error::Error>> {
+        match *EXTRACTOR_CONTENT_MODE {
+            ContentMode::Tokens => Self::token_distribution(reader),
+            ContentMode::FullText => Self::full_text(reader),
+        }
     }
 }
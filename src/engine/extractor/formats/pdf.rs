@@ -3,7 +3,13 @@ use std::{env, io::BufReader};
 use once_cell::sync::Lazy;
 use pdfium_render::prelude::*;
 
-use crate::{engine::extractor::{constants, formats::FileExtractor}, entities::document::Document};
+use crate::{
+    engine::extractor::{
+        constants,
+        formats::{ExtractedText, FileExtractor},
+    },
+    entities::document::Document,
+};
 
 //const LOG_TARGET: &str = "extractor_pdf";
 
@@ -19,7 +25,7 @@ pub static PDFIUM_LIB_PATH: Lazy<&'static str> = Lazy::new(|| {
 pub struct PdfExtractor;
 
 impl FileExtractor for PdfExtractor {
-    fn extract(document: Document) -> Result<String, Box<dyn std::error::Error>> {
+    fn extract(document: Document) -> Result<ExtractedText, Box<dyn std::error::Error>> {
         let lib = if PDFIUM_LIB_PATH.is_empty() {
             Pdfium::bind_to_system_library()?
         } else {
@@ -32,11 +38,11 @@ impl FileExtractor for PdfExtractor {
         let mut text = String::new();
 
         for page in document.pages().iter() {
-            let page_text = page.text().unwrap().to_string();
+            let page_text = page.text()?.to_string();
             text.push_str(&page_text);
             text.push('\n');
         }
 
-        Self::token_distribution(BufReader::new(text.as_bytes()))
+        Self::content(BufReader::new(text.as_bytes()))
     }
 }
@@ -1,6 +1,9 @@
 use std::{fs::File, io::BufReader};
 
-use crate::{engine::extractor::formats::FileExtractor, entities::document::Document};
+use crate::{
+    engine::extractor::formats::{ExtractedText, FileExtractor},
+    entities::document::Document,
+};
 
 //const LOG_TARGET: &str = "extractor_text";
 
@@ -8,9 +11,9 @@ use crate::{engine::extractor::formats::FileExtractor, entities::document::Docum
 pub struct TextExtractor;
 
 impl FileExtractor for TextExtractor {
-    fn extract(document: Document) -> Result<String, Box<dyn std::error::Error>> {
+    fn extract(document: Document) -> Result<ExtractedText, Box<dyn std::error::Error>> {
         let file = File::open(document.get_path())?;
 
-        Self::token_distribution(BufReader::new(file))
+        Self::content(BufReader::new(file))
     }
 }
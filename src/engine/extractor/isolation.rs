@@ -0,0 +1,335 @@
+use std::{
+    any::Any,
+    env,
+    error::Error,
+    fmt,
+    io::{self, Read, Write},
+    panic::{self, AssertUnwindSafe},
+    path::{Path, PathBuf},
+    process::{Command, ExitStatus, Stdio},
+    str::FromStr,
+    thread,
+    time::{Duration, Instant},
+};
+
+use crossbeam::channel::RecvTimeoutError;
+use once_cell::sync::Lazy;
+use tracing::warn;
+
+use crate::{
+    engine::{
+        bounded_channel,
+        extractor::{
+            constants,
+            formats::{
+                ExtractedText, FileExtractor, FormatType, microsoft::docx::DocxExtractor,
+                pdf::PdfExtractor, text::TextExtractor,
+            },
+        },
+    },
+    entities::{
+        document::Document,
+        failure::{ExtractionFailure, FailureKind},
+    },
+};
+
+const LOG_TARGET: &str = "extractor_isolation";
+
+/// Subcommand of the `rsearch` binary that runs `child_main`.
+pub const CHILD_SUBCOMMAND: &str = "extract-child";
+
+/// Exit code of a child whose extractor returned an error. Its last line on
+/// stderr is the failure kind and message, separated by a tab.
+const CHILD_FAILED_EXIT_CODE: i32 = 2;
+
+/// Exit code of a child whose content on stdout was truncated.
+const CHILD_TRUNCATED_EXIT_CODE: i32 = 3;
+
+const CHILD_POLL_INTERVAL: Duration = Duration::from_millis(10);
+
+/// Time budget of one extraction, `None` when `EXTRACTOR_TIMEOUT` is 0.
+pub static EXTRACTOR_TIMEOUT: Lazy<Option<Duration>> = Lazy::new(|| {
+    let timeout = env::var("EXTRACTOR_TIMEOUT")
+        .ok()
+        .and_then(|s| s.parse::<u64>().ok())
+        .unwrap_or(constants::DEFAULT_TIMEOUT_MS);
+
+    (timeout > 0).then(|| Duration::from_millis(timeout))
+});
+
+pub static EXTRACTOR_ISOLATION: Lazy<IsolationMode> = Lazy::new(|| {
+    env::var("EXTRACTOR_ISOLATION")
+        .ok()
+        .and_then(|s| IsolationMode::from_str(&s).ok())
+        .unwrap_or_else(|| {
+            IsolationMode::from_str(constants::DEFAULT_ISOLATION).unwrap_or(IsolationMode::Thread)
+        })
+});
+
+/// Program started in process isolation, the current executable when empty.
+static EXTRACTOR_CHILD_COMMAND: Lazy<&'static str> = Lazy::new(|| {
+    Box::leak(
+        env::var("EXTRACTOR_CHILD_COMMAND")
+            .unwrap_or_else(|_| constants::DEFAULT_CHILD_COMMAND.into())
+            .into_boxed_str(),
+    )
+});
+
+/// Where PDF, DOCX and text extraction runs.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum IsolationMode {
+    /// In the worker's process, on a guard thread. Panics are caught, but a
+    /// thread that runs past the timeout cannot be stopped and is left behind.
+    Thread,
+    /// In a child process, killed when it runs past the timeout.
+    Process,
+}
+
+impl FromStr for IsolationMode {
+    type Err = ();
+
+    fn from_str(input: &str) -> Result<IsolationMode, Self::Err> {
+        match input.to_lowercase().as_str() {
+            "thread" => Ok(IsolationMode::Thread),
+            "process" => Ok(IsolationMode::Process),
+            _ => Err(()),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum IsolationError {
+    Panicked(String),
+    /// The child process exited without a result, e.g. killed by a signal.
+    Exited(String),
+    TimedOut(Duration),
+    Spawn(io::Error),
+}
+
+impl fmt::Display for IsolationError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            IsolationError::Panicked(message) => write!(f, "extractor panicked: {}", message),
+            IsolationError::Exited(status) => write!(f, "extractor process failed: {}", status),
+            IsolationError::TimedOut(timeout) => {
+                write!(f, "extraction timed out after {:?}", timeout)
+            }
+            IsolationError::Spawn(err) => write!(f, "failed to start extractor: {}", err),
+        }
+    }
+}
+
+impl Error for IsolationError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            IsolationError::Spawn(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+/// Runs `extract` with panics caught and, when `EXTRACTOR_TIMEOUT` is set, on
+/// a guard thread the worker stops waiting for once the time is up.
+pub fn guard<T: Send + 'static>(
+    extractor: &'static str,
+    extract: impl FnOnce() -> Result<T, Box<dyn Error>> + Send + 'static,
+) -> Result<T, ExtractionFailure> {
+    let run = move || match panic::catch_unwind(AssertUnwindSafe(extract)) {
+        Ok(Ok(value)) => Ok(value),
+        Ok(Err(e)) => Err(ExtractionFailure::from_error(extractor, e.as_ref())),
+        Err(payload) => Err(ExtractionFailure::from_error(
+            extractor,
+            &IsolationError::Panicked(panic_message(payload.as_ref())),
+        )),
+    };
+
+    let Some(timeout) = *EXTRACTOR_TIMEOUT else {
+        return run();
+    };
+
+    let (result_tx, result_rx) = bounded_channel(1);
+    thread::Builder::new()
+        .name(format!("{}-guard", extractor))
+        .spawn(move || {
+            let _ = result_tx.send(run());
+        })
+        .map_err(|e| ExtractionFailure::from_error(extractor, &IsolationError::Spawn(e)))?;
+
+    match result_rx.recv_timeout(timeout) {
+        Ok(result) => result,
+        Err(RecvTimeoutError::Timeout) => {
+            warn!(target: LOG_TARGET, "{} timed out after {:?}, abandoning its thread", extractor, timeout);
+            Err(ExtractionFailure::from_error(
+                extractor,
+                &IsolationError::TimedOut(timeout),
+            ))
+        }
+        Err(RecvTimeoutError::Disconnected) => Err(ExtractionFailure::from_error(
+            extractor,
+            &IsolationError::Panicked("guard thread exited without a result".to_string()),
+        )),
+    }
+}
+
+/// Extracts a PDF, DOCX or text document as set by `EXTRACTOR_ISOLATION`.
+pub fn extract(
+    format: &FormatType,
+    document: Document,
+) -> Result<ExtractedText, ExtractionFailure> {
+    let extractor = extractor_name(format);
+
+    match *EXTRACTOR_ISOLATION {
+        IsolationMode::Thread => {
+            let format = format.clone();
+            guard(extractor, move || extract_direct(&format, document))
+        }
+        IsolationMode::Process => extract_in_child(extractor, &document),
+    }
+}
+
+/// Entry point of the child process: writes the content of `path` to stdout,
+/// exiting with `CHILD_TRUNCATED_EXIT_CODE` when it was truncated, or its
+/// failure to stderr and exits with `CHILD_FAILED_EXIT_CODE`.
+pub fn child_main(path: &Path) -> i32 {
+    let document = Document::from_path(path);
+    let format = document.get_format_type();
+
+    match extract_direct(&format, document) {
+        Ok(extracted) => {
+            let mut stdout = io::stdout().lock();
+            match stdout
+                .write_all(extracted.get_content().as_bytes())
+                .and_then(|_| stdout.flush())
+            {
+                Ok(_) if extracted.is_truncated() => CHILD_TRUNCATED_EXIT_CODE,
+                Ok(_) => 0,
+                Err(_) => 1,
+            }
+        }
+        Err(e) => {
+            let failure = ExtractionFailure::from_error(extractor_name(&format), e.as_ref());
+            eprintln!("{}\t{}", failure.get_kind().to_str(), failure.get_message());
+            CHILD_FAILED_EXIT_CODE
+        }
+    }
+}
+
+fn extractor_name(format: &FormatType) -> &'static str {
+    match format {
+        FormatType::Pdf => "PdfExtractor",
+        FormatType::Docx => "DocxExtractor",
+        FormatType::Text => "TextExtractor",
+        FormatType::Archive(_) => "ZipExtractor",
+        FormatType::Unknown => "Unknown",
+    }
+}
+
+fn extract_direct(
+    format: &FormatType,
+    document: Document,
+) -> Result<ExtractedText, Box<dyn Error>> {
+    match format {
+        FormatType::Pdf => PdfExtractor::extract(document),
+        FormatType::Docx => DocxExtractor::extract(document),
+        FormatType::Text => TextExtractor::extract(document),
+        _ => Err(format!("no file extractor for {} documents", format.to_str()).into()),
+    }
+}
+
+fn extract_in_child(
+    extractor: &str,
+    document: &Document,
+) -> Result<ExtractedText, ExtractionFailure> {
+    let failure = |err: IsolationError| ExtractionFailure::from_error(extractor, &err);
+
+    let program = if EXTRACTOR_CHILD_COMMAND.is_empty() {
+        env::current_exe().map_err(|e| failure(IsolationError::Spawn(e)))?
+    } else {
+        PathBuf::from(*EXTRACTOR_CHILD_COMMAND)
+    };
+
+    let mut child = Command::new(program)
+        .arg(CHILD_SUBCOMMAND)
+        .arg(document.get_path())
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .map_err(|e| failure(IsolationError::Spawn(e)))?;
+
+    // Drained while waiting, a full pipe would block the child.
+    let stdout = read_all(child.stdout.take());
+    let stderr = read_all(child.stderr.take());
+
+    let started = Instant::now();
+    let status = loop {
+        match child.try_wait() {
+            Ok(Some(status)) => break status,
+            Ok(None) => {
+                if let Some(timeout) = *EXTRACTOR_TIMEOUT
+                    && started.elapsed() >= timeout
+                {
+                    let _ = child.kill();
+                    let _ = child.wait();
+                    return Err(failure(IsolationError::TimedOut(timeout)));
+                }
+                thread::sleep(CHILD_POLL_INTERVAL);
+            }
+            Err(e) => {
+                let _ = child.kill();
+                let _ = child.wait();
+                return Err(failure(IsolationError::Spawn(e)));
+            }
+        }
+    };
+
+    let stdout = stdout.join().unwrap_or_default();
+    let stderr = String::from_utf8_lossy(&stderr.join().unwrap_or_default()).into_owned();
+    let last_line = stderr.lines().last().unwrap_or("").trim();
+
+    if status.success() || status.code() == Some(CHILD_TRUNCATED_EXIT_CODE) {
+        return Ok(ExtractedText::new(
+            String::from_utf8_lossy(&stdout).into_owned(),
+            !status.success(),
+        ));
+    }
+
+    if status.code() == Some(CHILD_FAILED_EXIT_CODE)
+        && let Some((kind, message)) = last_line.split_once('\t')
+    {
+        let kind = FailureKind::from_str(kind).unwrap_or(FailureKind::Other);
+        return Err(ExtractionFailure::new(kind, message.to_string(), extractor));
+    }
+
+    Err(failure(IsolationError::Exited(exit_description(
+        status, last_line,
+    ))))
+}
+
+fn read_all(pipe: Option<impl Read + Send + 'static>) -> thread::JoinHandle<Vec<u8>> {
+    thread::spawn(move || {
+        let mut bytes = Vec::new();
+        if let Some(mut pipe) = pipe {
+            let _ = pipe.read_to_end(&mut bytes);
+        }
+        bytes
+    })
+}
+
+fn exit_description(status: ExitStatus, last_line: &str) -> String {
+    if last_line.is_empty() {
+        status.to_string()
+    } else {
+        format!("{} ({})", status, last_line)
+    }
+}
+
+fn panic_message(payload: &(dyn Any + Send)) -> String {
+    if let Some(message) = payload.downcast_ref::<&str>() {
+        message.to_string()
+    } else if let Some(message) = payload.downcast_ref::<String>() {
+        message.clone()
+    } else {
+        "unknown panic".to_string()
+    }
+}
@@ -1,7 +1,10 @@
+pub mod commands;
 pub mod formats;
+pub mod hash;
+pub mod isolation;
+pub mod retry;
 pub mod tokens;
 pub mod workers;
-pub mod commands;
 pub mod constants {
     include!(concat!(env!("OUT_DIR"), "/extractor_constants.rs"));
 }
@@ -10,13 +13,16 @@ use std::{env, thread::JoinHandle, time::Duration};
 
 use crate::{
     engine::{
-        EngineError, EngineTask, PipelineStage, Receiver, Sender,
+        EngineError, EngineTask, EngineTaskWorker, PipelineStage,
+        decompressor::DecompressorChannelTx,
         extractor::{commands::ExtractorCommand, workers::ExtractorWorker},
-        scanner::{ScannedDocument, Scanner},
+        lanes::{LaneReceiver, LaneSender},
+        scanner::Scanner,
     },
     storage::StorageChannelTx,
 };
 use once_cell::sync::Lazy;
+use tracing::info;
 
 const LOG_TARGET: &str = "extractor";
 
@@ -35,6 +41,34 @@ static EXTRACTOR_FLUSH_INTERVAL: Lazy<Duration> = Lazy::new(|| {
         .unwrap_or(Duration::from_millis(constants::DEFAULT_FLUSH_INTERVAL_MS))
 });
 
+/// Extraction attempts for a document whose failures are transient, the
+/// first one included.
+static EXTRACTOR_RETRY_MAX_ATTEMPTS: Lazy<u32> = Lazy::new(|| {
+    env::var("EXTRACTOR_RETRY_MAX_ATTEMPTS")
+        .ok()
+        .and_then(|s| s.parse().ok())
+        .unwrap_or(constants::DEFAULT_RETRY_MAX_ATTEMPTS)
+});
+
+static EXTRACTOR_RETRY_BASE_DELAY: Lazy<Duration> = Lazy::new(|| {
+    env::var("EXTRACTOR_RETRY_BASE_DELAY")
+        .ok()
+        .and_then(|s| s.parse::<u64>().ok())
+        .map(Duration::from_millis)
+        .unwrap_or(Duration::from_millis(
+            constants::DEFAULT_RETRY_BASE_DELAY_MS,
+        ))
+});
+
+/// Capacity of each lane of the extractor channel, 0 for unbounded. The
+/// scanner blocks while its job's lane is full.
+pub static EXTRACTOR_CHANNEL_CAPACITY: Lazy<usize> = Lazy::new(|| {
+    env::var("EXTRACTOR_CHANNEL_CAPACITY")
+        .ok()
+        .and_then(|s| s.parse().ok())
+        .unwrap_or(constants::DEFAULT_CHANNEL_CAPACITY)
+});
+
 #[derive(Debug)]
 pub enum ExtractorError {
     ExtractionFailed,
@@ -42,38 +76,49 @@ pub enum ExtractorError {
     IoError(std::io::Error),
 }
 
-pub type ExtractorChannelTx = Sender<ExtractorCommand>;
-pub type ExtractorChannelRx = Receiver<ExtractorCommand>;
+pub type ExtractorChannelTx = LaneSender<ExtractorCommand>;
+pub type ExtractorChannelRx = LaneReceiver<ExtractorCommand>;
 
 #[derive(Debug)]
 pub struct Extractor {
     scanner: Scanner,
     database_tx: StorageChannelTx,
     workers: Vec<ExtractorWorker>,
+    /// Ids are not reused, a retired worker may still be running.
+    next_worker_id: usize,
     channel_tx: ExtractorChannelTx,
     channel_rx: ExtractorChannelRx,
+    decompressor_tx: DecompressorChannelTx,
 }
 
 impl Extractor {
+    /// Archives are handed over to the decompressor stage on
+    /// `decompressor_tx`.
     pub fn new(
         database_tx: StorageChannelTx,
         scanner: Scanner,
         channel_tx: ExtractorChannelTx,
         channel_rx: ExtractorChannelRx,
+        decompressor_tx: DecompressorChannelTx,
     ) -> Self {
         Extractor {
             scanner,
             database_tx,
             workers: Vec::new(),
+            next_worker_id: 0,
             channel_tx,
             channel_rx,
+            decompressor_tx,
         }
     }
 }
 
 impl PipelineStage for Extractor {
     fn add_worker(&mut self) -> Result<JoinHandle<()>, EngineError> {
-        let index = self.workers.len();
+        let index = self.next_worker_id;
+        self.next_worker_id += 1;
+
+        info!(target: LOG_TARGET, "Starting extractor worker {}", index);
 
         let database_tx = self.database_tx.clone();
         let channel_sender = self.channel_tx.clone();
@@ -85,9 +130,27 @@ impl PipelineStage for Extractor {
             self.scanner.clone(),
             channel_sender,
             channel_receiver,
+            self.decompressor_tx.clone(),
         );
         let handle = worker.run()?;
         self.workers.push(worker);
         Ok(handle)
     }
+
+    fn remove_worker(&mut self) -> Result<(), EngineError> {
+        if let Some(worker) = self.workers.pop() {
+            info!(target: LOG_TARGET, "Retiring extractor worker {}", worker.get_id());
+            worker.retire();
+        }
+
+        Ok(())
+    }
+
+    fn get_worker_count(&self) -> usize {
+        self.workers.len()
+    }
+
+    fn get_queue_depth(&self) -> usize {
+        self.channel_rx.len()
+    }
 }
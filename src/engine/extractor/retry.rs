@@ -0,0 +1,89 @@
+use std::time::{Duration, Instant};
+
+use crate::engine::{extractor::EXTRACTOR_RETRY_BASE_DELAY, scanner::ScannedDocument};
+
+/// Longest wait between two attempts, whatever the attempt count.
+const RETRY_MAX_DELAY: Duration = Duration::from_secs(60);
+
+/// Documents waiting for another extraction attempt after a transient
+/// failure. Each worker keeps its own queue.
+#[derive(Debug, Default)]
+pub struct RetryQueue {
+    pending: Vec<(Instant, ScannedDocument)>,
+}
+
+impl RetryQueue {
+    pub fn is_empty(&self) -> bool {
+        self.pending.is_empty()
+    }
+
+    pub fn len(&self) -> usize {
+        self.pending.len()
+    }
+
+    /// Delay before the attempt that follows `attempts` failed ones: the base
+    /// delay, doubled after each failure.
+    pub fn backoff(attempts: u32) -> Duration {
+        EXTRACTOR_RETRY_BASE_DELAY
+            .saturating_mul(2u32.saturating_pow(attempts.saturating_sub(1)))
+            .min(RETRY_MAX_DELAY)
+    }
+
+    pub fn push(&mut self, scanned: ScannedDocument, delay: Duration) {
+        self.pending.push((Instant::now() + delay, scanned));
+    }
+
+    /// A document whose delay has elapsed, if any.
+    pub fn pop_due(&mut self) -> Option<ScannedDocument> {
+        let now = Instant::now();
+        let index = self.pending.iter().position(|(due, _)| *due <= now)?;
+
+        Some(self.pending.swap_remove(index).1)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::entities::{container::ContainerType, document::Document};
+
+    fn scanned(path: &str) -> ScannedDocument {
+        ScannedDocument {
+            container_type: ContainerType::Folder,
+            document: Document::from_path(std::path::Path::new(path)),
+            job: None,
+            failure: None,
+        }
+    }
+
+    #[test]
+    fn backoff_doubles_after_each_failure() {
+        let base = *EXTRACTOR_RETRY_BASE_DELAY;
+
+        assert_eq!(RetryQueue::backoff(0), base.min(RETRY_MAX_DELAY));
+        assert_eq!(RetryQueue::backoff(1), base.min(RETRY_MAX_DELAY));
+        assert_eq!(RetryQueue::backoff(2), (base * 2).min(RETRY_MAX_DELAY));
+        assert_eq!(RetryQueue::backoff(3), (base * 4).min(RETRY_MAX_DELAY));
+    }
+
+    #[test]
+    fn backoff_is_capped() {
+        assert_eq!(RetryQueue::backoff(40), RETRY_MAX_DELAY);
+        assert_eq!(RetryQueue::backoff(u32::MAX), RETRY_MAX_DELAY);
+        assert!(RetryQueue::backoff(10) <= RETRY_MAX_DELAY);
+    }
+
+    #[test]
+    fn pops_only_documents_whose_delay_elapsed() {
+        let mut queue = RetryQueue::default();
+        queue.push(scanned("/data/later.txt"), Duration::from_secs(3600));
+        queue.push(scanned("/data/now.txt"), Duration::ZERO);
+        assert_eq!(queue.len(), 2);
+
+        let due = queue.pop_due().expect("a document is due");
+        assert_eq!(due.document.get_path(), "/data/now.txt");
+        assert!(queue.pop_due().is_none());
+        assert_eq!(queue.len(), 1);
+        assert!(!queue.is_empty());
+    }
+}
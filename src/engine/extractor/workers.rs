@@ -1,27 +1,33 @@
 use std::{
+    path::Path,
+    sync::{
+        Arc,
+        atomic::{AtomicBool, Ordering},
+    },
     thread::JoinHandle,
     time::{Duration, Instant},
 };
 
 use crate::{
     engine::{
-        ChannelRecvTimeoutError, EngineError, EngineTask, EngineTaskWorker,
+        ChannelRecvTimeoutError, EngineError, EngineTask, EngineTaskWorker, PipelineTracker,
+        decompressor::DecompressorChannelTx,
         extractor::{
-            EXTRACTOR_FLUSH_INTERVAL, EXTRACTOR_INSERT_BATCH_SIZE, ExtractorChannelRx,
-            ExtractorChannelTx, ExtractorCommand, ExtractorError,
-            formats::{
-                self, DataExtracted, FileExtractor, FormatType, archive::zip::ZipExtractor,
-                microsoft::docx::DocxExtractor, pdf::PdfExtractor, text::TextExtractor,
-            },
+            EXTRACTOR_FLUSH_INTERVAL, EXTRACTOR_INSERT_BATCH_SIZE, EXTRACTOR_RETRY_MAX_ATTEMPTS,
+            ExtractorChannelRx, ExtractorChannelTx, ExtractorCommand, ExtractorError,
+            formats::{EXTRACTOR_CONTENT_MODE, FormatType},
+            hash, isolation,
+            retry::RetryQueue,
         },
         scanner::{ScannedDocument, Scanner},
-        unbounded_channel,
     },
     entities::{
-        container::{Container, ContainerType},
+        container::ContainerType,
         document::DocumentStatus,
+        failure::{ExtractionFailure, FailureKind},
     },
-    storage::{StorageChannelTx, StorageError, commands::StorageCommand},
+    metrics::metrics,
+    storage::{StorageChannelTx, commands::StorageCommand},
 };
 use tracing::{error, info, warn};
 
@@ -35,7 +41,9 @@ pub struct ExtractorWorker {
     channel_tx: ExtractorChannelTx,
     channel_rx: ExtractorChannelRx,
     database_tx: StorageChannelTx,
+    decompressor_tx: DecompressorChannelTx,
     scanner: Scanner,
+    retiring: Arc<AtomicBool>,
 }
 
 impl ExtractorWorker {
@@ -45,20 +53,70 @@ impl ExtractorWorker {
         scanner: Scanner,
         channel_tx: ExtractorChannelTx,
         channel_rx: ExtractorChannelRx,
+        decompressor_tx: DecompressorChannelTx,
     ) -> Self {
         ExtractorWorker {
             id,
             database_tx,
+            decompressor_tx,
             scanner,
             channel_tx,
             channel_rx,
+            retiring: Arc::new(AtomicBool::new(false)),
         }
     }
 
+    /// Stops taking documents from the channel. The worker exits once its
+    /// retries are done and its buffer is flushed.
+    pub fn retire(&self) {
+        self.retiring.store(true, Ordering::SeqCst);
+    }
+
     pub fn get_database_tx(&self) -> &StorageChannelTx {
         &self.database_tx
     }
 
+    /// Stores a document whose extraction failed, or schedules another
+    /// attempt when the failure is transient and attempts remain.
+    fn handle_failure(
+        mut scanned: ScannedDocument,
+        mut failure: ExtractionFailure,
+        attempts: u32,
+        tracker: &PipelineTracker,
+        retries: &mut RetryQueue,
+        buffer: &mut Vec<ScannedDocument>,
+    ) {
+        failure.set_attempts(attempts);
+
+        if failure.get_kind().is_transient() && attempts < *EXTRACTOR_RETRY_MAX_ATTEMPTS {
+            let delay = RetryQueue::backoff(attempts);
+            warn!(
+                target: LOG_TARGET,
+                "Retrying {} in {:?} (attempt {} failed: {})",
+                scanned.document.get_path(),
+                delay,
+                attempts,
+                failure.get_message()
+            );
+
+            metrics().get_extraction_retries().inc();
+            scanned.failure = Some(Box::new(failure));
+            // Still pending until the last attempt, so the pipeline does not
+            // drain in between.
+            tracker.enqueued();
+            retries.push(scanned, delay);
+            return;
+        }
+
+        // Keep it findable by path without content.
+        scanned.document.set_status(DocumentStatus::Failed);
+        if let Some(job) = &scanned.job {
+            job.extraction_failed();
+        }
+        scanned.failure = Some(Box::new(failure));
+        buffer.push(scanned);
+    }
+
     pub fn flush_buffer(
         database_tx: StorageChannelTx,
         buffer: &mut Vec<ScannedDocument>,
@@ -108,141 +166,180 @@ impl EngineTask<ExtractorChannelTx, ExtractorChannelRx> for ExtractorWorker {
         let worker_id = self.id;
 
         let database_tx = self.database_tx.clone();
-        let scanner = self.scanner.clone();
-        let channel_tx = self.channel_tx.clone();
+        let decompressor_tx = self.decompressor_tx.clone();
+        let tracker = self.scanner.get_tracker().clone();
+        let retiring = self.retiring.clone();
 
         let handle = std::thread::spawn(move || {
             let mut buffer: Vec<ScannedDocument> = vec![];
             let mut last_flush = Instant::now();
+            let mut retries = RetryQueue::default();
+            let metrics = metrics();
 
             info!(target: LOG_TARGET, "Starting extractor worker {}", worker_id);
 
             loop {
-                match receiver.recv_timeout(Duration::from_millis(WORKER_RECEIVE_TIMEOUT_MS)) {
+                let received = match retries.pop_due() {
+                    Some(scanned) => Ok(ExtractorCommand::ProcessDocument(scanned)),
+                    None if retiring.load(Ordering::SeqCst) => {
+                        if retries.is_empty() {
+                            if !buffer.is_empty()
+                                && let Err(e) = Self::flush_buffer(database_tx.clone(), &mut buffer)
+                            {
+                                error!(target: LOG_TARGET, worker_id = worker_id, "Failed to flush buffer: {:?}", e);
+                            }
+
+                            info!(target: LOG_TARGET, worker_id = worker_id, "Extractor worker retired");
+                            break;
+                        }
+
+                        // Waiting for a retry to be due.
+                        std::thread::sleep(Duration::from_millis(WORKER_RECEIVE_TIMEOUT_MS));
+                        Err(ChannelRecvTimeoutError::Timeout)
+                    }
+                    None => receiver.recv_timeout(Duration::from_millis(WORKER_RECEIVE_TIMEOUT_MS)),
+                };
+                metrics
+                    .get_queue_depth()
+                    .with("extractor")
+                    .set(receiver.len() as i64);
+
+                match received {
                     Ok(command) => match command {
                         ExtractorCommand::ProcessDocument(mut scanned) => {
+                            let _completion = tracker.completion();
+
                             info!(target: LOG_TARGET, worker_id = worker_id, "Processing document: {:?}", scanned);
 
+                            // A retried document carries its previous failure.
+                            let previous_failure = scanned.failure.take();
+                            let attempts = previous_failure
+                                .as_ref()
+                                .map_or(0, |failure| failure.get_attempts())
+                                + 1;
+                            let job = scanned.job.clone();
                             let document = &mut scanned.document;
                             let document_format = document.get_format_type();
 
                             if scanned.container_type == ContainerType::Archive {
                                 info!(target: LOG_TARGET, worker_id = worker_id, "Extracting from archive document: {:?}", document);
                                 document.set_status(DocumentStatus::Extracted);
+                                if let Some(job) = &job {
+                                    job.extracted();
+                                }
 
                                 buffer.push(scanned);
                                 continue;
                             }
 
-                            match document_format {
-                                FormatType::Pdf => {
-                                    if let Ok(content) = PdfExtractor::extract(document.clone()) {
-                                        info!(target: LOG_TARGET, worker_id = worker_id, "Extracted text, length: {}", content.len());
+                            let format = document_format.to_str();
+                            let started = Instant::now();
+                            let mut failed = false;
 
-                                        document.set_content(content);
-                                        document.set_status(DocumentStatus::Extracted);
-
-                                        buffer.push(scanned);
-                                    } else {
-                                        error!(target: LOG_TARGET, worker_id = worker_id, "Failed to extract PDF document: {:?}", document);
-                                    }
-                                }
-                                FormatType::Docx => {
-                                    if let Ok(content) = DocxExtractor::extract(document.clone()) {
-                                        info!(target: LOG_TARGET, worker_id = worker_id, "Extracted text, length: {}", content.len());
+                            match document_format {
+                                FormatType::Pdf | FormatType::Docx | FormatType::Text => {
+                                    // Hashed by the worker, whatever the isolation mode,
+                                    // under the same timeout: a read that hangs fails
+                                    // the document instead of blocking the worker.
+                                    let path = document.get_path().to_string();
+                                    let hashed = isolation::guard("hash", move || {
+                                        hash::hash_file(Path::new(&path)).map_err(Into::into)
+                                    });
+                                    let extracted = match hashed {
+                                        Ok(hash) => {
+                                            document.set_hash(Some(hash));
+                                            isolation::extract(&document_format, document.clone())
+                                        }
+                                        Err(failure)
+                                            if failure.get_kind() == FailureKind::TimedOut =>
+                                        {
+                                            Err(failure)
+                                        }
+                                        Err(failure) => {
+                                            warn!(target: LOG_TARGET, worker_id = worker_id, "Failed to hash {}: {}", document.get_path(), failure.get_message());
+                                            isolation::extract(&document_format, document.clone())
+                                        }
+                                    };
 
-                                        document.set_content(content);
-                                        document.set_status(DocumentStatus::Extracted);
+                                    match extracted {
+                                        Ok(extracted) => {
+                                            info!(target: LOG_TARGET, worker_id = worker_id, "Extracted text, length: {}", extracted.get_content().len());
 
-                                        buffer.push(scanned);
-                                    } else {
-                                        error!(target: LOG_TARGET, worker_id = worker_id, "Failed to extract DOCX document: {:?}", document);
-                                    }
-                                }
-                                FormatType::Text => {
-                                    if let Ok(content) = TextExtractor::extract(document.clone()) {
-                                        info!(target: LOG_TARGET, worker_id = worker_id, "Extracted text, length: {}", content.len());
+                                            if extracted.is_truncated() {
+                                                warn!(target: LOG_TARGET, worker_id = worker_id, "Text truncated: {}", document.get_path());
+                                            }
 
-                                        document.set_content(content);
-                                        document.set_status(DocumentStatus::Extracted);
+                                            document.set_truncated(extracted.is_truncated());
+                                            document.set_content_mode(*EXTRACTOR_CONTENT_MODE);
+                                            document.set_content(extracted.into_content());
+                                            document.set_status(DocumentStatus::Extracted);
+                                            if let Some(job) = &job {
+                                                job.extracted();
+                                            }
 
-                                        buffer.push(scanned);
-                                    } else {
-                                        error!(target: LOG_TARGET, worker_id = worker_id, "Failed to extract TEXT document: {:?}", document);
+                                            buffer.push(scanned);
+                                        }
+                                        Err(failure) => {
+                                            error!(target: LOG_TARGET, worker_id = worker_id, "Failed to extract {} document: {} ({})", format, failure.get_message(), document.get_path());
+                                            failed = true;
+                                            Self::handle_failure(
+                                                scanned,
+                                                failure,
+                                                attempts,
+                                                &tracker,
+                                                &mut retries,
+                                                &mut buffer,
+                                            );
+                                        }
                                     }
                                 }
-                                FormatType::Archive(archive) => match archive {
-                                    formats::Archive::Zip => {
-                                        let zip_extractor = ZipExtractor::new(scanner.clone());
-                                        match zip_extractor.extract(document.clone()) {
-                                            Ok(data) => match data {
-                                                DataExtracted::ArchiveDocuments {
-                                                    archive,
-                                                    documents,
-                                                } => {
-                                                    info!(target: LOG_TARGET, worker_id = worker_id, "Extracted {} documents from ZIP archive", documents.len());
-
-                                                    let (resp_tx, resp_rx) = unbounded_channel::<
-                                                        Result<Container, StorageError>,
-                                                    >(
-                                                    );
-
-                                                    database_tx
-                                                        .send(StorageCommand::SaveArchive {
-                                                            archive,
-                                                            resp_tx: Some(resp_tx),
-                                                        })
-                                                        .unwrap();
-
-                                                    match resp_rx.recv() {
-                                                        Ok(result) => match result {
-                                                            Ok(archive) => {
-                                                                info!(target: LOG_TARGET, worker_id = worker_id, "Archive saved successfully with ID: {}", archive.get_id());
-
-                                                                for scanned_doc in documents {
-                                                                    let mut doc =
-                                                                        scanned_doc.document;
-                                                                    doc.set_container_id(
-                                                                        archive.get_id(),
-                                                                    );
-
-                                                                    let document = ExtractorCommand::ProcessDocument(ScannedDocument {
-                                                                    container_type: scanned_doc
-                                                                        .container_type,
-                                                                    document: doc.clone(),
-                                                                });
-
-                                                                    channel_tx
-                                                                        .send(document)
-                                                                        .unwrap();
-                                                                }
-                                                            }
-                                                            Err(e) => {
-                                                                error!(target: LOG_TARGET, worker_id = worker_id, "Failed to save archive: {:?}", e);
-                                                            }
-                                                        },
-                                                        Err(e) => {
-                                                            error!(target: LOG_TARGET, worker_id = worker_id, "Failed to receive archive save response: {:?}", e);
-                                                        }
-                                                    }
-                                                }
-                                                _ => {}
-                                            },
-                                            Err(e) => {
-                                                error!(target: LOG_TARGET, worker_id = worker_id, "Failed to extract ZIP archive: {:?} ({})", e, document.get_path());
-                                            }
+                                FormatType::Archive(_) => {
+                                    // Read by the decompressor stage, which sends the
+                                    // members back, or the archive with its failure.
+                                    scanned.failure = previous_failure;
+                                    tracker.enqueued();
+                                    if let Err(e) = decompressor_tx.send(scanned) {
+                                        tracker.completed();
+                                        error!(target: LOG_TARGET, worker_id = worker_id, "Failed to send archive to decompressor: {:?}", e);
+                                        if let Some(job) = &job {
+                                            job.failed(1);
                                         }
                                     }
-                                },
+                                    continue;
+                                }
                                 FormatType::Unknown => {
                                     error!(target: LOG_TARGET, worker_id = worker_id, "Unknown document format for document: {:?}", document);
+                                    if let Some(job) = &job {
+                                        job.skipped();
+                                    }
                                     continue;
                                 }
                             }
+
+                            metrics
+                                .get_extraction_seconds()
+                                .with(format)
+                                .observe_duration(started.elapsed());
+                            if failed {
+                                metrics.get_documents_failed().with(format).inc();
+                            } else {
+                                metrics.get_documents_extracted().with(format).inc();
+                            }
                         }
-                        _ => {
-                            warn!(target: LOG_TARGET, worker_id = worker_id, "Received unsupported command");
-                            continue;
+                        ExtractorCommand::ArchiveFailed(mut scanned) => {
+                            let _completion = tracker.completion();
+
+                            if let Some(failure) = scanned.failure.take() {
+                                let attempts = failure.get_attempts();
+                                Self::handle_failure(
+                                    scanned,
+                                    *failure,
+                                    attempts,
+                                    &tracker,
+                                    &mut retries,
+                                    &mut buffer,
+                                );
+                            }
                         }
                     },
                     Err(ChannelRecvTimeoutError::Timeout) => {
@@ -250,6 +347,12 @@ impl EngineTask<ExtractorChannelTx, ExtractorChannelRx> for ExtractorWorker {
                     }
                     Err(e) => {
                         error!(target: LOG_TARGET, "Channel receive error: {:?}", e);
+
+                        if !buffer.is_empty()
+                            && let Err(e) = Self::flush_buffer(database_tx.clone(), &mut buffer)
+                        {
+                            error!(target: LOG_TARGET, worker_id = worker_id, "Failed to flush buffer: {:?}", e);
+                        }
                         break;
                     }
                 }
@@ -267,6 +370,17 @@ impl EngineTask<ExtractorChannelTx, ExtractorChannelRx> for ExtractorWorker {
                     }
                     last_flush = Instant::now();
                 }
+
+                if tracker.is_drained() {
+                    if !buffer.is_empty()
+                        && let Err(e) = Self::flush_buffer(database_tx.clone(), &mut buffer)
+                    {
+                        error!(target: LOG_TARGET, worker_id = worker_id, "Failed to flush buffer: {:?}", e);
+                    }
+
+                    info!(target: LOG_TARGET, worker_id = worker_id, "Pipeline drained, stopping extractor worker");
+                    break;
+                }
             }
         });
 
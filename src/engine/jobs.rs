@@ -0,0 +1,600 @@
+pub mod constants {
+    include!(concat!(env!("OUT_DIR"), "/jobs_constants.rs"));
+}
+
+use std::{
+    collections::HashMap,
+    env,
+    hash::{Hash, Hasher},
+    sync::{Arc, Mutex},
+};
+
+use once_cell::sync::Lazy;
+use tracing::info;
+
+use crate::engine::{Receiver, Sender, lanes::Priority, unbounded_channel};
+
+const LOG_TARGET: &str = "scan_job";
+
+/// Seconds a completed job stays in `ScanJobs` after it finished.
+static JOBS_RETENTION_SECS: Lazy<i64> = Lazy::new(|| {
+    env::var("JOBS_RETENTION_SECS")
+        .ok()
+        .and_then(|s| s.parse().ok())
+        .unwrap_or(constants::DEFAULT_JOBS_RETENTION_SECS)
+});
+
+/// Completed jobs kept in `ScanJobs`, the most recently finished ones.
+static JOBS_MAX_FINISHED: Lazy<usize> = Lazy::new(|| {
+    env::var("JOBS_MAX_FINISHED")
+        .ok()
+        .and_then(|s| s.parse().ok())
+        .unwrap_or(constants::DEFAULT_JOBS_MAX_FINISHED)
+});
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ScanJobState {
+    /// The scanner is walking the folder.
+    Scanning,
+    /// The walk is done, documents are still being extracted or saved.
+    Processing,
+    Completed,
+}
+
+impl ScanJobState {
+    pub fn to_str(&self) -> &str {
+        match self {
+            ScanJobState::Scanning => "Scanning",
+            ScanJobState::Processing => "Processing",
+            ScanJobState::Completed => "Completed",
+        }
+    }
+}
+
+/// Snapshot of a scan job's counters.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ScanProgress {
+    id: String,
+    path: String,
+    priority: Priority,
+    state: ScanJobState,
+    discovered: usize,
+    archive_members: usize,
+    extracted: usize,
+    failed: usize,
+    skipped: usize,
+    unchanged: usize,
+    committed: usize,
+    batches: usize,
+    done: usize,
+    started_at: i64,
+    finished_at: Option<i64>,
+}
+
+impl ScanProgress {
+    fn new(id: String, path: String, priority: Priority) -> Self {
+        ScanProgress {
+            id,
+            path,
+            priority,
+            state: ScanJobState::Scanning,
+            discovered: 0,
+            archive_members: 0,
+            extracted: 0,
+            failed: 0,
+            skipped: 0,
+            unchanged: 0,
+            committed: 0,
+            batches: 0,
+            done: 0,
+            started_at: chrono::Utc::now().timestamp(),
+            finished_at: None,
+        }
+    }
+
+    pub fn get_id(&self) -> &str {
+        &self.id
+    }
+
+    pub fn get_path(&self) -> &str {
+        &self.path
+    }
+
+    pub fn get_priority(&self) -> Priority {
+        self.priority
+    }
+
+    pub fn get_state(&self) -> ScanJobState {
+        self.state
+    }
+
+    /// Files matched by the scanner's filters.
+    pub fn get_discovered(&self) -> usize {
+        self.discovered
+    }
+
+    /// Documents found inside discovered archives.
+    pub fn get_archive_members(&self) -> usize {
+        self.archive_members
+    }
+
+    pub fn get_extracted(&self) -> usize {
+        self.extracted
+    }
+
+    /// Failed extractions (the document is still stored, without content)
+    /// plus documents the storage engine could not save.
+    pub fn get_failed(&self) -> usize {
+        self.failed
+    }
+
+    /// Documents that are not stored themselves: archives, whose members
+    /// are, and unknown formats.
+    pub fn get_skipped(&self) -> usize {
+        self.skipped
+    }
+
+    /// Files left alone because they did not change since they were
+    /// indexed. They are not part of the total.
+    pub fn get_unchanged(&self) -> usize {
+        self.unchanged
+    }
+
+    /// Documents persisted by the storage engine.
+    pub fn get_committed(&self) -> usize {
+        self.committed
+    }
+
+    /// Storage transactions that contained documents of this job.
+    pub fn get_batches(&self) -> usize {
+        self.batches
+    }
+
+    /// Unix seconds.
+    pub fn get_started_at(&self) -> i64 {
+        self.started_at
+    }
+
+    pub fn get_finished_at(&self) -> Option<i64> {
+        self.finished_at
+    }
+
+    /// Documents known so far: discovered files plus archive members.
+    pub fn get_total(&self) -> usize {
+        self.discovered + self.archive_members
+    }
+
+    /// Documents that have left the pipeline, stored or not.
+    pub fn get_done(&self) -> usize {
+        self.done
+    }
+
+    /// Share of the known documents that are done, from 0 to 100. The total
+    /// keeps growing while the folder is being walked.
+    pub fn get_percent(&self) -> f64 {
+        match self.get_total() {
+            _ if self.state == ScanJobState::Completed => 100.0,
+            0 => 0.0,
+            total => self.done as f64 * 100.0 / total as f64,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ScanEvent {
+    /// Sent when the walk finishes and after every committed batch.
+    Progress(ScanProgress),
+    Completed(ScanProgress),
+}
+
+#[derive(Debug)]
+struct ScanJobInner {
+    progress: Mutex<ScanProgress>,
+    subscribers: Mutex<Vec<Sender<ScanEvent>>>,
+}
+
+/// Handle on a scan job, shared by the scanner, the extractor workers and the
+/// storage engine through the documents it produced. Clones refer to the
+/// same job.
+#[derive(Debug, Clone)]
+pub struct ScanJob {
+    id: String,
+    priority: Priority,
+    inner: Arc<ScanJobInner>,
+}
+
+impl PartialEq for ScanJob {
+    fn eq(&self, other: &Self) -> bool {
+        self.id == other.id
+    }
+}
+
+impl Eq for ScanJob {}
+
+impl Hash for ScanJob {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.id.hash(state);
+    }
+}
+
+impl ScanJob {
+    pub fn new(path: String, priority: Priority) -> Self {
+        let id = uuid::Uuid::new_v4().to_string();
+
+        ScanJob {
+            priority,
+            inner: Arc::new(ScanJobInner {
+                progress: Mutex::new(ScanProgress::new(id.clone(), path, priority)),
+                subscribers: Mutex::new(Vec::new()),
+            }),
+            id,
+        }
+    }
+
+    pub fn get_id(&self) -> &str {
+        &self.id
+    }
+
+    /// Lane of the job's scan and of every document it produces.
+    pub fn get_priority(&self) -> Priority {
+        self.priority
+    }
+
+    pub fn get_path(&self) -> String {
+        self.inner.progress.lock().unwrap().path.clone()
+    }
+
+    pub fn get_progress(&self) -> ScanProgress {
+        self.inner.progress.lock().unwrap().clone()
+    }
+
+    pub fn is_completed(&self) -> bool {
+        self.inner.progress.lock().unwrap().state == ScanJobState::Completed
+    }
+
+    /// Receives the job's events from now on. A job that has already
+    /// completed sends its final summary right away.
+    pub fn subscribe(&self) -> Receiver<ScanEvent> {
+        let (tx, rx) = unbounded_channel::<ScanEvent>();
+        let progress = self.inner.progress.lock().unwrap();
+
+        if progress.state == ScanJobState::Completed {
+            let _ = tx.send(ScanEvent::Completed(progress.clone()));
+        } else {
+            self.inner.subscribers.lock().unwrap().push(tx);
+        }
+
+        rx
+    }
+
+    pub fn discovered(&self) {
+        self.update(|progress| progress.discovered += 1);
+    }
+
+    pub fn archive_members(&self, count: usize) {
+        self.update(|progress| progress.archive_members += count);
+    }
+
+    pub fn extracted(&self) {
+        self.update(|progress| progress.extracted += 1);
+    }
+
+    /// Extraction failed but the document is still stored, without content.
+    pub fn extraction_failed(&self) {
+        self.update(|progress| progress.failed += 1);
+    }
+
+    /// Documents that failed and will not be stored.
+    pub fn failed(&self, count: usize) {
+        self.update(|progress| {
+            progress.failed += count;
+            progress.done += count;
+        });
+        self.notify();
+    }
+
+    pub fn skipped(&self) {
+        self.update(|progress| {
+            progress.skipped += 1;
+            progress.done += 1;
+        });
+    }
+
+    pub fn unchanged(&self) {
+        self.update(|progress| progress.unchanged += 1);
+    }
+
+    pub fn committed(&self, count: usize) {
+        self.update(|progress| {
+            progress.committed += count;
+            progress.batches += 1;
+            progress.done += count;
+        });
+        self.notify();
+    }
+
+    /// The scanner has walked the whole folder.
+    pub fn scan_finished(&self) {
+        self.update(|progress| progress.state = ScanJobState::Processing);
+        self.notify();
+    }
+
+    fn update(&self, apply: impl FnOnce(&mut ScanProgress)) {
+        let completed = {
+            let mut progress = self.inner.progress.lock().unwrap();
+            apply(&mut progress);
+
+            let completed =
+                progress.state == ScanJobState::Processing && progress.done >= progress.get_total();
+            if completed {
+                progress.state = ScanJobState::Completed;
+                progress.finished_at = Some(chrono::Utc::now().timestamp());
+            }
+            completed
+        };
+
+        if completed {
+            let progress = self.get_progress();
+            info!(
+                target: LOG_TARGET,
+                "Scan job {} completed: {} committed, {} failed, {} skipped",
+                self.id,
+                progress.committed,
+                progress.failed,
+                progress.skipped
+            );
+
+            // Dropping the senders closes the subscriptions.
+            for subscriber in self.inner.subscribers.lock().unwrap().drain(..) {
+                let _ = subscriber.send(ScanEvent::Completed(progress.clone()));
+            }
+        }
+    }
+
+    fn notify(&self) {
+        let progress = self.get_progress();
+        if progress.state == ScanJobState::Completed {
+            return;
+        }
+
+        self.inner.subscribers.lock().unwrap().retain(|subscriber| {
+            subscriber
+                .send(ScanEvent::Progress(progress.clone()))
+                .is_ok()
+        });
+    }
+}
+
+/// Registry of the scan jobs started through an [`crate::api::Api`].
+/// Completed jobs are dropped `JOBS_RETENTION_SECS` after they finished, or
+/// sooner past `JOBS_MAX_FINISHED` of them; handles already taken keep
+/// working.
+#[derive(Debug, Clone, Default)]
+pub struct ScanJobs {
+    jobs: Arc<Mutex<HashMap<String, ScanJob>>>,
+}
+
+impl ScanJobs {
+    pub fn create(&self, path: String, priority: Priority) -> ScanJob {
+        let job = ScanJob::new(path, priority);
+        let mut jobs = self.jobs.lock().unwrap();
+        Self::evict(&mut jobs);
+        jobs.insert(job.get_id().to_string(), job.clone());
+        job
+    }
+
+    fn evict(jobs: &mut HashMap<String, ScanJob>) {
+        let expired_before = chrono::Utc::now().timestamp() - *JOBS_RETENTION_SECS;
+        Self::evict_finished(jobs, expired_before, *JOBS_MAX_FINISHED);
+    }
+
+    /// Drops the jobs that finished before `expired_before`, and the finished
+    /// ones past the `max_finished` most recent.
+    fn evict_finished(
+        jobs: &mut HashMap<String, ScanJob>,
+        expired_before: i64,
+        max_finished: usize,
+    ) {
+        let mut finished: Vec<(i64, String)> = jobs
+            .values()
+            .filter_map(|job| {
+                let progress = job.get_progress();
+                progress
+                    .finished_at
+                    .map(|finished_at| (finished_at, progress.id))
+            })
+            .collect();
+        // Most recently finished first.
+        finished.sort_by(|a, b| b.cmp(a));
+
+        let before = jobs.len();
+        for (index, (finished_at, id)) in finished.iter().enumerate() {
+            if *finished_at < expired_before || index >= max_finished {
+                jobs.remove(id);
+            }
+        }
+
+        if jobs.len() < before {
+            info!(target: LOG_TARGET, "Evicted {} finished scan jobs", before - jobs.len());
+        }
+    }
+
+    pub fn get(&self, id: &str) -> Option<ScanJob> {
+        let mut jobs = self.jobs.lock().unwrap();
+        Self::evict(&mut jobs);
+        jobs.get(id).cloned()
+    }
+
+    /// Oldest first.
+    pub fn list(&self) -> Vec<ScanProgress> {
+        let mut jobs = self.jobs.lock().unwrap();
+        Self::evict(&mut jobs);
+
+        let mut jobs: Vec<ScanProgress> = jobs.values().map(ScanJob::get_progress).collect();
+        jobs.sort_by_key(|progress| progress.started_at);
+        jobs
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn job(discovered: usize) -> ScanJob {
+        let job = ScanJob::new("/data".to_string(), Priority::Normal);
+        for _ in 0..discovered {
+            job.discovered();
+        }
+        job
+    }
+
+    fn finished_job(finished_at: i64) -> ScanJob {
+        let job = job(0);
+        job.scan_finished();
+        job.inner.progress.lock().unwrap().finished_at = Some(finished_at);
+        job
+    }
+
+    fn registry(jobs: &[&ScanJob]) -> HashMap<String, ScanJob> {
+        jobs.iter()
+            .map(|job| (job.get_id().to_string(), (*job).clone()))
+            .collect()
+    }
+
+    #[test]
+    fn percent_follows_done_over_total() {
+        let job = job(0);
+        assert_eq!(job.get_progress().get_percent(), 0.0);
+
+        for _ in 0..4 {
+            job.discovered();
+        }
+        job.committed(1);
+        assert_eq!(job.get_progress().get_percent(), 25.0);
+
+        job.skipped();
+        assert_eq!(job.get_progress().get_percent(), 50.0);
+    }
+
+    #[test]
+    fn completes_once_the_walk_is_done_and_every_document_left() {
+        let job = job(2);
+        job.committed(2);
+        assert_eq!(job.get_progress().get_state(), ScanJobState::Scanning);
+
+        job.discovered();
+        job.scan_finished();
+        assert_eq!(job.get_progress().get_state(), ScanJobState::Processing);
+        assert!(job.get_progress().get_finished_at().is_none());
+
+        job.failed(1);
+        let progress = job.get_progress();
+        assert!(job.is_completed());
+        assert!(progress.get_finished_at().is_some());
+        assert_eq!(progress.get_percent(), 100.0);
+        assert_eq!((progress.get_committed(), progress.get_failed()), (2, 1));
+    }
+
+    #[test]
+    fn archive_members_keep_the_job_open() {
+        let job = job(1);
+        job.scan_finished();
+        job.archive_members(2);
+        job.skipped();
+        assert!(!job.is_completed());
+
+        job.committed(2);
+        assert!(job.is_completed());
+        assert_eq!(job.get_progress().get_total(), 3);
+    }
+
+    #[test]
+    fn unchanged_files_are_not_part_of_the_total() {
+        let job = job(1);
+        job.unchanged();
+        job.scan_finished();
+        assert_eq!(job.get_progress().get_total(), 1);
+
+        job.committed(1);
+        assert!(job.is_completed());
+        assert_eq!(job.get_progress().get_unchanged(), 1);
+    }
+
+    #[test]
+    fn subscribers_get_progress_then_the_summary() {
+        let job = job(2);
+        let events = job.subscribe();
+
+        job.scan_finished();
+        job.committed(1);
+        job.committed(1);
+
+        let events: Vec<ScanEvent> = events.iter().collect();
+        assert_eq!(events.len(), 3);
+        assert!(matches!(events[0], ScanEvent::Progress(_)));
+        assert!(matches!(events[1], ScanEvent::Progress(_)));
+        match &events[2] {
+            ScanEvent::Completed(progress) => assert_eq!(progress.get_committed(), 2),
+            event => panic!("expected the summary, got {:?}", event),
+        }
+    }
+
+    #[test]
+    fn subscribing_to_a_completed_job_sends_its_summary() {
+        let job = job(0);
+        job.scan_finished();
+        assert!(job.is_completed());
+
+        let events: Vec<ScanEvent> = job.subscribe().iter().collect();
+        assert!(matches!(events.as_slice(), [ScanEvent::Completed(_)]));
+    }
+
+    #[test]
+    fn evicts_jobs_finished_before_the_retention_window() {
+        let running = job(1);
+        let expired = finished_job(100);
+        let recent = finished_job(200);
+        let mut jobs = registry(&[&running, &expired, &recent]);
+
+        ScanJobs::evict_finished(&mut jobs, 150, 10);
+
+        assert!(jobs.contains_key(running.get_id()));
+        assert!(!jobs.contains_key(expired.get_id()));
+        assert!(jobs.contains_key(recent.get_id()));
+    }
+
+    #[test]
+    fn keeps_only_the_most_recently_finished_jobs() {
+        let running = job(1);
+        let oldest = finished_job(100);
+        let older = finished_job(200);
+        let newest = finished_job(300);
+        let mut jobs = registry(&[&running, &oldest, &older, &newest]);
+
+        ScanJobs::evict_finished(&mut jobs, 0, 2);
+
+        assert_eq!(jobs.len(), 3);
+        assert!(jobs.contains_key(running.get_id()));
+        assert!(!jobs.contains_key(oldest.get_id()));
+        assert!(jobs.contains_key(newest.get_id()));
+    }
+
+    #[test]
+    fn registry_finds_and_lists_created_jobs() {
+        let jobs = ScanJobs::default();
+        let first = jobs.create("/a".to_string(), Priority::Low);
+        let second = jobs.create("/b".to_string(), Priority::High);
+
+        assert_eq!(jobs.get(first.get_id()), Some(first.clone()));
+        assert_eq!(jobs.get("unknown"), None);
+
+        let listed = jobs.list();
+        assert_eq!(listed.len(), 2);
+        assert!(
+            listed
+                .iter()
+                .any(|progress| progress.get_id() == second.get_id()
+                    && progress.get_priority() == Priority::High)
+        );
+    }
+}
@@ -0,0 +1,293 @@
+use std::{
+    fmt,
+    str::FromStr,
+    time::{Duration, Instant},
+};
+
+use crossbeam::channel::{RecvError, Select, SendError, TryRecvError, TrySendError};
+
+use crate::engine::{ChannelRecvTimeoutError, Receiver, Sender, bounded_channel};
+
+/// How soon queued work is picked up. Higher lanes are always served first,
+/// messages of a lane in the order they were sent.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Priority {
+    /// Bulk background crawls.
+    Low,
+    #[default]
+    Normal,
+    /// Interactive requests and watched-file changes.
+    High,
+}
+
+impl Priority {
+    /// Highest first, the order lanes are served in.
+    pub const ALL: [Priority; 3] = [Priority::High, Priority::Normal, Priority::Low];
+
+    pub fn to_str(&self) -> &str {
+        match self {
+            Priority::Low => "low",
+            Priority::Normal => "normal",
+            Priority::High => "high",
+        }
+    }
+
+    fn lane(self) -> usize {
+        match self {
+            Priority::High => 0,
+            Priority::Normal => 1,
+            Priority::Low => 2,
+        }
+    }
+}
+
+impl FromStr for Priority {
+    type Err = ();
+
+    fn from_str(input: &str) -> Result<Priority, Self::Err> {
+        match input.to_lowercase().as_str() {
+            "low" => Ok(Priority::Low),
+            "normal" => Ok(Priority::Normal),
+            "high" => Ok(Priority::High),
+            _ => Err(()),
+        }
+    }
+}
+
+/// Messages that pick their own lane.
+pub trait Prioritized {
+    fn priority(&self) -> Priority;
+}
+
+/// Sending half of a `lane_channel`.
+pub struct LaneSender<T> {
+    lanes: Vec<Sender<T>>,
+}
+
+/// Receiving half of a `lane_channel`.
+pub struct LaneReceiver<T> {
+    lanes: Vec<Receiver<T>>,
+}
+
+/// One channel per `Priority`, each holding at most `capacity` messages
+/// (0 for unbounded), so a full low lane does not hold back higher ones.
+pub fn lane_channel<T>(capacity: usize) -> (LaneSender<T>, LaneReceiver<T>) {
+    let (senders, receivers) = Priority::ALL
+        .iter()
+        .map(|_| bounded_channel::<T>(capacity))
+        .unzip();
+
+    (
+        LaneSender { lanes: senders },
+        LaneReceiver { lanes: receivers },
+    )
+}
+
+impl<T: Prioritized> LaneSender<T> {
+    /// Blocks while the message's lane is full.
+    pub fn send(&self, message: T) -> Result<(), SendError<T>> {
+        self.lanes[message.priority().lane()].send(message)
+    }
+
+    pub fn try_send(&self, message: T) -> Result<(), TrySendError<T>> {
+        self.lanes[message.priority().lane()].try_send(message)
+    }
+}
+
+impl<T> LaneSender<T> {
+    /// Messages waiting in every lane.
+    pub fn len(&self) -> usize {
+        self.lanes.iter().map(Sender::len).sum()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+impl<T> LaneReceiver<T> {
+    /// Blocks until a message is available, taken from the highest lane that
+    /// has one.
+    pub fn recv(&self) -> Result<T, RecvError> {
+        self.recv_deadline(None).map_err(|_| RecvError)
+    }
+
+    pub fn recv_timeout(&self, timeout: Duration) -> Result<T, ChannelRecvTimeoutError> {
+        self.recv_deadline(Some(Instant::now() + timeout))
+    }
+
+    /// A message from a lane above `priority`, without blocking. Lets work of
+    /// `priority` step aside for more urgent work.
+    pub fn try_recv_above(&self, priority: Priority) -> Option<T> {
+        self.lanes[..priority.lane()]
+            .iter()
+            .find_map(|lane| lane.try_recv().ok())
+    }
+
+    /// Messages waiting in every lane.
+    pub fn len(&self) -> usize {
+        self.lanes.iter().map(Receiver::len).sum()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Messages waiting in the lane of `priority`.
+    pub fn len_of(&self, priority: Priority) -> usize {
+        self.lanes[priority.lane()].len()
+    }
+
+    fn recv_deadline(&self, deadline: Option<Instant>) -> Result<T, ChannelRecvTimeoutError> {
+        loop {
+            let mut disconnected = 0;
+            for lane in &self.lanes {
+                match lane.try_recv() {
+                    Ok(message) => return Ok(message),
+                    Err(TryRecvError::Empty) => {}
+                    Err(TryRecvError::Disconnected) => disconnected += 1,
+                }
+            }
+
+            // Lanes share their senders, so they disconnect together.
+            if disconnected == self.lanes.len() {
+                return Err(ChannelRecvTimeoutError::Disconnected);
+            }
+
+            // Waits for any lane, then starts over from the highest one.
+            let mut select = Select::new();
+            for lane in &self.lanes {
+                select.recv(lane);
+            }
+            match deadline {
+                Some(deadline) => {
+                    select
+                        .ready_deadline(deadline)
+                        .map_err(|_| ChannelRecvTimeoutError::Timeout)?;
+                }
+                None => {
+                    select.ready();
+                }
+            }
+        }
+    }
+}
+
+impl<T> Clone for LaneSender<T> {
+    fn clone(&self) -> Self {
+        LaneSender {
+            lanes: self.lanes.clone(),
+        }
+    }
+}
+
+impl<T> Clone for LaneReceiver<T> {
+    fn clone(&self) -> Self {
+        LaneReceiver {
+            lanes: self.lanes.clone(),
+        }
+    }
+}
+
+impl<T> fmt::Debug for LaneSender<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("LaneSender")
+            .field("len", &self.len())
+            .finish()
+    }
+}
+
+impl<T> fmt::Debug for LaneReceiver<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("LaneReceiver")
+            .field("len", &self.len())
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Debug, PartialEq, Eq)]
+    struct Message(Priority, u32);
+
+    impl Prioritized for Message {
+        fn priority(&self) -> Priority {
+            self.0
+        }
+    }
+
+    #[test]
+    fn serves_higher_lanes_first_and_each_lane_in_order() {
+        let (tx, rx) = lane_channel::<Message>(0);
+        tx.send(Message(Priority::Low, 1)).unwrap();
+        tx.send(Message(Priority::Normal, 2)).unwrap();
+        tx.send(Message(Priority::High, 3)).unwrap();
+        tx.send(Message(Priority::Normal, 4)).unwrap();
+        tx.send(Message(Priority::High, 5)).unwrap();
+        assert_eq!(rx.len(), 5);
+        assert_eq!(rx.len_of(Priority::Normal), 2);
+
+        let received: Vec<u32> = (0..5).map(|_| rx.recv().unwrap().1).collect();
+        assert_eq!(received, vec![3, 5, 2, 4, 1]);
+        assert!(rx.is_empty());
+    }
+
+    #[test]
+    fn try_recv_above_only_takes_more_urgent_work() {
+        let (tx, rx) = lane_channel::<Message>(0);
+        tx.send(Message(Priority::Normal, 1)).unwrap();
+        tx.send(Message(Priority::Low, 2)).unwrap();
+
+        assert_eq!(rx.try_recv_above(Priority::Normal), None);
+        assert_eq!(rx.try_recv_above(Priority::High), None);
+        assert_eq!(
+            rx.try_recv_above(Priority::Low),
+            Some(Message(Priority::Normal, 1))
+        );
+
+        tx.send(Message(Priority::High, 3)).unwrap();
+        assert_eq!(
+            rx.try_recv_above(Priority::Low),
+            Some(Message(Priority::High, 3))
+        );
+        assert_eq!(rx.len(), 1);
+    }
+
+    #[test]
+    fn a_full_lane_does_not_block_the_others() {
+        let (tx, rx) = lane_channel::<Message>(1);
+        tx.send(Message(Priority::Low, 1)).unwrap();
+        assert!(matches!(
+            tx.try_send(Message(Priority::Low, 2)),
+            Err(TrySendError::Full(_))
+        ));
+
+        tx.try_send(Message(Priority::High, 3)).unwrap();
+        assert_eq!(rx.recv().unwrap(), Message(Priority::High, 3));
+    }
+
+    #[test]
+    fn recv_timeout_reports_timeout_then_disconnection() {
+        let (tx, rx) = lane_channel::<Message>(0);
+        assert!(matches!(
+            rx.recv_timeout(Duration::from_millis(10)),
+            Err(ChannelRecvTimeoutError::Timeout)
+        ));
+
+        drop(tx);
+        assert!(matches!(
+            rx.recv_timeout(Duration::from_millis(10)),
+            Err(ChannelRecvTimeoutError::Disconnected)
+        ));
+    }
+
+    #[test]
+    fn parses_priorities() {
+        assert_eq!(Priority::from_str("HIGH"), Ok(Priority::High));
+        assert_eq!(Priority::from_str("low"), Ok(Priority::Low));
+        assert_eq!(Priority::from_str("urgent"), Err(()));
+        assert_eq!(Priority::default(), Priority::Normal);
+    }
+}
@@ -1,20 +1,48 @@
-use std::thread::JoinHandle;
+use std::{
+    sync::{
+        Arc,
+        atomic::{AtomicBool, AtomicUsize, Ordering},
+    },
+    thread::JoinHandle,
+};
 
-use crate::storage::StorageError;
+use tracing::info;
 
+use crate::{
+    api::{Api, ApiError},
+    engine::{
+        builder::EngineBuilder, scaling::ScalableStage, scanner::ScannerError,
+        watcher::WatcherError,
+    },
+    storage::StorageError,
+};
+
+pub mod builder;
 pub mod classifier;
 pub mod decompressor;
 pub mod extractor;
+pub mod jobs;
+pub mod lanes;
+pub mod scaling;
 pub mod scanner;
 pub mod utils;
+pub mod watcher;
 
-//const LOG_TARGET: &str = "engine";
+const LOG_TARGET: &str = "engine";
 
 pub type Sender<T> = crossbeam::channel::Sender<T>;
 pub type Receiver<T> = crossbeam::channel::Receiver<T>;
 pub fn unbounded_channel<T>() -> (Sender<T>, Receiver<T>) {
     crossbeam::channel::unbounded::<T>()
 }
+/// Channel holding at most `capacity` messages, senders block while it is
+/// full. A capacity of 0 means unbounded.
+pub fn bounded_channel<T>(capacity: usize) -> (Sender<T>, Receiver<T>) {
+    match capacity {
+        0 => crossbeam::channel::unbounded::<T>(),
+        capacity => crossbeam::channel::bounded::<T>(capacity),
+    }
+}
 pub type ChannelRecvTimeoutError = crossbeam::channel::RecvTimeoutError;
 
 #[derive(Debug)]
@@ -24,11 +52,140 @@ pub enum EngineError {
     RusqliteError(rusqlite::Error),
     StorageError(StorageError),
     ExtractorError(extractor::ExtractorError),
+    ScannerError(ScannerError),
+    WatcherError(WatcherError),
+    /// The stage is shutting down and cannot be resized.
+    StageClosed,
+    /// A pipeline thread panicked, named after its stage.
+    WorkerPanicked(String),
+    /// Storage did not acknowledge the shutdown.
+    StorageShutdownFailed,
+}
+
+/// Work accounting shared by the scanner and the extractor workers.
+///
+/// Every document sent to the extractor channel is counted as pending until a
+/// worker has finished with it, including archive members re-enqueued by a
+/// worker. Once the scanner has been shut down (`close`) and nothing is
+/// pending, the pipeline is drained and the workers can exit.
+#[derive(Debug, Clone, Default)]
+pub struct PipelineTracker {
+    pending: Arc<AtomicUsize>,
+    closed: Arc<AtomicBool>,
+}
+
+impl PipelineTracker {
+    pub fn get_pending(&self) -> usize {
+        self.pending.load(Ordering::SeqCst)
+    }
+
+    pub fn is_closed(&self) -> bool {
+        self.closed.load(Ordering::SeqCst)
+    }
+
+    pub fn is_drained(&self) -> bool {
+        self.is_closed() && self.get_pending() == 0
+    }
+
+    /// No more scans will be queued.
+    pub fn close(&self) {
+        self.closed.store(true, Ordering::SeqCst);
+    }
+
+    /// Must be called before the document is sent, so that the count never
+    /// drops to zero while it is in flight.
+    pub fn enqueued(&self) {
+        self.pending.fetch_add(1, Ordering::SeqCst);
+    }
+
+    pub fn completed(&self) {
+        self.pending.fetch_sub(1, Ordering::SeqCst);
+    }
+
+    /// Guard that marks one pending document as completed when dropped.
+    pub fn completion(&self) -> Completion {
+        Completion {
+            tracker: self.clone(),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct Completion {
+    tracker: PipelineTracker,
 }
 
-#[derive(Debug, Default)]
+impl Drop for Completion {
+    fn drop(&mut self) {
+        self.tracker.completed();
+    }
+}
+
+/// The whole pipeline, storage, scanner, extractor and decompressor stages,
+/// built with `Engine::builder`. Dropping it without `shutdown` leaves the
+/// threads running until the process exits.
+#[derive(Debug)]
 pub struct Engine {
-    pub classifier: classifier::Classifier,
+    api: Api,
+    storage_handle: JoinHandle<()>,
+    scanner_handles: Vec<JoinHandle<()>>,
+    watcher_handles: Vec<JoinHandle<()>>,
+    stages: Vec<ScalableStage>,
+}
+
+impl Engine {
+    pub fn builder() -> EngineBuilder {
+        EngineBuilder::default()
+    }
+
+    pub fn get_api(&self) -> &Api {
+        &self.api
+    }
+
+    pub fn get_stages(&self) -> &[ScalableStage] {
+        &self.stages
+    }
+
+    /// Drains the pipeline: the watcher sends its pending changes, the
+    /// scanner finishes the queued scans, the extractor workers flush their
+    /// last batches, then storage persists everything and stops.
+    pub fn shutdown(self) -> Result<(), EngineError> {
+        // The scanner must still be running to take its last changes.
+        let _ = self.api.finish_watching();
+        for handle in self.watcher_handles {
+            handle
+                .join()
+                .map_err(|_| EngineError::WorkerPanicked("watcher".to_string()))?;
+        }
+
+        // Without extraction there is no scanner thread to notify.
+        let _ = self.api.finish_scans();
+
+        for handle in self.scanner_handles {
+            handle
+                .join()
+                .map_err(|_| EngineError::WorkerPanicked("scanner".to_string()))?;
+        }
+        info!(target: LOG_TARGET, "Scanner has finished");
+
+        for stage in &self.stages {
+            if !stage.join() {
+                return Err(EngineError::WorkerPanicked(stage.get_name().to_string()));
+            }
+            info!(target: LOG_TARGET, "Stage {} has finished", stage.get_name());
+        }
+
+        self.api.shutdown_storage().map_err(|e| match e {
+            ApiError::StorageError(e) => EngineError::StorageError(e),
+            _ => EngineError::StorageShutdownFailed,
+        })?;
+        self.storage_handle
+            .join()
+            .map_err(|_| EngineError::WorkerPanicked("storage".to_string()))?;
+        info!(target: LOG_TARGET, "Storage has finished");
+
+        Ok(())
+    }
 }
 
 pub trait PipelineStage {
@@ -41,6 +198,16 @@ pub trait PipelineStage {
     }
 
     fn add_worker(&mut self) -> Result<JoinHandle<()>, EngineError>;
+
+    /// Asks the most recently added worker to stop. It finishes the work it
+    /// holds, without taking more from the stage's channel, then exits.
+    fn remove_worker(&mut self) -> Result<(), EngineError>;
+
+    /// Workers running, retiring ones excluded.
+    fn get_worker_count(&self) -> usize;
+
+    /// Messages waiting in the stage's channel.
+    fn get_queue_depth(&self) -> usize;
 }
 
 pub trait EngineTask<S, R> {
@@ -0,0 +1,354 @@
+pub mod constants {
+    include!(concat!(env!("OUT_DIR"), "/scaling_constants.rs"));
+}
+
+use std::{
+    env, fmt,
+    sync::{
+        Arc, Mutex,
+        atomic::{AtomicBool, Ordering},
+    },
+    thread::{self, JoinHandle},
+    time::Duration,
+};
+
+use once_cell::sync::Lazy;
+use tracing::{error, info};
+
+use crate::engine::{EngineError, PipelineStage};
+
+const LOG_TARGET: &str = "scaling";
+
+static AUTOSCALE_INTERVAL: Lazy<Duration> = Lazy::new(|| {
+    env::var("AUTOSCALE_INTERVAL")
+        .ok()
+        .and_then(|s| s.parse::<u64>().ok())
+        .map(Duration::from_millis)
+        .unwrap_or(Duration::from_millis(
+            constants::DEFAULT_AUTOSCALE_INTERVAL_MS,
+        ))
+});
+
+/// Queue depth above which the autoscaler adds a worker.
+static AUTOSCALE_QUEUE_HIGH_WATER: Lazy<usize> = Lazy::new(|| {
+    env::var("AUTOSCALE_QUEUE_HIGH_WATER")
+        .ok()
+        .and_then(|s| s.parse().ok())
+        .unwrap_or(constants::DEFAULT_AUTOSCALE_QUEUE_HIGH_WATER)
+});
+
+/// One-minute load average per CPU above which the autoscaler removes a
+/// worker.
+static AUTOSCALE_MAX_LOAD: Lazy<f64> = Lazy::new(|| {
+    env::var("AUTOSCALE_MAX_LOAD")
+        .ok()
+        .and_then(|s| s.parse().ok())
+        .unwrap_or(constants::DEFAULT_AUTOSCALE_MAX_LOAD)
+});
+
+/// Bounds and thresholds the autoscaler keeps a stage within.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct AutoscalePolicy {
+    min_workers: usize,
+    max_workers: usize,
+    queue_high_water: usize,
+    max_load: f64,
+}
+
+impl AutoscalePolicy {
+    /// Thresholds from `AUTOSCALE_QUEUE_HIGH_WATER` and `AUTOSCALE_MAX_LOAD`.
+    pub fn new(min_workers: usize, max_workers: usize) -> Self {
+        let min_workers = min_workers.max(1);
+
+        AutoscalePolicy {
+            min_workers,
+            max_workers: max_workers.max(min_workers),
+            queue_high_water: *AUTOSCALE_QUEUE_HIGH_WATER,
+            max_load: *AUTOSCALE_MAX_LOAD,
+        }
+    }
+
+    pub fn get_min_workers(&self) -> usize {
+        self.min_workers
+    }
+
+    pub fn get_max_workers(&self) -> usize {
+        self.max_workers
+    }
+
+    pub fn get_queue_high_water(&self) -> usize {
+        self.queue_high_water
+    }
+
+    pub fn set_queue_high_water(&mut self, queue_high_water: usize) {
+        self.queue_high_water = queue_high_water;
+    }
+
+    pub fn get_max_load(&self) -> f64 {
+        self.max_load
+    }
+
+    pub fn set_max_load(&mut self, max_load: f64) {
+        self.max_load = max_load;
+    }
+
+    /// Worker count for the next interval: one step at a time, down while the
+    /// machine is overloaded or the queue is empty, up while the queue is
+    /// above the high water mark.
+    pub fn target(&self, workers: usize, queue_depth: usize, load: Option<f64>) -> usize {
+        let overloaded = load.is_some_and(|load| load > self.max_load);
+
+        if workers < self.min_workers {
+            self.min_workers
+        } else if workers > self.max_workers {
+            self.max_workers
+        } else if (overloaded || queue_depth == 0) && workers > self.min_workers {
+            workers - 1
+        } else if !overloaded && queue_depth > self.queue_high_water && workers < self.max_workers {
+            workers + 1
+        } else {
+            workers
+        }
+    }
+}
+
+/// Snapshot of a stage, see `ScalableStage::get_status`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct StageStatus {
+    name: String,
+    workers: usize,
+    queue_depth: usize,
+    autoscale: Option<AutoscalePolicy>,
+}
+
+impl StageStatus {
+    pub fn get_name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn get_workers(&self) -> usize {
+        self.workers
+    }
+
+    pub fn get_queue_depth(&self) -> usize {
+        self.queue_depth
+    }
+
+    pub fn get_autoscale(&self) -> Option<&AutoscalePolicy> {
+        self.autoscale.as_ref()
+    }
+}
+
+/// A running pipeline stage whose worker count can change at runtime, shared
+/// by `Api`, its autoscaler and whoever joins the workers at shutdown.
+#[derive(Clone)]
+pub struct ScalableStage {
+    name: String,
+    stage: Arc<Mutex<Box<dyn PipelineStage + Send>>>,
+    handles: Arc<Mutex<Vec<JoinHandle<()>>>>,
+    autoscale: Arc<Mutex<Option<AutoscalePolicy>>>,
+    autoscaler_running: Arc<AtomicBool>,
+    closed: Arc<AtomicBool>,
+}
+
+impl fmt::Debug for ScalableStage {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ScalableStage")
+            .field("name", &self.name)
+            .field("closed", &self.closed)
+            .finish_non_exhaustive()
+    }
+}
+
+impl ScalableStage {
+    /// Starts `workers` workers of the stage.
+    pub fn start(
+        name: &str,
+        mut stage: impl PipelineStage + Send + 'static,
+        workers: usize,
+    ) -> Result<Self, EngineError> {
+        let handles = stage.init(workers)?;
+
+        Ok(ScalableStage {
+            name: name.to_string(),
+            stage: Arc::new(Mutex::new(Box::new(stage))),
+            handles: Arc::new(Mutex::new(handles)),
+            autoscale: Arc::new(Mutex::new(None)),
+            autoscaler_running: Arc::new(AtomicBool::new(false)),
+            closed: Arc::new(AtomicBool::new(false)),
+        })
+    }
+
+    pub fn get_name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn get_workers(&self) -> usize {
+        self.stage.lock().unwrap().get_worker_count()
+    }
+
+    pub fn get_status(&self) -> StageStatus {
+        let stage = self.stage.lock().unwrap();
+
+        StageStatus {
+            name: self.name.clone(),
+            workers: stage.get_worker_count(),
+            queue_depth: stage.get_queue_depth(),
+            autoscale: *self.autoscale.lock().unwrap(),
+        }
+    }
+
+    /// Adds or retires workers until `workers` are running, at least one.
+    /// Retired workers finish what they hold before exiting.
+    pub fn scale(&self, workers: usize) -> Result<usize, EngineError> {
+        let workers = workers.max(1);
+        let mut stage = self.stage.lock().unwrap();
+
+        // Checked under the lock, so `join` cannot miss a new worker.
+        if self.closed.load(Ordering::SeqCst) {
+            return Err(EngineError::StageClosed);
+        }
+
+        while stage.get_worker_count() < workers {
+            let handle = stage.add_worker()?;
+            self.handles.lock().unwrap().push(handle);
+        }
+        while stage.get_worker_count() > workers {
+            stage.remove_worker()?;
+        }
+
+        info!(target: LOG_TARGET, "Stage {} scaled to {} workers", self.name, workers);
+
+        Ok(workers)
+    }
+
+    /// Lets the autoscaler resize the stage within `policy` every
+    /// `AUTOSCALE_INTERVAL` ms, or stops it with `None`.
+    pub fn set_autoscale(&self, policy: Option<AutoscalePolicy>) -> Result<(), EngineError> {
+        if self.closed.load(Ordering::SeqCst) {
+            return Err(EngineError::StageClosed);
+        }
+
+        *self.autoscale.lock().unwrap() = policy;
+
+        if policy.is_some() && !self.autoscaler_running.swap(true, Ordering::SeqCst) {
+            let stage = self.clone();
+            thread::Builder::new()
+                .name(format!("{}-autoscaler", self.name))
+                .spawn(move || stage.autoscale_loop())
+                .map_err(EngineError::IoError)?;
+        }
+
+        Ok(())
+    }
+
+    pub fn get_autoscale(&self) -> Option<AutoscalePolicy> {
+        *self.autoscale.lock().unwrap()
+    }
+
+    fn autoscale_loop(&self) {
+        info!(target: LOG_TARGET, "Autoscaler started for stage {}", self.name);
+
+        while !self.closed.load(Ordering::SeqCst) {
+            thread::sleep(*AUTOSCALE_INTERVAL);
+
+            let Some(policy) = self.get_autoscale() else {
+                continue;
+            };
+
+            let status = self.get_status();
+            let target = policy.target(status.workers, status.queue_depth, cpu_load());
+
+            if target == status.workers {
+                continue;
+            }
+
+            match self.scale(target) {
+                Ok(_) => {}
+                Err(EngineError::StageClosed) => break,
+                Err(e) => {
+                    error!(target: LOG_TARGET, "Failed to scale stage {}: {:?}", self.name, e);
+                }
+            }
+        }
+    }
+
+    /// Stops resizing and waits for every worker ever started, including the
+    /// ones started by scaling. Returns false if one of them panicked.
+    pub fn join(&self) -> bool {
+        self.closed.store(true, Ordering::SeqCst);
+        // Waits for a resize in progress.
+        drop(self.stage.lock().unwrap());
+
+        let mut ok = true;
+        loop {
+            let handle = self.handles.lock().unwrap().pop();
+            match handle {
+                Some(handle) => ok &= handle.join().is_ok(),
+                None => break,
+            }
+        }
+
+        ok
+    }
+}
+
+/// One-minute load average per CPU, where the platform reports it.
+fn cpu_load() -> Option<f64> {
+    let loadavg = std::fs::read_to_string("/proc/loadavg").ok()?;
+    let load: f64 = loadavg.split_whitespace().next()?.parse().ok()?;
+    let cpus = thread::available_parallelism().ok()?.get();
+
+    Some(load / cpus as f64)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn policy(min_workers: usize, max_workers: usize) -> AutoscalePolicy {
+        let mut policy = AutoscalePolicy::new(min_workers, max_workers);
+        policy.set_queue_high_water(10);
+        policy.set_max_load(4.0);
+        policy
+    }
+
+    #[test]
+    fn new_keeps_the_bounds_consistent() {
+        let policy = AutoscalePolicy::new(0, 0);
+        assert_eq!((policy.get_min_workers(), policy.get_max_workers()), (1, 1));
+
+        let policy = AutoscalePolicy::new(4, 2);
+        assert_eq!((policy.get_min_workers(), policy.get_max_workers()), (4, 4));
+    }
+
+    #[test]
+    fn target_brings_the_worker_count_back_within_bounds() {
+        let policy = policy(2, 8);
+        assert_eq!(policy.target(0, 0, None), 2);
+        assert_eq!(policy.target(12, 100, None), 8);
+    }
+
+    #[test]
+    fn target_adds_a_worker_above_the_high_water_mark() {
+        let policy = policy(1, 8);
+        assert_eq!(policy.target(3, 11, None), 4);
+        assert_eq!(policy.target(3, 11, Some(1.0)), 4);
+        assert_eq!(policy.target(8, 11, None), 8);
+    }
+
+    #[test]
+    fn target_holds_between_empty_and_high_water() {
+        let policy = policy(1, 8);
+        assert_eq!(policy.target(3, 10, None), 3);
+        assert_eq!(policy.target(3, 1, Some(4.0)), 3);
+    }
+
+    #[test]
+    fn target_removes_a_worker_when_idle_or_overloaded() {
+        let policy = policy(2, 8);
+        assert_eq!(policy.target(5, 0, None), 4);
+        assert_eq!(policy.target(5, 50, Some(4.5)), 4);
+        assert_eq!(policy.target(2, 0, Some(9.0)), 2);
+    }
+}
@@ -0,0 +1,32 @@
+use crate::engine::{
+    jobs::ScanJob,
+    lanes::{Prioritized, Priority},
+};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ScannerCommand {
+    /// Walk the job's path.
+    Scan(ScanJob),
+    /// Queue the given files again, without applying the filters or the
+    /// size and modification time check. Paths that are no longer files are
+    /// skipped.
+    Rescan { job: ScanJob, paths: Vec<String> },
+    /// Files and folders created or modified under a watched root. Files go
+    /// through the filters, folders are walked like a scan.
+    Changes { job: ScanJob, paths: Vec<String> },
+    /// Stop accepting scans once the queued ones are done, and close the
+    /// pipeline so the extractor workers exit after draining.
+    Shutdown,
+}
+
+impl Prioritized for ScannerCommand {
+    /// Shutdown waits behind every queued scan.
+    fn priority(&self) -> Priority {
+        match self {
+            ScannerCommand::Scan(job)
+            | ScannerCommand::Rescan { job, .. }
+            | ScannerCommand::Changes { job, .. } => job.get_priority(),
+            ScannerCommand::Shutdown => Priority::Low,
+        }
+    }
+}
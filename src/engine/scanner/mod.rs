@@ -1,7 +1,10 @@
+pub mod commands;
 pub mod filters;
 
 use std::{
+    collections::HashMap,
     path::Path,
+    str::FromStr,
     thread::{self, JoinHandle},
 };
 
@@ -9,14 +12,24 @@ use tracing::{error, info};
 
 use crate::{
     engine::{
-        Receiver, Sender,
-        extractor::{ExtractorChannelTx, commands::ExtractorCommand},
-        scanner::filters::{Filter, FilterError},
+        PipelineTracker,
+        extractor::{ExtractorChannelTx, commands::ExtractorCommand, formats::FormatType},
+        jobs::ScanJob,
+        lanes::{LaneReceiver, LaneSender, Prioritized, Priority},
+        scanner::{
+            commands::ScannerCommand,
+            filters::{Filter, FilterError},
+        },
+        unbounded_channel,
     },
     entities::{
         container::ContainerType,
         document::{Document, DocumentStatus},
+        failure::ExtractionFailure,
+        stamp::FileStamp,
     },
+    metrics::metrics,
+    storage::{StorageChannelTx, commands::StorageCommand},
 };
 
 const LOG_TARGET: &str = "scanner";
@@ -38,18 +51,37 @@ pub enum FiltersMode {
 pub struct ScannedDocument {
     pub container_type: ContainerType,
     pub document: Document,
+    /// Scan job the document belongs to, for progress reporting.
+    pub job: Option<ScanJob>,
+    /// Set by the extractor when the content could not be extracted.
+    pub failure: Option<Box<ExtractionFailure>>,
 }
 
-pub type ScannerChannelTx = Sender<String>;
-pub type ScannerChannelRx = Receiver<String>;
+impl ScannedDocument {
+    /// The job's priority, `Priority::Normal` without a job.
+    pub fn priority(&self) -> Priority {
+        self.job
+            .as_ref()
+            .map_or(Priority::default(), ScanJob::get_priority)
+    }
+}
+
+pub type ScannerChannelTx = LaneSender<ScannerCommand>;
+pub type ScannerChannelRx = LaneReceiver<ScannerCommand>;
 
 #[derive(Debug, Clone)]
 pub struct Scanner {
     filters: Vec<Filter>,
     filters_mode: FiltersMode,
+    /// Formats sent to the extractor, all of them when `None`.
+    formats: Option<Vec<FormatType>>,
     channel_tx: ScannerChannelTx,
     channel_rx: ScannerChannelRx,
     channel_extractor_tx: ExtractorChannelTx,
+    /// Where the stamps of indexed files are loaded from. Without it every
+    /// file is extracted again.
+    database_tx: Option<StorageChannelTx>,
+    tracker: PipelineTracker,
 }
 
 impl Scanner {
@@ -61,12 +93,25 @@ impl Scanner {
         Scanner {
             filters: Vec::new(),
             filters_mode: FiltersMode::And,
+            formats: None,
             channel_tx,
             channel_rx,
             channel_extractor_tx,
+            database_tx: None,
+            tracker: PipelineTracker::default(),
         }
     }
 
+    pub fn get_channel_tx(&self) -> &ScannerChannelTx {
+        &self.channel_tx
+    }
+
+    /// Shared with every clone of this scanner, including the ones handed to
+    /// the extractor workers.
+    pub fn get_tracker(&self) -> &PipelineTracker {
+        &self.tracker
+    }
+
     pub fn check_filters(&self, path: &Path) -> bool {
         if self.filters.is_empty() {
             return true;
@@ -101,33 +146,226 @@ impl Scanner {
         self.filters.push(filter);
     }
 
-    fn process_document(&mut self, document: Document) {
+    pub fn set_formats(&mut self, formats: Option<Vec<FormatType>>) {
+        self.formats = formats;
+    }
+
+    /// Enables incremental scans: files whose size and modification time
+    /// did not change since they were indexed are skipped.
+    pub fn set_database_tx(&mut self, database_tx: Option<StorageChannelTx>) {
+        self.database_tx = database_tx;
+    }
+
+    pub fn check_format(&self, path: &Path) -> bool {
+        let Some(formats) = &self.formats else {
+            return true;
+        };
+
+        let extension = path.extension().and_then(|ext| ext.to_str()).unwrap_or("");
+        let format = FormatType::from_str(extension).unwrap_or(FormatType::Unknown);
+
+        formats.contains(&format)
+    }
+
+    fn process_document(&mut self, document: Document, job: Option<&ScanJob>) {
         let document = ExtractorCommand::ProcessDocument(ScannedDocument {
             container_type: ContainerType::Folder, // You might want to set this appropriately
             document: document.clone(),
+            job: job.cloned(),
+            failure: None,
         });
 
+        self.tracker.enqueued();
         if let Err(e) = self.channel_extractor_tx.send(document) {
+            self.tracker.completed();
             error!(target: LOG_TARGET, "Failed to send document to extractor: {:?}", e);
         }
     }
 
-    pub fn scan_folder(&mut self, path: &str) {
+    /// Stamps of what is indexed under `path`, loaded once per scan. Empty
+    /// without a storage channel or when they cannot be loaded, so every file
+    /// is extracted.
+    fn load_stamps(&self, path: &str) -> HashMap<String, FileStamp> {
+        let Some(database_tx) = &self.database_tx else {
+            return HashMap::new();
+        };
+
+        let (resp_tx, resp_rx) = unbounded_channel();
+        if let Err(e) = database_tx.send(StorageCommand::ListFileStamps {
+            path: path.to_string(),
+            resp_tx,
+        }) {
+            error!(target: LOG_TARGET, "Failed to request file stamps: {:?}", e);
+            return HashMap::new();
+        }
+
+        match resp_rx.recv() {
+            Ok(Ok(stamps)) => stamps,
+            Ok(Err(e)) => {
+                error!(target: LOG_TARGET, "Failed to load file stamps, scanning everything: {:?}", e);
+                HashMap::new()
+            }
+            Err(e) => {
+                error!(target: LOG_TARGET, "Failed to receive file stamps: {:?}", e);
+                HashMap::new()
+            }
+        }
+    }
+
+    pub fn scan_folder(&mut self, path: &str, job: Option<&ScanJob>) {
+        self.walk_folder(path, job);
+
+        if let Some(job) = job {
+            job.scan_finished();
+        }
+    }
+
+    fn walk_folder(&mut self, path: &str, job: Option<&ScanJob>) {
         info!(target: LOG_TARGET, "Scanning folder: {}", path);
 
+        let stamps = self.load_stamps(path);
         let walker = walkdir::WalkDir::new(path);
+        let metrics = metrics();
+        let priority = job.map_or(Priority::default(), ScanJob::get_priority);
 
         for entry in walker.into_iter().filter_map(|e| e.ok()) {
+            self.run_preempting(priority);
+
             let file_path = entry.path();
+            let is_file = entry.file_type().is_file();
 
-            if self.check_filters(file_path) {
-                info!(target: LOG_TARGET, "Found file: {:?}", file_path);
+            if is_file {
+                metrics.get_files_seen().inc();
+            }
+
+            if self.check_filters(file_path) && self.check_format(file_path) {
                 let mut document = Document::from_path(file_path);
                 document.set_status(DocumentStatus::Scanned);
 
-                self.process_document(document);
+                if let Ok(metadata) = entry.metadata() {
+                    document.set_metadata(&metadata);
+                }
+
+                if stamps.get(document.get_path()).is_some_and(|stamp| {
+                    stamp.is_unchanged(document.get_size(), document.get_modified())
+                }) {
+                    metrics.get_files_unchanged().inc();
+                    if let Some(job) = job {
+                        job.unchanged();
+                    }
+                    continue;
+                }
+
+                info!(target: LOG_TARGET, "Found file: {:?}", file_path);
+
+                if let Some(job) = job {
+                    job.discovered();
+                }
+                self.process_document(document, job);
+            } else if is_file {
+                metrics.get_files_filtered().inc();
+            }
+        }
+    }
+
+    pub fn scan_files(&mut self, paths: &[String], job: Option<&ScanJob>) {
+        info!(target: LOG_TARGET, "Rescanning {} files", paths.len());
+
+        let priority = job.map_or(Priority::default(), ScanJob::get_priority);
+
+        for path in paths {
+            self.run_preempting(priority);
+
+            let file_path = Path::new(path);
+            let metadata = match file_path.metadata() {
+                Ok(metadata) if metadata.is_file() => metadata,
+                _ => {
+                    info!(target: LOG_TARGET, "Skipping missing file: {}", path);
+                    continue;
+                }
+            };
+
+            metrics().get_files_seen().inc();
+
+            let mut document = Document::from_path(file_path);
+            document.set_status(DocumentStatus::Scanned);
+            document.set_metadata(&metadata);
+
+            if let Some(job) = job {
+                job.discovered();
+            }
+            self.process_document(document, job);
+        }
+
+        if let Some(job) = job {
+            job.scan_finished();
+        }
+    }
+
+    pub fn scan_changes(&mut self, paths: &[String], job: Option<&ScanJob>) {
+        info!(target: LOG_TARGET, "Scanning {} changed paths", paths.len());
+
+        let priority = job.map_or(Priority::default(), ScanJob::get_priority);
+
+        for path in paths {
+            self.run_preempting(priority);
+
+            let file_path = Path::new(path);
+            let metadata = match file_path.metadata() {
+                Ok(metadata) if metadata.is_dir() => {
+                    self.walk_folder(path, job);
+                    continue;
+                }
+                Ok(metadata) if metadata.is_file() => metadata,
+                _ => continue,
+            };
+
+            metrics().get_files_seen().inc();
+
+            if !self.check_filters(file_path) || !self.check_format(file_path) {
+                metrics().get_files_filtered().inc();
+                continue;
+            }
+
+            let mut document = Document::from_path(file_path);
+            document.set_status(DocumentStatus::Scanned);
+            document.set_metadata(&metadata);
+
+            info!(target: LOG_TARGET, "Changed file: {:?}", file_path);
+
+            if let Some(job) = job {
+                job.discovered();
+            }
+            self.process_document(document, job);
+        }
+
+        if let Some(job) = job {
+            job.scan_finished();
+        }
+    }
+
+    /// Runs the commands queued above `priority` before going on, so an
+    /// interactive scan does not wait for a bulk crawl in progress.
+    fn run_preempting(&mut self, priority: Priority) {
+        while let Some(command) = self.channel_rx.try_recv_above(priority) {
+            info!(target: LOG_TARGET, "Pausing {} priority scan for {} priority command", priority.to_str(), command.priority().to_str());
+            self.run_command(command);
+        }
+    }
+
+    /// Returns false on `ScannerCommand::Shutdown`.
+    fn run_command(&mut self, command: ScannerCommand) -> bool {
+        match command {
+            ScannerCommand::Scan(job) => self.scan_folder(&job.get_path(), Some(&job)),
+            ScannerCommand::Rescan { job, paths } => self.scan_files(&paths, Some(&job)),
+            ScannerCommand::Changes { job, paths } => self.scan_changes(&paths, Some(&job)),
+            ScannerCommand::Shutdown => {
+                info!(target: LOG_TARGET, "Scanner shutting down");
+                return false;
             }
         }
+
+        true
     }
 
     pub fn init(&mut self) -> Result<Vec<JoinHandle<()>>, ScannerError> {
@@ -137,9 +375,18 @@ impl Scanner {
         let mut scanner = self.clone();
 
         let handle = thread::spawn(move || {
-            while let Ok(path) = scanner.channel_rx.recv() {
-                scanner.scan_folder(&path);
+            while let Ok(command) = scanner.channel_rx.recv() {
+                metrics()
+                    .get_queue_depth()
+                    .with(LOG_TARGET)
+                    .set(scanner.channel_rx.len() as i64);
+
+                if !scanner.run_command(command) {
+                    break;
+                }
             }
+
+            scanner.tracker.close();
         });
 
         handles.push(handle);
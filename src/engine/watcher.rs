@@ -0,0 +1,340 @@
+pub mod constants {
+    include!(concat!(env!("OUT_DIR"), "/watcher_constants.rs"));
+}
+
+use std::{
+    collections::BTreeSet,
+    env,
+    path::{Path, PathBuf},
+    str::FromStr,
+    thread::{self, JoinHandle},
+    time::{Duration, Instant},
+};
+
+use crossbeam::channel::{after, never, select};
+use notify::{EventKind, RecommendedWatcher, RecursiveMode, Watcher as _};
+use once_cell::sync::Lazy;
+use tracing::{error, info};
+
+use crate::{
+    engine::{
+        Receiver, Sender,
+        extractor::formats::FormatType,
+        jobs::ScanJobs,
+        lanes::Priority,
+        scanner::{ScannerChannelTx, commands::ScannerCommand},
+        unbounded_channel,
+    },
+    metrics::metrics,
+    storage::{StorageChannelTx, commands::StorageCommand},
+};
+
+const LOG_TARGET: &str = "watcher";
+
+/// Quiet time after the last event before pending changes are sent.
+static WATCH_DEBOUNCE: Lazy<Duration> = Lazy::new(|| {
+    env::var("WATCH_DEBOUNCE")
+        .ok()
+        .and_then(|s| s.parse::<u64>().ok())
+        .map(Duration::from_millis)
+        .unwrap_or(Duration::from_millis(constants::DEFAULT_WATCH_DEBOUNCE_MS))
+});
+
+/// Longest a change waits while events keep coming.
+static WATCH_MAX_DELAY: Lazy<Duration> = Lazy::new(|| {
+    env::var("WATCH_MAX_DELAY")
+        .ok()
+        .and_then(|s| s.parse::<u64>().ok())
+        .map(Duration::from_millis)
+        .unwrap_or(Duration::from_millis(constants::DEFAULT_WATCH_MAX_DELAY_MS))
+});
+
+#[derive(Debug)]
+pub enum WatcherError {
+    NotifyError(notify::Error),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum WatcherCommand {
+    /// Watch a folder and everything under it.
+    Watch(String),
+    /// Send the pending changes and stop watching.
+    Shutdown,
+}
+
+pub type WatcherChannelTx = Sender<WatcherCommand>;
+pub type WatcherChannelRx = Receiver<WatcherCommand>;
+
+/// Paths touched since the last flush. Whether each was changed or removed
+/// is decided when they are sent, so a file written then deleted, or an
+/// editor's save through a temporary file, ends up as a single change.
+#[derive(Debug, Default)]
+struct PendingChanges {
+    paths: BTreeSet<PathBuf>,
+    first_at: Option<Instant>,
+    last_at: Option<Instant>,
+}
+
+impl PendingChanges {
+    fn add(&mut self, paths: Vec<PathBuf>, now: Instant) {
+        self.first_at.get_or_insert(now);
+        self.last_at = Some(now);
+        self.paths.extend(paths);
+    }
+
+    /// When the pending changes are due, `None` while there are none.
+    fn due_at(&self) -> Option<Instant> {
+        let (first_at, last_at) = (self.first_at?, self.last_at?);
+
+        Some((last_at + *WATCH_DEBOUNCE).min(first_at + *WATCH_MAX_DELAY))
+    }
+
+    fn take(&mut self) -> BTreeSet<PathBuf> {
+        self.first_at = None;
+        self.last_at = None;
+        std::mem::take(&mut self.paths)
+    }
+}
+
+/// Keeps the index of watched folders up to date: created and modified
+/// files are sent to the scanner as high priority changes, removed ones are
+/// marked deleted in storage.
+#[derive(Debug, Clone)]
+pub struct Watcher {
+    channel_tx: WatcherChannelTx,
+    channel_rx: WatcherChannelRx,
+    channel_scanner_tx: ScannerChannelTx,
+    channel_storage_tx: StorageChannelTx,
+    /// Registry of the `Api`, which lists the rescans with the other jobs.
+    jobs: ScanJobs,
+}
+
+impl Watcher {
+    pub fn new(
+        channel_tx: WatcherChannelTx,
+        channel_rx: WatcherChannelRx,
+        channel_scanner_tx: ScannerChannelTx,
+        channel_storage_tx: StorageChannelTx,
+        jobs: ScanJobs,
+    ) -> Self {
+        Watcher {
+            channel_tx,
+            channel_rx,
+            channel_scanner_tx,
+            channel_storage_tx,
+            jobs,
+        }
+    }
+
+    pub fn get_channel_tx(&self) -> &WatcherChannelTx {
+        &self.channel_tx
+    }
+
+    fn watch(roots: &mut Vec<PathBuf>, notifier: &mut RecommendedWatcher, path: &str) {
+        let path = PathBuf::from(path);
+        if roots.iter().any(|root| path.starts_with(root)) {
+            return;
+        }
+
+        if let Err(e) = notifier.watch(&path, RecursiveMode::Recursive) {
+            error!(target: LOG_TARGET, "Failed to watch {:?}: {:?}", path, e);
+            return;
+        }
+
+        // Folders under the new root are covered by it.
+        roots.retain(|root| {
+            if root.starts_with(&path) {
+                let _ = notifier.unwatch(root);
+                return false;
+            }
+            true
+        });
+
+        info!(target: LOG_TARGET, "Watching {:?}", path);
+        roots.push(path);
+    }
+
+    /// Files of an unknown format are dropped here: the extractor would skip
+    /// them, and they include the database and log files this process writes.
+    fn is_indexable(path: &Path) -> bool {
+        let extension = path.extension().and_then(|ext| ext.to_str()).unwrap_or("");
+
+        FormatType::from_str(extension).unwrap_or(FormatType::Unknown) != FormatType::Unknown
+    }
+
+    fn flush(&self, paths: BTreeSet<PathBuf>) {
+        let mut changed = Vec::new();
+        let mut removed = Vec::new();
+        // Paths sort right after the folder they are in, which is walked or
+        // marked as a whole.
+        let mut covered: Option<PathBuf> = None;
+
+        for path in paths {
+            if covered
+                .as_ref()
+                .is_some_and(|folder| path.starts_with(folder))
+            {
+                continue;
+            }
+
+            match path.metadata() {
+                Ok(metadata) if metadata.is_dir() => {
+                    changed.push(path.to_string_lossy().to_string());
+                    covered = Some(path);
+                }
+                Ok(_) => {
+                    if Self::is_indexable(&path) {
+                        changed.push(path.to_string_lossy().to_string());
+                    }
+                }
+                Err(_) => {
+                    removed.push(path.to_string_lossy().to_string());
+                    covered = Some(path);
+                }
+            }
+        }
+
+        if changed.is_empty() && removed.is_empty() {
+            return;
+        }
+
+        info!(target: LOG_TARGET, "Sending {} changed and {} removed paths", changed.len(), removed.len());
+
+        let metrics = metrics();
+        metrics.get_watch_changed().add(changed.len() as u64);
+        metrics.get_watch_removed().add(removed.len() as u64);
+
+        if !removed.is_empty() {
+            let (resp_tx, resp_rx) = unbounded_channel();
+            if let Err(e) = self.channel_storage_tx.send(StorageCommand::MarkDeleted {
+                paths: removed,
+                resp_tx,
+            }) {
+                error!(target: LOG_TARGET, "Failed to send removed paths to storage: {:?}", e);
+            } else if let Ok(Err(e)) = resp_rx.recv() {
+                error!(target: LOG_TARGET, "Failed to mark removed documents deleted: {:?}", e);
+            }
+        }
+
+        if !changed.is_empty() {
+            let job = self.jobs.create("watch".to_string(), Priority::High);
+            if let Err(e) = self.channel_scanner_tx.send(ScannerCommand::Changes {
+                job,
+                paths: changed,
+            }) {
+                error!(target: LOG_TARGET, "Failed to send changed paths to scanner: {:?}", e);
+            }
+        }
+    }
+
+    pub fn init(&mut self) -> Result<Vec<JoinHandle<()>>, WatcherError> {
+        let (events_tx, events_rx) = unbounded_channel::<notify::Result<notify::Event>>();
+        let mut notifier = notify::recommended_watcher(move |event| {
+            let _ = events_tx.send(event);
+        })
+        .map_err(WatcherError::NotifyError)?;
+
+        info!(target: LOG_TARGET, "Watcher is running");
+
+        let watcher = self.clone();
+
+        let handle = thread::spawn(move || {
+            let mut roots = Vec::new();
+            let mut pending = PendingChanges::default();
+
+            loop {
+                let timer = match pending.due_at() {
+                    Some(due_at) => after(due_at.saturating_duration_since(Instant::now())),
+                    None => never(),
+                };
+
+                select! {
+                    recv(watcher.channel_rx) -> command => match command {
+                        Ok(WatcherCommand::Watch(path)) => {
+                            Self::watch(&mut roots, &mut notifier, &path);
+                        }
+                        Ok(WatcherCommand::Shutdown) | Err(_) => {
+                            info!(target: LOG_TARGET, "Watcher shutting down");
+                            watcher.flush(pending.take());
+                            break;
+                        }
+                    },
+                    recv(events_rx) -> event => match event {
+                        // Events were dropped, walk the roots again.
+                        Ok(Ok(event)) if event.need_rescan() => pending.add(roots.clone(), Instant::now()),
+                        Ok(Ok(event)) => match event.kind {
+                            // Reads, including the extractor's own.
+                            EventKind::Access(_) => {}
+                            _ => pending.add(event.paths, Instant::now()),
+                        },
+                        Ok(Err(e)) => error!(target: LOG_TARGET, "Watch error: {:?}", e),
+                        Err(_) => break,
+                    },
+                    recv(timer) -> _ => watcher.flush(pending.take()),
+                }
+            }
+        });
+
+        Ok(vec![handle])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn paths(paths: &[&str]) -> Vec<PathBuf> {
+        paths.iter().map(PathBuf::from).collect()
+    }
+
+    #[test]
+    fn nothing_is_due_without_changes() {
+        let pending = PendingChanges::default();
+        assert_eq!(pending.due_at(), None);
+    }
+
+    #[test]
+    fn each_event_pushes_the_flush_back() {
+        let start = Instant::now();
+        let mut pending = PendingChanges::default();
+
+        pending.add(paths(&["/data/a.txt"]), start);
+        assert_eq!(pending.due_at(), Some(start + *WATCH_DEBOUNCE));
+
+        let later = start + *WATCH_DEBOUNCE / 2;
+        pending.add(paths(&["/data/b.txt"]), later);
+        assert_eq!(
+            pending.due_at(),
+            Some((later + *WATCH_DEBOUNCE).min(start + *WATCH_MAX_DELAY))
+        );
+    }
+
+    #[test]
+    fn steady_events_flush_after_the_max_delay() {
+        let start = Instant::now();
+        let mut pending = PendingChanges::default();
+
+        pending.add(paths(&["/data/a.txt"]), start);
+        pending.add(
+            paths(&["/data/a.txt"]),
+            start + *WATCH_MAX_DELAY + *WATCH_DEBOUNCE,
+        );
+
+        assert_eq!(pending.due_at(), Some(start + *WATCH_MAX_DELAY));
+    }
+
+    #[test]
+    fn take_sends_each_path_once_and_resets() {
+        let start = Instant::now();
+        let mut pending = PendingChanges::default();
+        pending.add(paths(&["/data/b.txt", "/data/a.txt"]), start);
+        pending.add(paths(&["/data/a.txt"]), start);
+
+        assert_eq!(
+            pending.take().into_iter().collect::<Vec<_>>(),
+            paths(&["/data/a.txt", "/data/b.txt"])
+        );
+        assert_eq!(pending.due_at(), None);
+        assert!(pending.take().is_empty());
+    }
+}
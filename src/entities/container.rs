@@ -1,6 +1,11 @@
 use std::{collections::HashMap, path, str::FromStr};
 
-use crate::{engine::scanner::ScannedDocument, entities::document::Document};
+use rusqlite::types::Value;
+
+use crate::{
+    engine::scanner::ScannedDocument,
+    entities::{document::Document, path_and_prefix},
+};
 
 #[derive(Debug)]
 pub enum ContainerError {
@@ -39,6 +44,8 @@ pub struct Container {
     id: i64,
     path: String,
     r#type: ContainerType,
+    size: Option<i64>,
+    modified: Option<i64>,
 }
 
 impl Container {
@@ -47,9 +54,13 @@ impl Container {
             id,
             path,
             r#type: container_type,
+            size: None,
+            modified: None,
         }
     }
 
+    /// Keeps the document's size and modification time, so an archive that
+    /// did not change is not extracted again.
     pub fn from_document(document: &Document, container_type: ContainerType) -> Self {
         let path = document.get_path();
 
@@ -57,6 +68,8 @@ impl Container {
             id: 0,
             path: path.into(),
             r#type: container_type,
+            size: document.get_size(),
+            modified: document.get_modified(),
         }
     }
 
@@ -68,6 +81,67 @@ impl Container {
         &self.path
     }
 
+    pub fn get_type(&self) -> &ContainerType {
+        &self.r#type
+    }
+
+    /// Size of the archive file, `None` for folders.
+    pub fn get_size(&self) -> Option<i64> {
+        self.size
+    }
+
+    /// Unix seconds, `None` for folders.
+    pub fn get_modified(&self) -> Option<i64> {
+        self.modified
+    }
+
+    pub fn set_stamp(&mut self, size: Option<i64>, modified: Option<i64>) {
+        self.size = size;
+        self.modified = modified;
+    }
+
+    /// Records the size and modification time of a stored archive, after
+    /// which scans skip it while they do not change.
+    pub fn stamp(
+        conn: &rusqlite::Connection,
+        id: i64,
+        size: Option<i64>,
+        modified: Option<i64>,
+    ) -> Result<(), ContainerError> {
+        conn.execute(
+            "UPDATE containers SET size = ?2, modified = ?3 WHERE id = ?1",
+            rusqlite::params![id, size, modified],
+        )
+        .map_err(ContainerError::DatabaseError)?;
+
+        Ok(())
+    }
+
+    pub fn list(conn: &rusqlite::Connection) -> Result<Vec<Container>, ContainerError> {
+        let mut stmt = conn
+            .prepare("SELECT id, path, type, size, modified FROM containers ORDER BY path")
+            .map_err(ContainerError::DatabaseError)?;
+
+        let rows = stmt
+            .query_map([], |row| {
+                let container_type_str: String = row.get(2)?;
+                let container_type =
+                    ContainerType::from_str(&container_type_str).expect("Invalid container type");
+
+                Ok(Container {
+                    id: row.get(0)?,
+                    path: row.get(1)?,
+                    r#type: container_type,
+                    size: row.get(3)?,
+                    modified: row.get(4)?,
+                })
+            })
+            .map_err(ContainerError::DatabaseError)?;
+
+        rows.collect::<Result<Vec<_>, _>>()
+            .map_err(ContainerError::DatabaseError)
+    }
+
     pub fn update_cache_from_documents(
         conn: &mut rusqlite::Connection,
         documents: &[ScannedDocument],
@@ -106,7 +180,7 @@ impl Container {
                 "INSERT INTO containers (path, type)
          VALUES (?1, ?2)
          ON CONFLICT(path) DO NOTHING
-         RETURNING id, path, type",
+         RETURNING id, path, type, size, modified",
             )
             .map_err(ContainerError::DatabaseError)?;
 
@@ -121,13 +195,15 @@ impl Container {
                 id: row.get(0)?,
                 path: row.get(1)?,
                 r#type: container_type,
+                size: row.get(3)?,
+                modified: row.get(4)?,
             })
         }) {
             return Ok(container);
         }
 
         conn.query_row(
-            "SELECT id, path, type FROM containers WHERE path = ?1",
+            "SELECT id, path, type, size, modified FROM containers WHERE path = ?1",
             [path],
             |row| {
                 let container_type_str: String = row.get(2)?;
@@ -138,24 +214,65 @@ impl Container {
                     id: row.get(0)?,
                     path: row.get(1)?,
                     r#type: container_type,
+                    size: row.get(3)?,
+                    modified: row.get(4)?,
                 })
             },
         )
         .map_err(ContainerError::DatabaseError)
     }
 
+    /// Deletes the containers without documents at or under `root`, or in
+    /// the whole index without one, leaving out the `pending` archives whose
+    /// documents are still being stored.
+    pub fn delete_empty(
+        conn: &rusqlite::Connection,
+        root: Option<&str>,
+        pending: &[i64],
+    ) -> Result<usize, ContainerError> {
+        let mut params: Vec<Value> = pending.iter().map(|id| Value::Integer(*id)).collect();
+        let predicate = match root {
+            Some(root) => {
+                let (root, prefix) = path_and_prefix(root);
+                params.push(Value::Text(root.to_string()));
+                params.push(Value::Text(prefix.clone()));
+                params.push(Value::Text(prefix));
+
+                "AND (path = ? OR substr(path, 1, length(?)) = ?)"
+            }
+            None => "",
+        };
+
+        conn.execute(
+            &format!(
+                "DELETE FROM containers
+                WHERE id NOT IN (SELECT DISTINCT container_id FROM documents)
+                    AND id NOT IN ({}) {}",
+                vec!["?"; pending.len()].join(", "),
+                predicate
+            ),
+            rusqlite::params_from_iter(params),
+        )
+        .map_err(ContainerError::DatabaseError)
+    }
+
     pub fn save(&mut self, conn: &mut rusqlite::Connection) -> Result<(), ContainerError> {
         let mut stmt = conn
             .prepare(
-                "INSERT INTO containers (path, type)
-         VALUES (?1, ?2)
-         ON CONFLICT(path) DO NOTHING
+                "INSERT INTO containers (path, type, size, modified)
+         VALUES (?1, ?2, ?3, ?4)
+         ON CONFLICT(path) DO UPDATE SET size = excluded.size, modified = excluded.modified
          RETURNING id",
             )
             .map_err(ContainerError::DatabaseError)?;
 
         let mut rows = stmt
-            .query(rusqlite::params![self.path, self.r#type.to_str()])
+            .query(rusqlite::params![
+                self.path,
+                self.r#type.to_str(),
+                self.size,
+                self.modified
+            ])
             .map_err(ContainerError::DatabaseError)?;
 
         if let Some(row) = rows.next().map_err(ContainerError::DatabaseError)? {
@@ -176,3 +293,88 @@ impl Container {
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use super::*;
+    use crate::{entities::document::DocumentStatus, storage::StorageEngine};
+
+    fn container(
+        conn: &mut rusqlite::Connection,
+        path: &str,
+        container_type: ContainerType,
+    ) -> i64 {
+        let mut container = Container::new(0, path.to_string(), container_type);
+        container.save(conn).expect("Failed to save container");
+        container.get_id()
+    }
+
+    fn paths(conn: &rusqlite::Connection) -> Vec<String> {
+        let mut paths: Vec<String> = Container::list(conn)
+            .expect("Failed to list containers")
+            .iter()
+            .map(|container| container.get_path().to_string())
+            .collect();
+        paths.sort();
+        paths
+    }
+
+    fn indexed(conn: &mut rusqlite::Connection) -> i64 {
+        let data = container(conn, "/data", ContainerType::Folder);
+        container(conn, "/data/empty", ContainerType::Folder);
+        let archive = container(conn, "/data/z.zip", ContainerType::Archive);
+        container(conn, "/database", ContainerType::Folder);
+
+        let mut document = Document::from_path(Path::new("/data/a.txt"));
+        document.set_container_id(data);
+        document.set_status(DocumentStatus::Extracted);
+        Document::save_bulk(
+            conn,
+            vec![ScannedDocument {
+                container_type: ContainerType::Folder,
+                document,
+                job: None,
+                failure: None,
+            }],
+            &mut HashMap::new(),
+        )
+        .expect("Failed to save document");
+
+        archive
+    }
+
+    #[test]
+    fn deletes_empty_containers_under_the_root_only() {
+        let mut conn = StorageEngine::open_in_memory();
+        indexed(&mut conn);
+
+        assert_eq!(
+            Container::delete_empty(&conn, Some("/data/"), &[]).unwrap(),
+            2
+        );
+        assert_eq!(paths(&conn), vec!["/data", "/database"]);
+
+        assert_eq!(Container::delete_empty(&conn, None, &[]).unwrap(), 1);
+        assert_eq!(paths(&conn), vec!["/data"]);
+    }
+
+    #[test]
+    fn keeps_archives_still_being_stored() {
+        let mut conn = StorageEngine::open_in_memory();
+        let archive = indexed(&mut conn);
+
+        assert_eq!(Container::delete_empty(&conn, None, &[archive]).unwrap(), 2);
+        assert_eq!(paths(&conn), vec!["/data", "/data/z.zip"]);
+    }
+
+    #[test]
+    fn the_root_covers_every_container() {
+        let mut conn = StorageEngine::open_in_memory();
+        indexed(&mut conn);
+
+        assert_eq!(Container::delete_empty(&conn, Some("/"), &[]).unwrap(), 3);
+        assert_eq!(paths(&conn), vec!["/data"]);
+    }
+}
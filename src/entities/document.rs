@@ -1,10 +1,24 @@
-use std::{collections::HashMap, fmt::Display, path::Path, str::FromStr};
+use std::{
+    collections::HashMap,
+    fmt::Display,
+    path::{MAIN_SEPARATOR, Path},
+    str::FromStr,
+};
 
-use tracing::info;
+use rusqlite::OptionalExtension;
+use tracing::{error, info};
 
 use crate::{
-    engine::{extractor::formats::FormatType, scanner::ScannedDocument},
-    entities::container::Container,
+    engine::{
+        extractor::formats::{ContentMode, FormatType},
+        scanner::ScannedDocument,
+    },
+    entities::{
+        container::Container,
+        failure::{ExtractionFailure, FailureError},
+        path_and_prefix,
+    },
+    storage::STORAGE_TRIGRAM_INDEX,
 };
 
 const LOG_TARGET: &str = "document";
@@ -23,6 +37,84 @@ pub enum DocumentStatus {
     Extracted,
     Classified,
     Deleted,
+    /// Extraction failed, see the `extraction_errors` table.
+    Failed,
+}
+
+impl FromStr for DocumentStatus {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "New" => Ok(DocumentStatus::New),
+            "Scanned" => Ok(DocumentStatus::Scanned),
+            "Extracted" => Ok(DocumentStatus::Extracted),
+            "Classified" => Ok(DocumentStatus::Classified),
+            "Deleted" => Ok(DocumentStatus::Deleted),
+            "Failed" => Ok(DocumentStatus::Failed),
+            _ => Err(()),
+        }
+    }
+}
+
+impl DocumentStatus {
+    pub fn to_str(&self) -> &str {
+        match self {
+            DocumentStatus::New => "New",
+            DocumentStatus::Scanned => "Scanned",
+            DocumentStatus::Extracted => "Extracted",
+            DocumentStatus::Classified => "Classified",
+            DocumentStatus::Deleted => "Deleted",
+            DocumentStatus::Failed => "Failed",
+        }
+    }
+}
+
+/// What saving a document did to the index.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum SaveOutcome {
+    Inserted,
+    /// The row was updated in place and its index rows replaced.
+    Updated,
+    /// Already stored with the same size, modification time, status, hash
+    /// and content.
+    Unchanged,
+    /// The row could not be written; the rest of the batch still was.
+    Failed,
+}
+
+impl SaveOutcome {
+    pub fn to_str(&self) -> &str {
+        match self {
+            SaveOutcome::Inserted => "Inserted",
+            SaveOutcome::Updated => "Updated",
+            SaveOutcome::Unchanged => "Unchanged",
+            SaveOutcome::Failed => "Failed",
+        }
+    }
+}
+
+/// One document of a `Document::save_bulk` batch.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SavedDocument {
+    id: Option<i64>,
+    path: String,
+    outcome: SaveOutcome,
+}
+
+impl SavedDocument {
+    /// `None` when the row failed, see `SaveOutcome::Failed`.
+    pub fn get_id(&self) -> Option<i64> {
+        self.id
+    }
+
+    pub fn get_path(&self) -> &str {
+        &self.path
+    }
+
+    pub fn get_outcome(&self) -> SaveOutcome {
+        self.outcome
+    }
 }
 
 #[derive(Debug, Clone, PartialEq, Eq, Hash)]
@@ -35,14 +127,29 @@ pub struct Document {
     description: String,
     status: DocumentStatus,
     container_id: Option<i64>,
+    size: Option<i64>,
+    modified: Option<i64>,
+    hash: Option<String>,
+    truncated: bool,
+    content_mode: Option<ContentMode>,
 }
 
 impl Display for Document {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         write!(
             f,
-            "Document {{ id: {:?}, path: {}, filename: {}, extension: {:?}, status: {:?}, container_id: {:?} }}",
-            self.id, self.path, self.filename, self.extension, self.status, self.container_id
+            "Document {{ id: {:?}, path: {}, filename: {}, extension: {:?}, status: {:?}, container_id: {:?}, size: {:?}, modified: {:?}, hash: {:?}, truncated: {}, content_mode: {:?} }}",
+            self.id,
+            self.path,
+            self.filename,
+            self.extension,
+            self.status,
+            self.container_id,
+            self.size,
+            self.modified,
+            self.hash,
+            self.truncated,
+            self.content_mode
         )
     }
 }
@@ -58,6 +165,11 @@ impl Default for Document {
             description: String::new(),
             status: DocumentStatus::New,
             container_id: None,
+            size: None,
+            modified: None,
+            hash: None,
+            truncated: false,
+            content_mode: None,
         }
     }
 }
@@ -91,6 +203,11 @@ impl Document {
             description: String::new(),
             status: DocumentStatus::New,
             container_id: None,
+            size: None,
+            modified: None,
+            hash: None,
+            truncated: false,
+            content_mode: None,
         }
     }
 
@@ -150,29 +267,71 @@ impl Document {
         self.container_id
     }
 
-    pub fn save(&mut self, conn: &rusqlite::Connection) -> Result<(), DocumentError> {
-        conn.execute(
-            "INSERT INTO documents (path, filename, extension, status, container_id) VALUES (?1, ?2, ?3, ?4, ?5)",
-            rusqlite::params![self.path, self.filename, self.extension, self.get_status_str(), self.container_id],
-        )
-        .map_err(|err| {
-            if let rusqlite::Error::SqliteFailure(ref err_code, _) = err && err_code.code == rusqlite::ErrorCode::ConstraintViolation {
-                return DocumentError::ConstraintViolation;
-            }
-            DocumentError::DatabaseError(err)
-        })?;
+    pub fn set_size(&mut self, size: Option<i64>) {
+        self.size = size;
+    }
 
-        let document_id = conn.last_insert_rowid();
+    /// File size in bytes (uncompressed size for archive members).
+    pub fn get_size(&self) -> Option<i64> {
+        self.size
+    }
 
-        conn.execute(
-            "INSERT INTO index_documents (document_id, content, description) VALUES (?1, ?2, ?3)",
-            rusqlite::params![document_id, self.content, self.description],
-        )
-        .map_err(DocumentError::DatabaseError)?;
+    pub fn set_modified(&mut self, modified: Option<i64>) {
+        self.modified = modified;
+    }
 
-        self.set_id(document_id);
+    /// Last modification time as a unix timestamp in seconds.
+    pub fn get_modified(&self) -> Option<i64> {
+        self.modified
+    }
 
-        Ok(())
+    pub fn set_hash(&mut self, hash: Option<String>) {
+        self.hash = hash;
+    }
+
+    /// SHA-256 of the file's bytes (of the entry's for archive members), in
+    /// hex.
+    pub fn get_hash(&self) -> Option<&str> {
+        self.hash.as_deref()
+    }
+
+    pub fn set_truncated(&mut self, truncated: bool) {
+        self.truncated = truncated;
+    }
+
+    /// Whether only the start of the text was indexed, see
+    /// `EXTRACTOR_MAX_CONTENT_BYTES`.
+    pub fn is_truncated(&self) -> bool {
+        self.truncated
+    }
+
+    pub fn set_content_mode(&mut self, content_mode: ContentMode) {
+        self.content_mode = Some(content_mode);
+    }
+
+    /// How the content was extracted, see `EXTRACTOR_CONTENT_MODE`. `None`
+    /// for documents without content, and for rows stored before the mode
+    /// was recorded.
+    pub fn get_content_mode(&self) -> Option<ContentMode> {
+        self.content_mode
+    }
+
+    pub fn set_metadata(&mut self, metadata: &std::fs::Metadata) {
+        self.size = Some(metadata.len() as i64);
+        self.modified = metadata
+            .modified()
+            .ok()
+            .and_then(|time| time.duration_since(std::time::UNIX_EPOCH).ok())
+            .map(|duration| duration.as_secs() as i64);
+    }
+
+    /// Inserts the document, or updates it when a document of the same name
+    /// is already stored in its container.
+    pub fn save(&mut self, conn: &rusqlite::Connection) -> Result<SaveOutcome, DocumentError> {
+        let container_id = self.container_id;
+        let (_, outcome) = self.upsert(conn, container_id, None)?;
+
+        Ok(outcome)
     }
 
     pub fn get_id_by_path(&self, conn: &rusqlite::Connection) -> Result<i64, DocumentError> {
@@ -193,6 +352,350 @@ impl Document {
         Ok(document_id)
     }
 
+    pub fn get_by_id(conn: &rusqlite::Connection, id: i64) -> Result<Document, DocumentError> {
+        conn.query_row(
+            "SELECT id, path, filename, extension, status, container_id, size, modified, hash, truncated, content_mode FROM documents_view WHERE id = ?1",
+            rusqlite::params![id],
+            |row| {
+                let status_str: String = row.get(4)?;
+                let status = DocumentStatus::from_str(&status_str).expect("Invalid document status");
+
+                Ok(Document {
+                    id: Some(row.get(0)?),
+                    path: row.get(1)?,
+                    filename: row.get(2)?,
+                    extension: row.get(3)?,
+                    content: String::new(),
+                    description: String::new(),
+                    status,
+                    container_id: Some(row.get(5)?),
+                    size: row.get(6)?,
+                    modified: row.get(7)?,
+                    hash: row.get(8)?,
+                    truncated: row.get(9)?,
+                    content_mode: row
+                        .get::<_, Option<String>>(10)?
+                        .and_then(|mode| ContentMode::from_str(&mode).ok()),
+                })
+            },
+        )
+        .map_err(|err| {
+            if err == rusqlite::Error::QueryReturnedNoRows {
+                DocumentError::NotFound
+            } else {
+                DocumentError::DatabaseError(err)
+            }
+        })
+    }
+
+    pub fn delete(conn: &mut rusqlite::Connection, id: i64) -> Result<(), DocumentError> {
+        let tx = conn.transaction().map_err(DocumentError::DatabaseError)?;
+
+        Self::delete_index(&tx, "?1", rusqlite::params![id])?;
+
+        let deleted = tx
+            .execute("DELETE FROM documents WHERE id = ?1", rusqlite::params![id])
+            .map_err(DocumentError::DatabaseError)?;
+
+        if deleted == 0 {
+            return Err(DocumentError::NotFound);
+        }
+
+        tx.commit().map_err(DocumentError::DatabaseError)?;
+
+        info!(target: LOG_TARGET, "Deleted document: {}", id);
+
+        Ok(())
+    }
+
+    pub fn purge_under_path(
+        conn: &mut rusqlite::Connection,
+        path: &str,
+    ) -> Result<usize, DocumentError> {
+        let (path, prefix) = path_and_prefix(path);
+        let tx = conn.transaction().map_err(DocumentError::DatabaseError)?;
+
+        Self::delete_index(
+            &tx,
+            "SELECT d.id FROM documents d
+                INNER JOIN containers c ON c.id = d.container_id
+                WHERE c.path = ?1 OR substr(c.path, 1, length(?2)) = ?2",
+            rusqlite::params![path, prefix],
+        )?;
+
+        let deleted = tx
+            .execute(
+                "DELETE FROM documents WHERE container_id IN (
+                    SELECT id FROM containers
+                    WHERE path = ?1 OR substr(path, 1, length(?2)) = ?2
+                )",
+                rusqlite::params![path, prefix],
+            )
+            .map_err(DocumentError::DatabaseError)?;
+
+        // Otherwise the next incremental scan would skip the archives.
+        tx.execute(
+            "UPDATE containers SET size = NULL, modified = NULL
+            WHERE path = ?1 OR substr(path, 1, length(?2)) = ?2",
+            rusqlite::params![path, prefix],
+        )
+        .map_err(DocumentError::DatabaseError)?;
+
+        tx.commit().map_err(DocumentError::DatabaseError)?;
+
+        info!(target: LOG_TARGET, "Purged {} documents under {}", deleted, path);
+
+        Ok(deleted)
+    }
+
+    /// Removes the documents of a container, e.g. the members of an archive
+    /// that is extracted again.
+    pub fn purge_container(
+        conn: &mut rusqlite::Connection,
+        container_id: i64,
+    ) -> Result<usize, DocumentError> {
+        let tx = conn.transaction().map_err(DocumentError::DatabaseError)?;
+
+        Self::delete_index(
+            &tx,
+            "SELECT id FROM documents WHERE container_id = ?1",
+            rusqlite::params![container_id],
+        )?;
+
+        let deleted = tx
+            .execute(
+                "DELETE FROM documents WHERE container_id = ?1",
+                rusqlite::params![container_id],
+            )
+            .map_err(DocumentError::DatabaseError)?;
+
+        tx.commit().map_err(DocumentError::DatabaseError)?;
+
+        if deleted > 0 {
+            info!(target: LOG_TARGET, "Purged {} documents of container {}", deleted, container_id);
+        }
+
+        Ok(deleted)
+    }
+
+    /// Marks the documents at or under each path `DocumentStatus::Deleted`:
+    /// the document of a file, the members of an archive and everything
+    /// under a folder. They leave the search indexes but keep their rows
+    /// until `purge_deleted`.
+    pub fn mark_deleted_under(
+        conn: &mut rusqlite::Connection,
+        paths: &[String],
+    ) -> Result<usize, DocumentError> {
+        let tx = conn.transaction().map_err(DocumentError::DatabaseError)?;
+        let ids = format!(
+            "SELECT d.id FROM documents d
+                INNER JOIN containers c ON c.id = d.container_id
+                WHERE d.status != ?3 AND (
+                    c.path || '{}' || d.filename = ?1
+                    OR c.path = ?1
+                    OR substr(c.path, 1, length(?2)) = ?2
+                )",
+            MAIN_SEPARATOR
+        );
+
+        let mut marked = 0;
+        for path in paths {
+            let (path, prefix) = path_and_prefix(path);
+            let params = rusqlite::params![path, prefix, DocumentStatus::Deleted.to_str()];
+
+            Self::delete_index(&tx, &ids, params)?;
+
+            marked += tx
+                .execute(
+                    &format!("UPDATE documents SET status = ?3 WHERE id IN ({})", ids),
+                    params,
+                )
+                .map_err(DocumentError::DatabaseError)?;
+
+            // An archive that comes back is extracted again.
+            tx.execute(
+                "UPDATE containers SET size = NULL, modified = NULL
+                WHERE path = ?1 OR substr(path, 1, length(?2)) = ?2",
+                rusqlite::params![path, prefix],
+            )
+            .map_err(DocumentError::DatabaseError)?;
+        }
+
+        tx.commit().map_err(DocumentError::DatabaseError)?;
+
+        info!(target: LOG_TARGET, "Marked {} documents deleted under {} paths", marked, paths.len());
+
+        Ok(marked)
+    }
+
+    /// Marks these documents `DocumentStatus::Deleted`, like
+    /// `mark_deleted_under`, in one pass over the indexes.
+    pub fn mark_deleted(
+        conn: &mut rusqlite::Connection,
+        ids: &[i64],
+    ) -> Result<usize, DocumentError> {
+        if ids.is_empty() {
+            return Ok(0);
+        }
+
+        let tx = conn.transaction().map_err(DocumentError::DatabaseError)?;
+
+        tx.execute(
+            "CREATE TEMP TABLE IF NOT EXISTS deleted_documents (id INTEGER PRIMARY KEY)",
+            [],
+        )
+        .map_err(DocumentError::DatabaseError)?;
+        tx.execute("DELETE FROM temp.deleted_documents", [])
+            .map_err(DocumentError::DatabaseError)?;
+
+        {
+            let mut stmt = tx
+                .prepare("INSERT OR IGNORE INTO temp.deleted_documents (id) VALUES (?1)")
+                .map_err(DocumentError::DatabaseError)?;
+            for id in ids {
+                stmt.execute([id]).map_err(DocumentError::DatabaseError)?;
+            }
+        }
+
+        Self::delete_index(&tx, "SELECT id FROM temp.deleted_documents", &[])?;
+
+        let marked = tx
+            .execute(
+                "UPDATE documents SET status = ?1
+                WHERE id IN (SELECT id FROM temp.deleted_documents)",
+                rusqlite::params![DocumentStatus::Deleted.to_str()],
+            )
+            .map_err(DocumentError::DatabaseError)?;
+
+        tx.execute("DELETE FROM temp.deleted_documents", [])
+            .map_err(DocumentError::DatabaseError)?;
+
+        tx.commit().map_err(DocumentError::DatabaseError)?;
+
+        info!(target: LOG_TARGET, "Marked {} documents deleted", marked);
+
+        Ok(marked)
+    }
+
+    /// Removes the documents marked `DocumentStatus::Deleted` whose container
+    /// is at or under `root`, or in the whole index without one.
+    pub fn purge_deleted(
+        conn: &mut rusqlite::Connection,
+        root: Option<&str>,
+    ) -> Result<usize, DocumentError> {
+        let (root, prefix) = match root {
+            Some(root) => {
+                let (root, prefix) = path_and_prefix(root);
+                (Some(root), Some(prefix))
+            }
+            None => (None, None),
+        };
+        let params = rusqlite::params![DocumentStatus::Deleted.to_str(), root, prefix];
+        let ids = "SELECT id FROM documents WHERE status = ?1 AND (?2 IS NULL OR container_id IN (
+                SELECT id FROM containers
+                WHERE path = ?2 OR substr(path, 1, length(?3)) = ?3
+            ))";
+
+        let tx = conn.transaction().map_err(DocumentError::DatabaseError)?;
+
+        Self::delete_index(&tx, ids, params)?;
+
+        let deleted = tx
+            .execute(
+                &format!("DELETE FROM documents WHERE id IN ({})", ids),
+                params,
+            )
+            .map_err(DocumentError::DatabaseError)?;
+
+        tx.commit().map_err(DocumentError::DatabaseError)?;
+
+        info!(target: LOG_TARGET, "Purged {} deleted documents under {}", deleted, root.unwrap_or("index"));
+
+        Ok(deleted)
+    }
+
+    /// Adds the document to the full-text index, and to the trigram index when
+    /// it is enabled.
+    fn insert_index(
+        conn: &rusqlite::Connection,
+        document_id: i64,
+        content: &str,
+        description: &str,
+    ) -> Result<(), DocumentError> {
+        conn.execute(
+            "INSERT INTO index_documents (document_id, content, description) VALUES (?1, ?2, ?3)",
+            rusqlite::params![document_id, content, description],
+        )
+        .map_err(DocumentError::DatabaseError)?;
+
+        if *STORAGE_TRIGRAM_INDEX {
+            conn.execute(
+                "INSERT INTO index_documents_trigram (document_id, content, description) VALUES (?1, ?2, ?3)",
+                rusqlite::params![document_id, content, description],
+            )
+            .map_err(DocumentError::DatabaseError)?;
+        }
+
+        Ok(())
+    }
+
+    /// Adds the document's full path and file name to `index_paths`.
+    fn insert_path_index(
+        conn: &rusqlite::Connection,
+        document_id: i64,
+    ) -> Result<(), DocumentError> {
+        conn.execute(
+            "INSERT INTO index_paths (document_id, path, filename)
+            SELECT id, path, filename FROM documents_view WHERE id = ?1",
+            rusqlite::params![document_id],
+        )
+        .map_err(DocumentError::DatabaseError)?;
+
+        Ok(())
+    }
+
+    /// Removes index and extraction error rows whose document id is in `ids`,
+    /// a SQL expression or subquery bound to `params`.
+    fn delete_index(
+        conn: &rusqlite::Connection,
+        ids: &str,
+        params: &[&dyn rusqlite::ToSql],
+    ) -> Result<(), DocumentError> {
+        conn.execute(
+            &format!("DELETE FROM index_documents WHERE document_id IN ({})", ids),
+            params,
+        )
+        .map_err(DocumentError::DatabaseError)?;
+
+        conn.execute(
+            &format!("DELETE FROM index_paths WHERE document_id IN ({})", ids),
+            params,
+        )
+        .map_err(DocumentError::DatabaseError)?;
+
+        conn.execute(
+            &format!(
+                "DELETE FROM extraction_errors WHERE document_id IN ({})",
+                ids
+            ),
+            params,
+        )
+        .map_err(DocumentError::DatabaseError)?;
+
+        if *STORAGE_TRIGRAM_INDEX {
+            conn.execute(
+                &format!(
+                    "DELETE FROM index_documents_trigram WHERE document_id IN ({})",
+                    ids
+                ),
+                params,
+            )
+            .map_err(DocumentError::DatabaseError)?;
+        }
+
+        Ok(())
+    }
+
     pub fn _get_id(&self, conn: &rusqlite::Connection) -> Result<i64, DocumentError> {
         if let Some(id) = self.id {
             Ok(id)
@@ -201,19 +704,21 @@ impl Document {
         }
     }
 
+    /// Upserts the batch in one transaction, see `Document::save`. Returns
+    /// what happened to each document, in order.
     pub fn save_bulk(
         conn: &mut rusqlite::Connection,
         documents: Vec<ScannedDocument>,
         container_cache: &mut HashMap<String, Container>,
-    ) -> Result<(), DocumentError> {
-        let tx = conn.transaction().map_err(DocumentError::DatabaseError)?;
-        let count = documents.len();
+    ) -> Result<Vec<SavedDocument>, DocumentError> {
+        let mut tx = conn.transaction().map_err(DocumentError::DatabaseError)?;
+        let mut saved = Vec::with_capacity(documents.len());
 
         for mut scanned in documents {
             let document = &mut scanned.document;
 
-            let container_id = if document.container_id.is_some() {
-                document.container_id.unwrap()
+            let container_id = if let Some(container_id) = document.container_id {
+                container_id
             } else {
                 let container_path = Path::new(&document.path)
                     .parent()
@@ -223,40 +728,162 @@ impl Document {
                 container_cache.get(&container_path).unwrap().get_id()
             };
 
-            tx.execute(
-                "INSERT INTO documents (filename, extension, status, container_id) VALUES (?1, ?2, ?3, ?4)",
-                rusqlite::params![document.filename, document.extension, document.get_status_str(), container_id],
+            // A row that fails is rolled back alone, the batch goes on.
+            let savepoint = tx.savepoint().map_err(DocumentError::DatabaseError)?;
+            let (id, outcome) = match document.upsert(
+                &savepoint,
+                Some(container_id),
+                scanned.failure.as_deref(),
+            ) {
+                Ok((id, outcome)) => {
+                    savepoint.commit().map_err(DocumentError::DatabaseError)?;
+                    (Some(id), outcome)
+                }
+                Err(e) => {
+                    error!(target: LOG_TARGET, "Failed to save document {}: {:?}", document.path, e);
+                    savepoint.finish().map_err(DocumentError::DatabaseError)?;
+                    (None, SaveOutcome::Failed)
+                }
+            };
+
+            saved.push(SavedDocument {
+                id,
+                path: document.path.clone(),
+                outcome,
+            });
+        }
+
+        tx.commit().map_err(DocumentError::DatabaseError)?;
+
+        let count = |outcome| saved.iter().filter(|s| s.outcome == outcome).count();
+        info!(
+            target: LOG_TARGET,
+            "Saved {} documents: {} inserted, {} updated, {} unchanged, {} failed",
+            saved.len(),
+            count(SaveOutcome::Inserted),
+            count(SaveOutcome::Updated),
+            count(SaveOutcome::Unchanged),
+            count(SaveOutcome::Failed)
+        );
+
+        Ok(saved)
+    }
+
+    /// Writes the row and its index rows. An existing row with the same size,
+    /// modification time, status, hash, truncation and content mode is left
+    /// alone unless the document failed again; otherwise it is updated in place and its index rows are
+    /// replaced, a new failure adding to the attempts of the previous one.
+    fn upsert(
+        &mut self,
+        conn: &rusqlite::Connection,
+        container_id: Option<i64>,
+        failure: Option<&ExtractionFailure>,
+    ) -> Result<(i64, SaveOutcome), DocumentError> {
+        let existing = conn
+            .query_row(
+                "SELECT id, extension, status, size, modified, hash, truncated, content_mode FROM documents WHERE filename = ?1 AND container_id = ?2",
+                rusqlite::params![self.filename, container_id],
+                |row| {
+                    Ok((
+                        row.get::<_, i64>(0)?,
+                        (
+                            row.get::<_, Option<String>>(1)?,
+                            row.get::<_, String>(2)?,
+                            row.get::<_, Option<i64>>(3)?,
+                            row.get::<_, Option<i64>>(4)?,
+                            row.get::<_, Option<String>>(5)?,
+                            row.get::<_, bool>(6)?,
+                            row.get::<_, Option<String>>(7)?,
+                        ),
+                    ))
+                },
+            )
+            .optional()
+            .map_err(DocumentError::DatabaseError)?;
+
+        if let Some((id, stored)) = &existing
+            && failure.is_none()
+            && self.size.is_some()
+            && self.modified.is_some()
+            && *stored
+                == (
+                    self.extension.clone(),
+                    self.get_status_str().to_string(),
+                    self.size,
+                    self.modified,
+                    self.hash.clone(),
+                    self.truncated,
+                    self.content_mode.map(|mode| mode.to_str().to_string()),
+                )
+        {
+            info!(target: LOG_TARGET, "Unchanged document: {}", self.path);
+            self.set_id(*id);
+            return Ok((*id, SaveOutcome::Unchanged));
+        }
+
+        let document_id: i64 = conn
+            .query_row(
+                "INSERT INTO documents (filename, extension, status, container_id, size, modified, hash, truncated, content_mode) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9)
+                ON CONFLICT(filename, container_id) DO UPDATE SET
+                    extension = excluded.extension,
+                    status = excluded.status,
+                    size = excluded.size,
+                    modified = excluded.modified,
+                    hash = excluded.hash,
+                    truncated = excluded.truncated,
+                    content_mode = excluded.content_mode
+                RETURNING id",
+                rusqlite::params![self.filename, self.extension, self.get_status_str(), container_id, self.size, self.modified, self.hash, self.truncated, self.content_mode.map(|mode| mode.to_str().to_string())],
+                |row| row.get(0),
             )
             .map_err(|err| {
                 if let rusqlite::Error::SqliteFailure(ref err_code, _) = err
-                    && err_code.code == rusqlite::ErrorCode::ConstraintViolation{
+                    && err_code.code == rusqlite::ErrorCode::ConstraintViolation
+                {
                     return DocumentError::ConstraintViolation;
                 }
                 DocumentError::DatabaseError(err)
             })?;
 
-            info!(target: LOG_TARGET, "Saved document: {}", document.path);
+        let previous_attempts: u32 = match (&existing, failure) {
+            (Some(_), Some(_)) => conn
+                .query_row(
+                    "SELECT attempts FROM extraction_errors WHERE document_id = ?1",
+                    [document_id],
+                    |row| row.get(0),
+                )
+                .optional()
+                .map_err(DocumentError::DatabaseError)?
+                .unwrap_or(0),
+            _ => 0,
+        };
 
-            let document_id = tx.last_insert_rowid();
-            document.set_id(document_id);
+        let outcome = if existing.is_some() {
+            Self::delete_index(conn, "?1", rusqlite::params![document_id])?;
+            SaveOutcome::Updated
+        } else {
+            SaveOutcome::Inserted
+        };
 
-            if document.content.is_empty() && document.description.is_empty() {
-                continue;
-            }
+        info!(target: LOG_TARGET, "Saved document ({}): {}", outcome.to_str(), self.path);
 
-            tx.execute(
-                "INSERT INTO index_documents (document_id, content, description) VALUES (?1, ?2, ?3)",
-                rusqlite::params![document_id, document.content, document.description],
-            )
-            .map_err(DocumentError::DatabaseError)?;
-        }
+        self.set_id(document_id);
 
-        tx.commit().map_err(DocumentError::DatabaseError)?;
+        Self::insert_path_index(conn, document_id)?;
 
-        info!(target: LOG_TARGET, "All documents saved successfully.");
-        info!(target: LOG_TARGET, "Added documents: {}", count);
+        if let Some(failure) = failure {
+            let mut failure = failure.clone();
+            failure.set_attempts(previous_attempts + failure.get_attempts());
+            failure
+                .save(conn, document_id)
+                .map_err(|FailureError::DatabaseError(err)| DocumentError::DatabaseError(err))?;
+        }
 
-        Ok(())
+        if !self.content.is_empty() || !self.description.is_empty() {
+            Self::insert_index(conn, document_id, &self.content, &self.description)?;
+        }
+
+        Ok((document_id, outcome))
     }
 
     pub fn get_format_type(&self) -> FormatType {
@@ -268,16 +895,293 @@ impl Document {
     }
 
     pub fn get_status_str(&self) -> &str {
-        match self.status {
-            DocumentStatus::New => "New",
-            DocumentStatus::Scanned => "Scanned",
-            DocumentStatus::Extracted => "Extracted",
-            DocumentStatus::Classified => "Classified",
-            DocumentStatus::Deleted => "Deleted",
-        }
+        self.status.to_str()
     }
 
     pub fn set_status(&mut self, status: DocumentStatus) {
         self.status = status;
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        entities::{
+            container::ContainerType,
+            failure::{FailedDocument, FailureKind},
+        },
+        storage::StorageEngine,
+    };
+
+    fn folder(conn: &mut rusqlite::Connection, path: &str) -> i64 {
+        let mut container = Container::new(0, path.to_string(), ContainerType::Folder);
+        container.save(conn).expect("Failed to save folder");
+        container.get_id()
+    }
+
+    fn scanned(container_id: i64, path: &str) -> ScannedDocument {
+        let mut document = Document::from_path(Path::new(path));
+        document.set_container_id(container_id);
+        document.set_size(Some(10));
+        document.set_modified(Some(1_700_000_000));
+        document.set_status(DocumentStatus::Extracted);
+
+        ScannedDocument {
+            container_type: ContainerType::Folder,
+            document,
+            job: None,
+            failure: None,
+        }
+    }
+
+    fn failed(container_id: i64, path: &str, attempts: u32) -> ScannedDocument {
+        let mut failure = ExtractionFailure::new(FailureKind::Io, "read error".to_string(), "Test");
+        failure.set_attempts(attempts);
+
+        let mut scanned = scanned(container_id, path);
+        scanned.document.set_status(DocumentStatus::Failed);
+        scanned.failure = Some(Box::new(failure));
+        scanned
+    }
+
+    fn save(
+        conn: &mut rusqlite::Connection,
+        documents: Vec<ScannedDocument>,
+    ) -> Vec<SavedDocument> {
+        Document::save_bulk(conn, documents, &mut HashMap::new()).expect("Failed to save batch")
+    }
+
+    fn attempts(conn: &rusqlite::Connection) -> Vec<(String, u32)> {
+        FailedDocument::list(conn, None, 10, 0)
+            .expect("Failed to list failures")
+            .iter()
+            .map(|failed| {
+                (
+                    failed.get_path().to_string(),
+                    failed.get_failure().get_attempts(),
+                )
+            })
+            .collect()
+    }
+
+    fn statuses(conn: &rusqlite::Connection) -> Vec<(String, String)> {
+        let mut stmt = conn
+            .prepare("SELECT path, status FROM documents_view ORDER BY path")
+            .unwrap();
+        stmt.query_map([], |row| Ok((row.get(0)?, row.get(1)?)))
+            .unwrap()
+            .collect::<Result<_, _>>()
+            .unwrap()
+    }
+
+    fn deleted(conn: &rusqlite::Connection) -> Vec<String> {
+        statuses(conn)
+            .into_iter()
+            .filter(|(_, status)| status == DocumentStatus::Deleted.to_str())
+            .map(|(path, _)| path)
+            .collect()
+    }
+
+    fn indexed_folders(conn: &mut rusqlite::Connection) {
+        let data = folder(conn, "/data");
+        let sub = folder(conn, "/data/sub");
+        let other = folder(conn, "/database");
+        save(
+            conn,
+            vec![
+                scanned(data, "/data/a.txt"),
+                scanned(data, "/data/b.txt"),
+                scanned(sub, "/data/sub/c.txt"),
+                scanned(other, "/database/d.txt"),
+            ],
+        );
+    }
+
+    fn outcomes(saved: &[SavedDocument]) -> Vec<SaveOutcome> {
+        saved.iter().map(SavedDocument::get_outcome).collect()
+    }
+
+    #[test]
+    fn saving_the_same_document_again_leaves_it_unchanged() {
+        let mut conn = StorageEngine::open_in_memory();
+        let folder = folder(&mut conn, "/data");
+
+        let first = save(&mut conn, vec![scanned(folder, "/data/a.txt")]);
+        let second = save(&mut conn, vec![scanned(folder, "/data/a.txt")]);
+
+        assert_eq!(outcomes(&first), vec![SaveOutcome::Inserted]);
+        assert_eq!(outcomes(&second), vec![SaveOutcome::Unchanged]);
+        assert_eq!(first[0].get_id(), second[0].get_id());
+    }
+
+    #[test]
+    fn a_changed_file_is_updated_in_place() {
+        let mut conn = StorageEngine::open_in_memory();
+        let folder = folder(&mut conn, "/data");
+        let first = save(&mut conn, vec![scanned(folder, "/data/a.txt")]);
+
+        let mut resized = scanned(folder, "/data/a.txt");
+        resized.document.set_size(Some(11));
+        let mut rehashed = scanned(folder, "/data/a.txt");
+        rehashed.document.set_size(Some(11));
+        rehashed.document.set_hash(Some("abc".to_string()));
+
+        let saved = save(&mut conn, vec![resized]);
+        assert_eq!(outcomes(&saved), vec![SaveOutcome::Updated]);
+        assert_eq!(saved[0].get_id(), first[0].get_id());
+        assert_eq!(
+            outcomes(&save(&mut conn, vec![rehashed])),
+            vec![SaveOutcome::Updated]
+        );
+    }
+
+    #[test]
+    fn truncation_or_content_mode_changes_update_the_row() {
+        let mut conn = StorageEngine::open_in_memory();
+        let folder = folder(&mut conn, "/data");
+
+        let mut truncated = scanned(folder, "/data/a.txt");
+        truncated.document.set_truncated(true);
+        truncated.document.set_content_mode(ContentMode::FullText);
+        save(&mut conn, vec![truncated]);
+
+        let mut complete = scanned(folder, "/data/a.txt");
+        complete.document.set_content_mode(ContentMode::FullText);
+        assert_eq!(
+            outcomes(&save(&mut conn, vec![complete])),
+            vec![SaveOutcome::Updated]
+        );
+
+        let mut tokens = scanned(folder, "/data/a.txt");
+        tokens.document.set_content_mode(ContentMode::Tokens);
+        let saved = save(&mut conn, vec![tokens]);
+        assert_eq!(outcomes(&saved), vec![SaveOutcome::Updated]);
+
+        let stored = Document::get_by_id(&conn, saved[0].get_id().unwrap()).unwrap();
+        assert!(!stored.is_truncated());
+        assert_eq!(stored.get_content_mode(), Some(ContentMode::Tokens));
+    }
+
+    #[test]
+    fn a_row_that_fails_does_not_fail_the_batch() {
+        let mut conn = StorageEngine::open_in_memory();
+        let folder = folder(&mut conn, "/data");
+        conn.execute(
+            "CREATE TEMP TRIGGER reject_bad BEFORE INSERT ON documents
+            WHEN NEW.filename = 'bad.txt'
+            BEGIN SELECT RAISE(ABORT, 'rejected'); END",
+            [],
+        )
+        .unwrap();
+
+        let saved = save(
+            &mut conn,
+            vec![
+                scanned(folder, "/data/a.txt"),
+                scanned(folder, "/data/bad.txt"),
+                scanned(folder, "/data/c.txt"),
+            ],
+        );
+
+        assert_eq!(
+            outcomes(&saved),
+            vec![
+                SaveOutcome::Inserted,
+                SaveOutcome::Failed,
+                SaveOutcome::Inserted
+            ]
+        );
+        assert_eq!(saved[1].get_id(), None);
+        let stored: i64 = conn
+            .query_row("SELECT COUNT(*) FROM documents", [], |row| row.get(0))
+            .unwrap();
+        assert_eq!(stored, 2);
+    }
+
+    #[test]
+    fn failing_again_adds_to_the_attempts_and_keeps_the_row() {
+        let mut conn = StorageEngine::open_in_memory();
+        let folder = folder(&mut conn, "/data");
+
+        let first = save(&mut conn, vec![failed(folder, "/data/a.pdf", 3)]);
+        let second = save(&mut conn, vec![failed(folder, "/data/a.pdf", 3)]);
+
+        assert_eq!(first[0].get_id(), second[0].get_id());
+        assert_eq!(second[0].get_outcome(), SaveOutcome::Updated);
+        assert_eq!(attempts(&conn), vec![("/data/a.pdf".to_string(), 6)]);
+    }
+
+    #[test]
+    fn a_successful_retry_clears_the_failure() {
+        let mut conn = StorageEngine::open_in_memory();
+        let folder = folder(&mut conn, "/data");
+
+        let first = save(&mut conn, vec![failed(folder, "/data/a.pdf", 3)]);
+        let second = save(&mut conn, vec![scanned(folder, "/data/a.pdf")]);
+
+        assert_eq!(first[0].get_id(), second[0].get_id());
+        assert!(attempts(&conn).is_empty());
+    }
+
+    #[test]
+    fn marks_a_removed_file_deleted() {
+        let mut conn = StorageEngine::open_in_memory();
+        indexed_folders(&mut conn);
+
+        let marked = Document::mark_deleted_under(&mut conn, &["/data/a.txt".to_string()]).unwrap();
+
+        assert_eq!(marked, 1);
+        assert_eq!(deleted(&conn), vec!["/data/a.txt"]);
+    }
+
+    #[test]
+    fn marks_everything_under_a_removed_folder_deleted() {
+        let mut conn = StorageEngine::open_in_memory();
+        indexed_folders(&mut conn);
+
+        let marked = Document::mark_deleted_under(&mut conn, &["/data/".to_string()]).unwrap();
+
+        assert_eq!(marked, 3);
+        assert_eq!(
+            deleted(&conn),
+            vec!["/data/a.txt", "/data/b.txt", "/data/sub/c.txt"]
+        );
+    }
+
+    #[test]
+    fn marks_everything_under_the_root_deleted() {
+        let mut conn = StorageEngine::open_in_memory();
+        indexed_folders(&mut conn);
+
+        let marked = Document::mark_deleted_under(&mut conn, &["/".to_string()]).unwrap();
+
+        assert_eq!(marked, 4);
+        assert_eq!(deleted(&conn).len(), 4);
+        assert_eq!(
+            Document::mark_deleted_under(&mut conn, &["/".to_string()]).unwrap(),
+            0
+        );
+    }
+
+    #[test]
+    fn purges_deleted_documents_under_the_root_only() {
+        let mut conn = StorageEngine::open_in_memory();
+        indexed_folders(&mut conn);
+        Document::mark_deleted_under(
+            &mut conn,
+            &["/data/sub".to_string(), "/database".to_string()],
+        )
+        .unwrap();
+
+        assert_eq!(
+            Document::purge_deleted(&mut conn, Some("/data")).unwrap(),
+            1
+        );
+        assert_eq!(deleted(&conn), vec!["/database/d.txt"]);
+
+        assert_eq!(Document::purge_deleted(&mut conn, None).unwrap(), 1);
+        assert!(deleted(&conn).is_empty());
+        assert_eq!(statuses(&conn).len(), 2);
+    }
+}
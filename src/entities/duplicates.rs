@@ -0,0 +1,300 @@
+use std::str::FromStr;
+
+use crate::entities::container::ContainerType;
+
+/// Groups of at least two documents with the same hash, leaving out the
+/// documents marked `Deleted`.
+const DUPLICATE_GROUPS: &str = "SELECT hash, MAX(COALESCE(size, 0)) AS size, COUNT(*) AS count
+    FROM documents
+    WHERE hash IS NOT NULL AND status != 'Deleted'
+    GROUP BY hash
+    HAVING count > 1";
+
+#[derive(Debug)]
+pub enum DuplicateError {
+    DatabaseError(rusqlite::Error),
+}
+
+/// One copy of a duplicated file, in a folder or an archive.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DuplicateCopy {
+    document_id: i64,
+    path: String,
+    container_type: ContainerType,
+}
+
+impl DuplicateCopy {
+    pub fn get_document_id(&self) -> i64 {
+        self.document_id
+    }
+
+    pub fn get_path(&self) -> &str {
+        &self.path
+    }
+
+    pub fn get_container_type(&self) -> &ContainerType {
+        &self.container_type
+    }
+}
+
+/// Documents whose content hashes to the same value.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DuplicateGroup {
+    hash: String,
+    size: i64,
+    copies: Vec<DuplicateCopy>,
+}
+
+impl DuplicateGroup {
+    pub fn get_hash(&self) -> &str {
+        &self.hash
+    }
+
+    /// Size of one copy in bytes.
+    pub fn get_size(&self) -> i64 {
+        self.size
+    }
+
+    /// Sorted by path.
+    pub fn get_copies(&self) -> &[DuplicateCopy] {
+        &self.copies
+    }
+
+    /// Bytes taken by every copy but one.
+    pub fn get_wasted_bytes(&self) -> i64 {
+        self.size * (self.copies.len() as i64 - 1)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct DuplicateReport {
+    groups: Vec<DuplicateGroup>,
+    total_groups: i64,
+    wasted_bytes: i64,
+}
+
+impl DuplicateReport {
+    /// Largest waste first.
+    pub fn get_groups(&self) -> &[DuplicateGroup] {
+        &self.groups
+    }
+
+    /// Number of groups, ignoring `limit` and `offset`.
+    pub fn get_total_groups(&self) -> i64 {
+        self.total_groups
+    }
+
+    /// Bytes wasted across every group, ignoring `limit` and `offset`.
+    pub fn get_wasted_bytes(&self) -> i64 {
+        self.wasted_bytes
+    }
+
+    pub fn load(
+        conn: &rusqlite::Connection,
+        limit: usize,
+        offset: usize,
+    ) -> Result<DuplicateReport, DuplicateError> {
+        let (total_groups, wasted_bytes) = conn
+            .query_row(
+                &format!(
+                    "SELECT COUNT(*), COALESCE(SUM(size * (count - 1)), 0) FROM ({})",
+                    DUPLICATE_GROUPS
+                ),
+                [],
+                |row| Ok((row.get(0)?, row.get(1)?)),
+            )
+            .map_err(DuplicateError::DatabaseError)?;
+
+        let mut stmt = conn
+            .prepare(&format!(
+                "{} ORDER BY size * (count - 1) DESC, hash LIMIT ?1 OFFSET ?2",
+                DUPLICATE_GROUPS
+            ))
+            .map_err(DuplicateError::DatabaseError)?;
+
+        let groups = stmt
+            .query_map(rusqlite::params![limit as i64, offset as i64], |row| {
+                Ok((row.get::<_, String>(0)?, row.get::<_, i64>(1)?))
+            })
+            .map_err(DuplicateError::DatabaseError)?
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(DuplicateError::DatabaseError)?;
+
+        let groups = groups
+            .into_iter()
+            .map(|(hash, size)| {
+                Ok(DuplicateGroup {
+                    copies: Self::copies(conn, &hash)?,
+                    hash,
+                    size,
+                })
+            })
+            .collect::<Result<Vec<_>, DuplicateError>>()?;
+
+        Ok(DuplicateReport {
+            groups,
+            total_groups,
+            wasted_bytes,
+        })
+    }
+
+    fn copies(
+        conn: &rusqlite::Connection,
+        hash: &str,
+    ) -> Result<Vec<DuplicateCopy>, DuplicateError> {
+        let mut stmt = conn
+            .prepare_cached(
+                "SELECT id, path, container_type FROM documents_view
+                WHERE hash = ?1 AND status != 'Deleted'
+                ORDER BY path",
+            )
+            .map_err(DuplicateError::DatabaseError)?;
+
+        let rows = stmt
+            .query_map([hash], |row| {
+                let container_type_str: String = row.get(2)?;
+                let container_type =
+                    ContainerType::from_str(&container_type_str).expect("Invalid container type");
+
+                Ok(DuplicateCopy {
+                    document_id: row.get(0)?,
+                    path: row.get(1)?,
+                    container_type,
+                })
+            })
+            .map_err(DuplicateError::DatabaseError)?;
+
+        rows.collect::<Result<Vec<_>, _>>()
+            .map_err(DuplicateError::DatabaseError)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{collections::HashMap, path::Path};
+
+    use super::*;
+    use crate::{
+        engine::scanner::ScannedDocument,
+        entities::{
+            container::Container,
+            document::{Document, DocumentStatus},
+        },
+        storage::StorageEngine,
+    };
+
+    fn folder(conn: &mut rusqlite::Connection, path: &str) -> i64 {
+        let mut container = Container::new(0, path.to_string(), ContainerType::Folder);
+        container.save(conn).expect("Failed to save folder");
+        container.get_id()
+    }
+
+    fn document(container_id: i64, path: &str, size: i64, hash: &str) -> ScannedDocument {
+        let mut document = Document::from_path(Path::new(path));
+        document.set_container_id(container_id);
+        document.set_size(Some(size));
+        document.set_hash(Some(hash.to_string()));
+        document.set_status(DocumentStatus::Extracted);
+
+        ScannedDocument {
+            container_type: ContainerType::Folder,
+            document,
+            job: None,
+            failure: None,
+        }
+    }
+
+    fn save(conn: &mut rusqlite::Connection, documents: Vec<ScannedDocument>) -> Vec<i64> {
+        Document::save_bulk(conn, documents, &mut HashMap::new())
+            .expect("Failed to save batch")
+            .iter()
+            .filter_map(|saved| saved.get_id())
+            .collect()
+    }
+
+    fn paths(group: &DuplicateGroup) -> Vec<&str> {
+        group
+            .get_copies()
+            .iter()
+            .map(|copy| copy.get_path())
+            .collect()
+    }
+
+    #[test]
+    fn groups_documents_with_the_same_hash() {
+        let mut conn = StorageEngine::open_in_memory();
+        let data = folder(&mut conn, "/data");
+        save(
+            &mut conn,
+            vec![
+                document(data, "/data/b.txt", 10, "same"),
+                document(data, "/data/a.txt", 10, "same"),
+                document(data, "/data/unique.txt", 10, "unique"),
+            ],
+        );
+
+        let report = DuplicateReport::load(&conn, 10, 0).unwrap();
+
+        assert_eq!(report.get_total_groups(), 1);
+        assert_eq!(report.get_groups()[0].get_hash(), "same");
+        assert_eq!(
+            paths(&report.get_groups()[0]),
+            vec!["/data/a.txt", "/data/b.txt"]
+        );
+    }
+
+    #[test]
+    fn orders_by_wasted_bytes_and_totals_every_group() {
+        let mut conn = StorageEngine::open_in_memory();
+        let data = folder(&mut conn, "/data");
+        save(
+            &mut conn,
+            vec![
+                document(data, "/data/small1.txt", 10, "small"),
+                document(data, "/data/small2.txt", 10, "small"),
+                document(data, "/data/small3.txt", 10, "small"),
+                document(data, "/data/large1.txt", 100, "large"),
+                document(data, "/data/large2.txt", 100, "large"),
+            ],
+        );
+
+        let report = DuplicateReport::load(&conn, 1, 0).unwrap();
+
+        assert_eq!(report.get_total_groups(), 2);
+        assert_eq!(report.get_wasted_bytes(), 120);
+        assert_eq!(report.get_groups().len(), 1);
+        assert_eq!(report.get_groups()[0].get_hash(), "large");
+        assert_eq!(report.get_groups()[0].get_wasted_bytes(), 100);
+
+        let next = DuplicateReport::load(&conn, 1, 1).unwrap();
+        assert_eq!(next.get_groups()[0].get_hash(), "small");
+        assert_eq!(next.get_groups()[0].get_wasted_bytes(), 20);
+    }
+
+    #[test]
+    fn leaves_out_deleted_copies() {
+        let mut conn = StorageEngine::open_in_memory();
+        let data = folder(&mut conn, "/data");
+        let ids = save(
+            &mut conn,
+            vec![
+                document(data, "/data/a.txt", 10, "same"),
+                document(data, "/data/b.txt", 10, "same"),
+                document(data, "/data/c.txt", 10, "same"),
+            ],
+        );
+
+        Document::mark_deleted(&mut conn, &ids[..1]).expect("Failed to mark deleted");
+        let report = DuplicateReport::load(&conn, 10, 0).unwrap();
+        assert_eq!(
+            paths(&report.get_groups()[0]),
+            vec!["/data/b.txt", "/data/c.txt"]
+        );
+        assert_eq!(report.get_wasted_bytes(), 10);
+
+        Document::mark_deleted(&mut conn, &ids[1..2]).expect("Failed to mark deleted");
+        let report = DuplicateReport::load(&conn, 10, 0).unwrap();
+        assert_eq!(report.get_total_groups(), 0);
+        assert!(report.get_groups().is_empty());
+    }
+}
@@ -0,0 +1,293 @@
+use std::{error::Error, str::FromStr};
+
+use pdfium_render::prelude::PdfiumError;
+
+use crate::{engine::extractor::isolation::IsolationError, entities::container::ContainerType};
+
+#[derive(Debug)]
+pub enum FailureError {
+    DatabaseError(rusqlite::Error),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum FailureKind {
+    /// The file vanished between the scan and the extraction.
+    NotFound,
+    PermissionDenied,
+    /// Any other I/O error.
+    Io,
+    /// Corrupt or unsupported content (bad PDF, ZIP or DOCX structure).
+    InvalidFormat,
+    /// The extractor itself could not run, e.g. PDFium failed to load.
+    Unavailable,
+    /// The extractor panicked, or its child process died.
+    Crashed,
+    /// The extraction ran past `EXTRACTOR_TIMEOUT`.
+    TimedOut,
+    Other,
+}
+
+impl FromStr for FailureKind {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "NotFound" => Ok(FailureKind::NotFound),
+            "PermissionDenied" => Ok(FailureKind::PermissionDenied),
+            "Io" => Ok(FailureKind::Io),
+            "InvalidFormat" => Ok(FailureKind::InvalidFormat),
+            "Unavailable" => Ok(FailureKind::Unavailable),
+            "Crashed" => Ok(FailureKind::Crashed),
+            "TimedOut" => Ok(FailureKind::TimedOut),
+            "Other" => Ok(FailureKind::Other),
+            _ => Err(()),
+        }
+    }
+}
+
+impl FailureKind {
+    /// Transient failures may succeed on another attempt: I/O errors, locked
+    /// files and an extractor that could not be loaded. The others are
+    /// permanent.
+    pub fn is_transient(&self) -> bool {
+        matches!(
+            self,
+            FailureKind::Io | FailureKind::PermissionDenied | FailureKind::Unavailable
+        )
+    }
+
+    pub fn to_str(&self) -> &str {
+        match self {
+            FailureKind::NotFound => "NotFound",
+            FailureKind::PermissionDenied => "PermissionDenied",
+            FailureKind::Io => "Io",
+            FailureKind::InvalidFormat => "InvalidFormat",
+            FailureKind::Unavailable => "Unavailable",
+            FailureKind::Crashed => "Crashed",
+            FailureKind::TimedOut => "TimedOut",
+            FailureKind::Other => "Other",
+        }
+    }
+
+    /// Kind of an error returned by one of the format extractors.
+    pub fn classify(err: &(dyn Error + 'static)) -> Self {
+        if let Some(err) = err.downcast_ref::<std::io::Error>() {
+            return match err.kind() {
+                std::io::ErrorKind::NotFound => FailureKind::NotFound,
+                std::io::ErrorKind::PermissionDenied => FailureKind::PermissionDenied,
+                std::io::ErrorKind::InvalidData => FailureKind::InvalidFormat,
+                _ => FailureKind::Io,
+            };
+        }
+
+        if let Some(err) = err.downcast_ref::<zip::result::ZipError>() {
+            return match err {
+                zip::result::ZipError::Io(err) => FailureKind::classify(err),
+                _ => FailureKind::InvalidFormat,
+            };
+        }
+
+        if let Some(err) = err.downcast_ref::<PdfiumError>() {
+            return match err {
+                PdfiumError::LoadLibraryError(_) | PdfiumError::LoadLibraryFunctionNameError(_) => {
+                    FailureKind::Unavailable
+                }
+                PdfiumError::IoError(err) => FailureKind::classify(err),
+                _ => FailureKind::InvalidFormat,
+            };
+        }
+
+        if let Some(err) = err.downcast_ref::<IsolationError>() {
+            return match err {
+                IsolationError::Panicked(_) | IsolationError::Exited(_) => FailureKind::Crashed,
+                IsolationError::TimedOut(_) => FailureKind::TimedOut,
+                IsolationError::Spawn(err) => FailureKind::classify(err),
+            };
+        }
+
+        if err.is::<quick_xml::Error>() {
+            return FailureKind::InvalidFormat;
+        }
+
+        FailureKind::Other
+    }
+}
+
+/// Why the content of a document could not be extracted.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct ExtractionFailure {
+    kind: FailureKind,
+    message: String,
+    extractor: String,
+    failed_at: i64,
+    attempts: u32,
+}
+
+impl ExtractionFailure {
+    pub fn new(kind: FailureKind, message: String, extractor: &str) -> Self {
+        ExtractionFailure {
+            kind,
+            message,
+            extractor: extractor.to_string(),
+            failed_at: chrono::Utc::now().timestamp(),
+            attempts: 1,
+        }
+    }
+
+    /// Some errors print over several lines, the message is kept on one.
+    pub fn from_error(extractor: &str, err: &(dyn Error + 'static)) -> Self {
+        let message = err
+            .to_string()
+            .split_whitespace()
+            .collect::<Vec<_>>()
+            .join(" ");
+        Self::new(FailureKind::classify(err), message, extractor)
+    }
+
+    pub fn get_kind(&self) -> FailureKind {
+        self.kind
+    }
+
+    pub fn get_message(&self) -> &str {
+        &self.message
+    }
+
+    /// Name of the extractor that failed, e.g. `PdfExtractor`.
+    pub fn get_extractor(&self) -> &str {
+        &self.extractor
+    }
+
+    /// Unix seconds.
+    pub fn get_failed_at(&self) -> i64 {
+        self.failed_at
+    }
+
+    /// Extraction attempts made, all of which failed.
+    pub fn get_attempts(&self) -> u32 {
+        self.attempts
+    }
+
+    pub fn set_attempts(&mut self, attempts: u32) {
+        self.attempts = attempts;
+    }
+
+    pub fn is_permanent(&self) -> bool {
+        !self.kind.is_transient()
+    }
+
+    /// Records the failure for a stored document, replacing an earlier one.
+    pub fn save(&self, conn: &rusqlite::Connection, document_id: i64) -> Result<(), FailureError> {
+        conn.execute(
+            "INSERT OR REPLACE INTO extraction_errors
+                (document_id, kind, message, extractor, failed_at, attempts, permanent)
+            VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
+            rusqlite::params![
+                document_id,
+                self.kind.to_str(),
+                self.message,
+                self.extractor,
+                self.failed_at,
+                self.attempts,
+                self.is_permanent()
+            ],
+        )
+        .map_err(FailureError::DatabaseError)?;
+
+        Ok(())
+    }
+}
+
+/// A stored document whose extraction failed.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FailedDocument {
+    document_id: i64,
+    path: String,
+    failure: ExtractionFailure,
+}
+
+impl FailedDocument {
+    pub fn get_document_id(&self) -> i64 {
+        self.document_id
+    }
+
+    pub fn get_path(&self) -> &str {
+        &self.path
+    }
+
+    pub fn get_failure(&self) -> &ExtractionFailure {
+        &self.failure
+    }
+
+    /// Most recent failures first, optionally of one kind only.
+    pub fn list(
+        conn: &rusqlite::Connection,
+        kind: Option<FailureKind>,
+        limit: usize,
+        offset: usize,
+    ) -> Result<Vec<FailedDocument>, FailureError> {
+        Self::query(
+            conn,
+            "WHERE ?1 IS NULL OR e.kind = ?1
+            ORDER BY e.failed_at DESC, d.path
+            LIMIT ?2 OFFSET ?3",
+            rusqlite::params![
+                kind.as_ref().map(FailureKind::to_str),
+                limit as i64,
+                offset as i64
+            ],
+        )
+    }
+
+    /// Failed documents that can be extracted again from their path:
+    /// transient failures only, unless `include_permanent` is set. Archive
+    /// members are left out, their archive is what gets rescanned.
+    pub fn list_retryable(
+        conn: &rusqlite::Connection,
+        include_permanent: bool,
+    ) -> Result<Vec<FailedDocument>, FailureError> {
+        Self::query(
+            conn,
+            "WHERE (?1 OR e.permanent = 0) AND d.container_type = ?2
+            ORDER BY d.path",
+            rusqlite::params![include_permanent, ContainerType::Folder.to_str()],
+        )
+    }
+
+    fn query(
+        conn: &rusqlite::Connection,
+        clauses: &str,
+        params: &[&dyn rusqlite::ToSql],
+    ) -> Result<Vec<FailedDocument>, FailureError> {
+        let mut stmt = conn
+            .prepare(&format!(
+                "SELECT e.document_id, d.path, e.kind, e.message, e.extractor, e.failed_at,
+                    e.attempts
+                FROM extraction_errors e
+                INNER JOIN documents_view d ON d.id = e.document_id
+                {}",
+                clauses
+            ))
+            .map_err(FailureError::DatabaseError)?;
+
+        let rows = stmt
+            .query_map(params, |row| {
+                let kind_str: String = row.get(2)?;
+
+                Ok(FailedDocument {
+                    document_id: row.get(0)?,
+                    path: row.get(1)?,
+                    failure: ExtractionFailure {
+                        kind: FailureKind::from_str(&kind_str).unwrap_or(FailureKind::Other),
+                        message: row.get(3)?,
+                        extractor: row.get(4)?,
+                        failed_at: row.get(5)?,
+                        attempts: row.get(6)?,
+                    },
+                })
+            })
+            .map_err(FailureError::DatabaseError)?;
+
+        rows.collect::<Result<Vec<_>, _>>()
+            .map_err(FailureError::DatabaseError)
+    }
+}
@@ -0,0 +1,124 @@
+use std::path::MAIN_SEPARATOR;
+
+use rusqlite::types::Value;
+
+#[derive(Debug)]
+pub enum FindError {
+    DatabaseError(rusqlite::Error),
+}
+
+/// Document located by path or file name through `index_paths`, independent
+/// of whether its content could be extracted.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PathHit {
+    document_id: i64,
+    path: String,
+    filename: String,
+}
+
+impl PathHit {
+    pub fn get_document_id(&self) -> i64 {
+        self.document_id
+    }
+
+    pub fn get_path(&self) -> &str {
+        &self.path
+    }
+
+    pub fn get_filename(&self) -> &str {
+        &self.filename
+    }
+
+    /// Locate-style lookup. Every whitespace-separated term must match:
+    ///
+    /// - plain terms are case-insensitive substrings of the full path;
+    /// - terms with `*`, `?` or `[...]` are case-insensitive globs, matched
+    ///   against the file name, or against the full path when they contain a
+    ///   path separator.
+    ///
+    /// Shorter paths come first.
+    pub fn find(
+        conn: &rusqlite::Connection,
+        pattern: &str,
+        limit: usize,
+    ) -> Result<Vec<PathHit>, FindError> {
+        let mut conditions = Vec::new();
+        let mut params = Vec::new();
+
+        for term in pattern.split_whitespace() {
+            let term = term.to_lowercase();
+
+            // `LIKE` is served by the trigram index, the second condition
+            // makes the match exact.
+            if term.contains(['*', '?', '[']) {
+                let column = if term.contains(MAIN_SEPARATOR) {
+                    "path"
+                } else {
+                    "filename"
+                };
+                conditions.push(format!("({0} LIKE ? AND lower({0}) GLOB ?)", column));
+                params.push(Value::Text(glob_to_like(&term)));
+                params.push(Value::Text(term));
+            } else {
+                conditions.push("(path LIKE ? AND instr(lower(path), ?) > 0)".to_string());
+                params.push(Value::Text(format!("%{}%", term)));
+                params.push(Value::Text(term));
+            }
+        }
+
+        if conditions.is_empty() {
+            return Ok(Vec::new());
+        }
+
+        params.push(Value::Integer(limit as i64));
+
+        let mut stmt = conn
+            .prepare(&format!(
+                "SELECT document_id, path, filename
+                FROM index_paths
+                WHERE {}
+                ORDER BY length(path), path
+                LIMIT ?",
+                conditions.join(" AND ")
+            ))
+            .map_err(FindError::DatabaseError)?;
+
+        let rows = stmt
+            .query_map(rusqlite::params_from_iter(params), |row| {
+                Ok(PathHit {
+                    document_id: row.get(0)?,
+                    path: row.get(1)?,
+                    filename: row.get(2)?,
+                })
+            })
+            .map_err(FindError::DatabaseError)?;
+
+        rows.collect::<Result<Vec<_>, _>>()
+            .map_err(FindError::DatabaseError)
+    }
+}
+
+/// `LIKE` pattern matching a superset of `glob`: `*` becomes `%`, `?` and
+/// bracket expressions become `_`.
+fn glob_to_like(glob: &str) -> String {
+    let mut like = String::with_capacity(glob.len());
+    let mut chars = glob.chars();
+
+    while let Some(c) = chars.next() {
+        match c {
+            '*' => like.push('%'),
+            '?' => like.push('_'),
+            '[' => {
+                for c in chars.by_ref() {
+                    if c == ']' {
+                        break;
+                    }
+                }
+                like.push('_');
+            }
+            c => like.push(c),
+        }
+    }
+
+    like
+}
@@ -0,0 +1,131 @@
+use std::str::FromStr;
+
+use rusqlite::types::Value;
+
+use crate::{
+    entities::{container::ContainerType, document::DocumentStatus},
+    storage::query::{Query, QueryError},
+};
+
+#[derive(Debug)]
+pub enum ListingError {
+    DatabaseError(rusqlite::Error),
+    QueryError(QueryError),
+}
+
+/// One row of `documents_view`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DocumentListing {
+    id: i64,
+    path: String,
+    filename: String,
+    extension: Option<String>,
+    status: DocumentStatus,
+    container_id: i64,
+    container_type: ContainerType,
+    container_path: String,
+    size: Option<i64>,
+    modified: Option<i64>,
+    hash: Option<String>,
+}
+
+impl DocumentListing {
+    pub fn get_id(&self) -> i64 {
+        self.id
+    }
+
+    pub fn get_path(&self) -> &str {
+        &self.path
+    }
+
+    pub fn get_filename(&self) -> &str {
+        &self.filename
+    }
+
+    pub fn get_extension(&self) -> Option<&str> {
+        self.extension.as_deref()
+    }
+
+    pub fn get_status(&self) -> &DocumentStatus {
+        &self.status
+    }
+
+    pub fn get_container_id(&self) -> i64 {
+        self.container_id
+    }
+
+    pub fn get_container_type(&self) -> &ContainerType {
+        &self.container_type
+    }
+
+    pub fn get_container_path(&self) -> &str {
+        &self.container_path
+    }
+
+    pub fn get_size(&self) -> Option<i64> {
+        self.size
+    }
+
+    /// Last modification time in unix seconds.
+    pub fn get_modified(&self) -> Option<i64> {
+        self.modified
+    }
+
+    pub fn get_hash(&self) -> Option<&str> {
+        self.hash.as_deref()
+    }
+
+    /// Documents in path order, optionally filtered by a search query.
+    /// `limit` of `None` returns every matching row.
+    pub fn list(
+        conn: &rusqlite::Connection,
+        query: Option<&Query>,
+        limit: Option<usize>,
+        offset: usize,
+    ) -> Result<Vec<DocumentListing>, ListingError> {
+        let (predicate, mut params) = match query {
+            Some(query) => query.to_sql(),
+            None => ("1".to_string(), Vec::new()),
+        };
+
+        params.push(Value::Integer(limit.map(|n| n as i64).unwrap_or(-1)));
+        params.push(Value::Integer(offset as i64));
+
+        let mut stmt = conn
+            .prepare(&format!(
+                "SELECT d.id, d.path, d.filename, d.extension, d.status, d.container_id,
+                    d.container_type, d.container_path, d.size, d.modified, d.hash
+                FROM documents_view d
+                WHERE {}
+                ORDER BY d.path
+                LIMIT ? OFFSET ?",
+                predicate
+            ))
+            .map_err(ListingError::DatabaseError)?;
+
+        let rows = stmt
+            .query_map(rusqlite::params_from_iter(params), |row| {
+                let status_str: String = row.get(4)?;
+                let container_type_str: String = row.get(6)?;
+
+                Ok(DocumentListing {
+                    id: row.get(0)?,
+                    path: row.get(1)?,
+                    filename: row.get(2)?,
+                    extension: row.get(3)?,
+                    status: DocumentStatus::from_str(&status_str).expect("Invalid document status"),
+                    container_id: row.get(5)?,
+                    container_type: ContainerType::from_str(&container_type_str)
+                        .expect("Invalid container type"),
+                    container_path: row.get(7)?,
+                    size: row.get(8)?,
+                    modified: row.get(9)?,
+                    hash: row.get(10)?,
+                })
+            })
+            .map_err(ListingError::DatabaseError)?;
+
+        rows.collect::<Result<Vec<_>, _>>()
+            .map_err(ListingError::DatabaseError)
+    }
+}
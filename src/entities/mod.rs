@@ -1,7 +1,51 @@
 pub mod container;
 pub mod document;
+pub mod duplicates;
+pub mod failure;
+pub mod find;
+pub mod listing;
+pub mod reconcile;
+pub mod search;
+pub mod stamp;
+pub mod stats;
+
+use std::path::MAIN_SEPARATOR;
 
 #[derive(Debug)]
 pub enum EntityError {
     DocumentError(document::DocumentError),
 }
+
+/// Splits a folder path into the path to compare containers with, without
+/// trailing separators, and the prefix of the paths under it. The root keeps
+/// its separator, which then ends the prefix too.
+pub(crate) fn path_and_prefix(path: &str) -> (&str, String) {
+    let path = match path.trim_end_matches(MAIN_SEPARATOR) {
+        "" => path,
+        trimmed => trimmed,
+    };
+    let prefix = if path.ends_with(MAIN_SEPARATOR) {
+        path.to_string()
+    } else {
+        format!("{}{}", path, MAIN_SEPARATOR)
+    };
+
+    (path, prefix)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn a_folder_path_gets_a_trailing_separator_prefix() {
+        assert_eq!(path_and_prefix("/data"), ("/data", "/data/".to_string()));
+        assert_eq!(path_and_prefix("/data/"), ("/data", "/data/".to_string()));
+        assert_eq!(path_and_prefix("/data//"), ("/data", "/data/".to_string()));
+    }
+
+    #[test]
+    fn the_root_keeps_its_separator() {
+        assert_eq!(path_and_prefix("/"), ("/", "/".to_string()));
+    }
+}
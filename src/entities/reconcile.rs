@@ -0,0 +1,153 @@
+use std::{collections::HashMap, path::Path, str::FromStr};
+
+use crate::entities::{container::ContainerType, document::DocumentStatus, path_and_prefix};
+
+#[derive(Debug)]
+pub enum ReconcileError {
+    DatabaseError(rusqlite::Error),
+}
+
+/// An indexed document a reconciliation pass looks for, see
+/// `ReconcileCandidate::list`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ReconcileCandidate {
+    document_id: i64,
+    path: String,
+    container_id: i64,
+    container_type: ContainerType,
+    container_path: String,
+}
+
+impl ReconcileCandidate {
+    pub fn get_document_id(&self) -> i64 {
+        self.document_id
+    }
+
+    pub fn get_path(&self) -> &str {
+        &self.path
+    }
+
+    pub fn get_container_id(&self) -> i64 {
+        self.container_id
+    }
+
+    pub fn get_container_type(&self) -> &ContainerType {
+        &self.container_type
+    }
+
+    pub fn get_container_path(&self) -> &str {
+        &self.container_path
+    }
+
+    /// Documents not yet marked deleted at or under `root`, or in the whole
+    /// index without one.
+    pub fn list(
+        conn: &rusqlite::Connection,
+        root: Option<&str>,
+    ) -> Result<Vec<ReconcileCandidate>, ReconcileError> {
+        let mut params = vec![DocumentStatus::Deleted.to_str().to_string()];
+        let predicate = match root {
+            Some(root) => {
+                let (root, prefix) = path_and_prefix(root);
+                params.push(root.to_string());
+                params.push(prefix);
+
+                "AND (container_path = ?2 OR substr(container_path, 1, length(?3)) = ?3)"
+            }
+            None => "",
+        };
+
+        let mut stmt = conn
+            .prepare(&format!(
+                "SELECT id, path, container_id, container_type, container_path
+                FROM documents_view
+                WHERE status != ?1 {}",
+                predicate
+            ))
+            .map_err(ReconcileError::DatabaseError)?;
+
+        let rows = stmt
+            .query_map(rusqlite::params_from_iter(params), |row| {
+                let container_type_str: String = row.get(3)?;
+                let container_type =
+                    ContainerType::from_str(&container_type_str).expect("Invalid container type");
+
+                Ok(ReconcileCandidate {
+                    document_id: row.get(0)?,
+                    path: row.get(1)?,
+                    container_id: row.get(2)?,
+                    container_type,
+                    container_path: row.get(4)?,
+                })
+            })
+            .map_err(ReconcileError::DatabaseError)?;
+
+        rows.collect::<Result<Vec<_>, _>>()
+            .map_err(ReconcileError::DatabaseError)
+    }
+
+    /// Looks for the file of each folder document and for the archive of
+    /// each archive member, checking each container once. Returns the ids of
+    /// the documents that are gone and of the containers that vanished.
+    ///
+    /// Touches the file system, so it runs on the caller's thread rather than
+    /// the storage thread.
+    pub fn find_missing(candidates: &[ReconcileCandidate]) -> (Vec<i64>, Vec<i64>) {
+        let mut containers: HashMap<i64, bool> = HashMap::new();
+        let mut deleted = Vec::new();
+
+        for candidate in candidates {
+            let container_exists = *containers
+                .entry(candidate.container_id)
+                .or_insert_with(|| Path::new(&candidate.container_path).exists());
+
+            let exists = container_exists
+                && (candidate.container_type != ContainerType::Folder
+                    || Path::new(&candidate.path).exists());
+            if !exists {
+                deleted.push(candidate.document_id);
+            }
+        }
+
+        let vanished = containers
+            .into_iter()
+            .filter(|(_, exists)| !exists)
+            .map(|(id, _)| id)
+            .collect();
+
+        (deleted, vanished)
+    }
+}
+
+/// Outcome of a reconciliation pass, see `Api::reconcile`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct ReconcileSummary {
+    checked: usize,
+    deleted: usize,
+    vanished_containers: usize,
+}
+
+impl ReconcileSummary {
+    pub fn new(checked: usize, deleted: usize, vanished_containers: usize) -> Self {
+        ReconcileSummary {
+            checked,
+            deleted,
+            vanished_containers,
+        }
+    }
+
+    /// Documents whose file was looked for.
+    pub fn get_checked(&self) -> usize {
+        self.checked
+    }
+
+    /// Documents marked `DocumentStatus::Deleted`.
+    pub fn get_deleted(&self) -> usize {
+        self.deleted
+    }
+
+    /// Folders and archives that no longer exist.
+    pub fn get_vanished_containers(&self) -> usize {
+        self.vanished_containers
+    }
+}
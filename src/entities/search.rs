@@ -0,0 +1,363 @@
+use std::str::FromStr;
+
+use rusqlite::types::Value;
+
+use crate::{
+    entities::{container::ContainerType, document::DocumentStatus},
+    storage::{
+        STORAGE_TRIGRAM_INDEX,
+        query::{Query, QueryError, SearchMode, trigram_union},
+    },
+};
+
+const SNIPPET_START: &str = "[";
+const SNIPPET_END: &str = "]";
+const SNIPPET_ELLIPSIS: &str = "…";
+const SNIPPET_TOKENS: usize = 16;
+const FACET_CONTAINER_PATH_LIMIT: usize = 50;
+
+#[derive(Debug)]
+pub enum SearchError {
+    DatabaseError(rusqlite::Error),
+    QueryError(QueryError),
+    /// Fuzzy search was requested but `STORAGE_TRIGRAM_INDEX` is off.
+    TrigramIndexDisabled,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct SearchHit {
+    document_id: i64,
+    path: String,
+    container_type: ContainerType,
+    extension: Option<String>,
+    rank: f64,
+    snippet: Option<String>,
+    hash: Option<String>,
+    copies: i64,
+}
+
+/// Hits sharing a content hash form one group, documents without a hash are
+/// groups of their own.
+const HASH_GROUP: &str = "COALESCE(d.hash, 'id:' || d.id)";
+
+/// Documents marked `DocumentStatus::Deleted` wait for a prune and are never
+/// searched.
+const NOT_DELETED: &str = "d.status != 'Deleted'";
+
+impl SearchHit {
+    pub fn get_document_id(&self) -> i64 {
+        self.document_id
+    }
+
+    pub fn get_path(&self) -> &str {
+        &self.path
+    }
+
+    pub fn get_container_type(&self) -> &ContainerType {
+        &self.container_type
+    }
+
+    pub fn get_extension(&self) -> Option<&str> {
+        self.extension.as_deref()
+    }
+
+    pub fn get_rank(&self) -> f64 {
+        self.rank
+    }
+
+    /// Best matching fragment of the indexed content, with the matched terms
+    /// wrapped in `[` `]`.
+    pub fn get_snippet(&self) -> Option<&str> {
+        self.snippet.as_deref()
+    }
+
+    pub fn get_hash(&self) -> Option<&str> {
+        self.hash.as_deref()
+    }
+
+    /// Matching documents with this hit's content, itself included, when the
+    /// query is collapsed; 1 otherwise.
+    pub fn get_copies(&self) -> i64 {
+        self.copies
+    }
+
+    pub fn search(
+        conn: &rusqlite::Connection,
+        query: &Query,
+        limit: usize,
+        offset: usize,
+    ) -> Result<Vec<SearchHit>, SearchError> {
+        let (predicate, mut params) = query.to_sql();
+
+        // Ranking and snippets come from the positive text terms only;
+        // qualifier-only queries are returned in document order.
+        let trigrams = match query.get_mode() {
+            SearchMode::Fuzzy => query.fuzzy_trigrams(),
+            SearchMode::Exact => Vec::new(),
+        };
+
+        let (rank_join, rank_columns) = match query.rank_expression() {
+            // Share of the query trigrams found in the document, negated so
+            // that better matches sort first like bm25.
+            Some(_) if !trigrams.is_empty() => {
+                let count = trigrams.len();
+                let mut rank_params: Vec<Value> = trigrams.into_iter().map(Value::Text).collect();
+                rank_params.push(Value::Text(
+                    query.fuzzy_snippet_expression().unwrap_or_default(),
+                ));
+                params.splice(0..0, rank_params);
+                (
+                    format!(
+                        "LEFT JOIN (
+                            SELECT document_id, -CAST(COUNT(*) AS REAL) / {} AS rank
+                            FROM ({})
+                            GROUP BY document_id
+                        ) r ON r.document_id = d.id
+                        LEFT JOIN (
+                            SELECT document_id,
+                                snippet(index_documents_trigram, -1, '{}', '{}', '{}', {}) AS snippet
+                            FROM index_documents_trigram
+                            WHERE index_documents_trigram MATCH ?
+                        ) s ON s.document_id = d.id",
+                        count,
+                        trigram_union(count),
+                        SNIPPET_START,
+                        SNIPPET_END,
+                        SNIPPET_ELLIPSIS,
+                        SNIPPET_TOKENS
+                    ),
+                    "r.rank, s.snippet",
+                )
+            }
+            Some(expression) => {
+                params.insert(0, Value::Text(expression));
+                (
+                    format!(
+                        "LEFT JOIN (
+                            SELECT document_id,
+                                bm25(index_documents) AS rank,
+                                snippet(index_documents, -1, '{}', '{}', '{}', {}) AS snippet
+                            FROM index_documents
+                            WHERE index_documents MATCH ?
+                        ) r ON r.document_id = d.id",
+                        SNIPPET_START, SNIPPET_END, SNIPPET_ELLIPSIS, SNIPPET_TOKENS
+                    ),
+                    "r.rank, r.snippet",
+                )
+            }
+            None => (String::new(), "NULL AS rank, NULL AS snippet"),
+        };
+
+        params.push(Value::Integer(limit as i64));
+        params.push(Value::Integer(offset as i64));
+
+        let sql = if query.is_collapsed() {
+            // The best ranked copy stands for its group.
+            format!(
+                "SELECT id, path, container_type, extension, rank, snippet, hash, copies
+                FROM (
+                    SELECT *,
+                        ROW_NUMBER() OVER (PARTITION BY hash_group ORDER BY rank IS NULL, rank, id) AS position,
+                        COUNT(*) OVER (PARTITION BY hash_group) AS copies
+                    FROM (
+                        SELECT d.id, d.path, d.container_type, d.extension, {}, d.hash,
+                            {} AS hash_group
+                        FROM documents_view d
+                        {}
+                        WHERE {} AND ({})
+                    )
+                )
+                WHERE position = 1
+                ORDER BY rank IS NULL, rank, id
+                LIMIT ? OFFSET ?",
+                rank_columns, HASH_GROUP, rank_join, NOT_DELETED, predicate
+            )
+        } else {
+            format!(
+                "SELECT d.id, d.path, d.container_type, d.extension, {}, d.hash, 1 AS copies
+                FROM documents_view d
+                {}
+                WHERE {} AND ({})
+                ORDER BY rank IS NULL, rank, d.id
+                LIMIT ? OFFSET ?",
+                rank_columns, rank_join, NOT_DELETED, predicate
+            )
+        };
+
+        let mut stmt = conn.prepare(&sql).map_err(SearchError::DatabaseError)?;
+
+        let rows = stmt
+            .query_map(rusqlite::params_from_iter(params), |row| {
+                let container_type_str: String = row.get(2)?;
+                let container_type =
+                    ContainerType::from_str(&container_type_str).expect("Invalid container type");
+
+                Ok(SearchHit {
+                    document_id: row.get(0)?,
+                    path: row.get(1)?,
+                    container_type,
+                    extension: row.get(3)?,
+                    rank: row.get::<_, Option<f64>>(4)?.unwrap_or_default(),
+                    snippet: row.get(5)?,
+                    hash: row.get(6)?,
+                    copies: row.get(7)?,
+                })
+            })
+            .map_err(SearchError::DatabaseError)?;
+
+        rows.collect::<Result<Vec<_>, _>>()
+            .map_err(SearchError::DatabaseError)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct SearchFacets {
+    extensions: Vec<(Option<String>, i64)>,
+    container_types: Vec<(ContainerType, i64)>,
+    container_paths: Vec<(String, i64)>,
+    statuses: Vec<(DocumentStatus, i64)>,
+}
+
+impl SearchFacets {
+    pub fn get_extensions(&self) -> &[(Option<String>, i64)] {
+        &self.extensions
+    }
+
+    pub fn get_container_types(&self) -> &[(ContainerType, i64)] {
+        &self.container_types
+    }
+
+    /// Containers with the most matching documents, capped at 50 entries.
+    pub fn get_container_paths(&self) -> &[(String, i64)] {
+        &self.container_paths
+    }
+
+    pub fn get_statuses(&self) -> &[(DocumentStatus, i64)] {
+        &self.statuses
+    }
+
+    pub fn load(conn: &rusqlite::Connection, query: &Query) -> Result<SearchFacets, SearchError> {
+        Ok(SearchFacets {
+            extensions: Self::count_by(conn, query, "d.extension", None)?,
+            container_types: Self::count_by::<String>(conn, query, "d.container_type", None)?
+                .into_iter()
+                .map(|(value, count)| {
+                    let container_type =
+                        ContainerType::from_str(&value).expect("Invalid container type");
+                    (container_type, count)
+                })
+                .collect(),
+            container_paths: Self::count_by(
+                conn,
+                query,
+                "d.container_path",
+                Some(FACET_CONTAINER_PATH_LIMIT),
+            )?,
+            statuses: Self::count_by::<String>(conn, query, "d.status", None)?
+                .into_iter()
+                .map(|(value, count)| {
+                    let status = DocumentStatus::from_str(&value).expect("Invalid document status");
+                    (status, count)
+                })
+                .collect(),
+        })
+    }
+
+    fn count_by<T: rusqlite::types::FromSql>(
+        conn: &rusqlite::Connection,
+        query: &Query,
+        column: &str,
+        limit: Option<usize>,
+    ) -> Result<Vec<(T, i64)>, SearchError> {
+        let (predicate, params) = query.to_sql();
+        let limit = limit.map(|n| format!("LIMIT {}", n)).unwrap_or_default();
+
+        let mut stmt = conn
+            .prepare(&format!(
+                "SELECT {column}, COUNT(*) AS count
+                FROM documents_view d
+                WHERE {NOT_DELETED} AND ({predicate})
+                GROUP BY {column}
+                ORDER BY count DESC, {column}
+                {limit}"
+            ))
+            .map_err(SearchError::DatabaseError)?;
+
+        let rows = stmt
+            .query_map(rusqlite::params_from_iter(params), |row| {
+                Ok((row.get(0)?, row.get(1)?))
+            })
+            .map_err(SearchError::DatabaseError)?;
+
+        rows.collect::<Result<Vec<_>, _>>()
+            .map_err(SearchError::DatabaseError)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct SearchResults {
+    hits: Vec<SearchHit>,
+    total: i64,
+    facets: Option<SearchFacets>,
+}
+
+impl SearchResults {
+    pub fn get_hits(&self) -> &[SearchHit] {
+        &self.hits
+    }
+
+    pub fn into_hits(self) -> Vec<SearchHit> {
+        self.hits
+    }
+
+    /// Number of matching documents, or of distinct contents when the query
+    /// is collapsed, ignoring `limit` and `offset`.
+    pub fn get_total(&self) -> i64 {
+        self.total
+    }
+
+    pub fn get_facets(&self) -> Option<&SearchFacets> {
+        self.facets.as_ref()
+    }
+
+    pub fn load(
+        conn: &rusqlite::Connection,
+        query: &Query,
+        limit: usize,
+        offset: usize,
+        facets: bool,
+    ) -> Result<SearchResults, SearchError> {
+        if query.get_mode() == SearchMode::Fuzzy && !*STORAGE_TRIGRAM_INDEX {
+            return Err(SearchError::TrigramIndexDisabled);
+        }
+
+        let (predicate, params) = query.to_sql();
+        let count = if query.is_collapsed() {
+            format!("COUNT(DISTINCT {})", HASH_GROUP)
+        } else {
+            "COUNT(*)".to_string()
+        };
+        let total = conn
+            .query_row(
+                &format!(
+                    "SELECT {} FROM documents_view d WHERE {} AND ({})",
+                    count, NOT_DELETED, predicate
+                ),
+                rusqlite::params_from_iter(params),
+                |row| row.get(0),
+            )
+            .map_err(SearchError::DatabaseError)?;
+
+        let facets = if facets {
+            Some(SearchFacets::load(conn, query)?)
+        } else {
+            None
+        };
+
+        Ok(SearchResults {
+            hits: SearchHit::search(conn, query, limit, offset)?,
+            total,
+            facets,
+        })
+    }
+}
@@ -0,0 +1,261 @@
+use std::collections::HashMap;
+
+use crate::{
+    engine::extractor::formats::EXTRACTOR_CONTENT_MODE,
+    entities::{container::ContainerType, document::DocumentStatus, path_and_prefix},
+};
+
+#[derive(Debug)]
+pub enum StampError {
+    DatabaseError(rusqlite::Error),
+}
+
+/// Size and modification time of an indexed file, as they were when it was
+/// last extracted.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FileStamp {
+    size: Option<i64>,
+    modified: Option<i64>,
+}
+
+impl FileStamp {
+    pub fn get_size(&self) -> Option<i64> {
+        self.size
+    }
+
+    pub fn get_modified(&self) -> Option<i64> {
+        self.modified
+    }
+
+    /// Whether a file of this size and modification time can be skipped. A
+    /// file indexed without either is always extracted again.
+    pub fn is_unchanged(&self, size: Option<i64>, modified: Option<i64>) -> bool {
+        self.size.is_some()
+            && self.modified.is_some()
+            && self.size == size
+            && self.modified == modified
+    }
+
+    /// Stamps of the files of folders at or under `path`, and of the archives
+    /// found there, by full path. Deleted documents have none, so a file that
+    /// comes back is extracted again, and neither have transient failures,
+    /// so the next scan tries them again once their retries are used up, nor
+    /// documents extracted in another `EXTRACTOR_CONTENT_MODE`.
+    pub fn list_under_path(
+        conn: &rusqlite::Connection,
+        path: &str,
+    ) -> Result<HashMap<String, FileStamp>, StampError> {
+        let (path, prefix) = path_and_prefix(path);
+
+        let mut stmt = conn
+            .prepare(
+                "SELECT path, size, modified FROM documents_view
+                WHERE container_type = ?1 AND status != ?5
+                    AND (container_path = ?3 OR substr(container_path, 1, length(?4)) = ?4)
+                    AND NOT (status = ?6 AND id IN (
+                        SELECT document_id FROM extraction_errors WHERE permanent = 0
+                    ))
+                    AND (content_mode IS NULL OR content_mode = ?7)
+                UNION ALL
+                SELECT path, size, modified FROM containers
+                WHERE type = ?2 AND substr(path, 1, length(?4)) = ?4",
+            )
+            .map_err(StampError::DatabaseError)?;
+
+        let rows = stmt
+            .query_map(
+                rusqlite::params![
+                    ContainerType::Folder.to_str(),
+                    ContainerType::Archive.to_str(),
+                    path,
+                    prefix,
+                    DocumentStatus::Deleted.to_str(),
+                    DocumentStatus::Failed.to_str(),
+                    EXTRACTOR_CONTENT_MODE.to_str()
+                ],
+                |row| {
+                    Ok((
+                        row.get::<_, String>(0)?,
+                        FileStamp {
+                            size: row.get(1)?,
+                            modified: row.get(2)?,
+                        },
+                    ))
+                },
+            )
+            .map_err(StampError::DatabaseError)?;
+
+        rows.collect::<Result<HashMap<_, _>, _>>()
+            .map_err(StampError::DatabaseError)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use super::*;
+    use crate::{
+        engine::{extractor::formats::ContentMode, scanner::ScannedDocument},
+        entities::{
+            container::Container,
+            document::Document,
+            failure::{ExtractionFailure, FailureKind},
+        },
+        storage::StorageEngine,
+    };
+
+    fn stamp(size: i64, modified: i64) -> FileStamp {
+        FileStamp {
+            size: Some(size),
+            modified: Some(modified),
+        }
+    }
+
+    fn container(
+        conn: &mut rusqlite::Connection,
+        path: &str,
+        container_type: ContainerType,
+        stamp: Option<FileStamp>,
+    ) -> i64 {
+        let mut container = Container::new(0, path.to_string(), container_type);
+        if let Some(stamp) = stamp {
+            container.set_stamp(stamp.size, stamp.modified);
+        }
+        container.save(conn).expect("Failed to save container");
+        container.get_id()
+    }
+
+    fn document(container_id: i64, path: &str) -> ScannedDocument {
+        let mut document = Document::from_path(Path::new(path));
+        document.set_container_id(container_id);
+        document.set_size(Some(10));
+        document.set_modified(Some(100));
+        document.set_status(DocumentStatus::Extracted);
+        document.set_content_mode(*EXTRACTOR_CONTENT_MODE);
+
+        ScannedDocument {
+            container_type: ContainerType::Folder,
+            document,
+            job: None,
+            failure: None,
+        }
+    }
+
+    fn failed(container_id: i64, path: &str, kind: FailureKind) -> ScannedDocument {
+        let mut scanned = document(container_id, path);
+        scanned.document.set_status(DocumentStatus::Failed);
+        scanned.failure = Some(Box::new(ExtractionFailure::new(
+            kind,
+            "failed".to_string(),
+            "Test",
+        )));
+        scanned
+    }
+
+    fn save(conn: &mut rusqlite::Connection, documents: Vec<ScannedDocument>) -> Vec<i64> {
+        Document::save_bulk(conn, documents, &mut HashMap::new())
+            .expect("Failed to save batch")
+            .iter()
+            .filter_map(|saved| saved.get_id())
+            .collect()
+    }
+
+    fn listed(conn: &rusqlite::Connection, path: &str) -> Vec<String> {
+        let mut paths: Vec<String> = FileStamp::list_under_path(conn, path)
+            .expect("Failed to list stamps")
+            .into_keys()
+            .collect();
+        paths.sort();
+        paths
+    }
+
+    #[test]
+    fn unchanged_only_when_size_and_modified_match() {
+        let stored = stamp(10, 100);
+        assert!(stored.is_unchanged(Some(10), Some(100)));
+        assert!(!stored.is_unchanged(Some(11), Some(100)));
+        assert!(!stored.is_unchanged(Some(10), Some(101)));
+        assert!(!stored.is_unchanged(None, Some(100)));
+    }
+
+    #[test]
+    fn a_file_indexed_without_a_stamp_is_always_extracted() {
+        let stored = FileStamp {
+            size: None,
+            modified: Some(100),
+        };
+        assert!(!stored.is_unchanged(None, Some(100)));
+    }
+
+    #[test]
+    fn lists_files_and_archives_under_the_folder() {
+        let mut conn = StorageEngine::open_in_memory();
+        let data = container(&mut conn, "/data", ContainerType::Folder, None);
+        let sub = container(&mut conn, "/data/sub", ContainerType::Folder, None);
+        let other = container(&mut conn, "/database", ContainerType::Folder, None);
+        container(
+            &mut conn,
+            "/data/z.zip",
+            ContainerType::Archive,
+            Some(stamp(20, 200)),
+        );
+        save(
+            &mut conn,
+            vec![
+                document(data, "/data/a.txt"),
+                document(sub, "/data/sub/b.txt"),
+                document(other, "/database/c.txt"),
+            ],
+        );
+
+        assert_eq!(
+            listed(&conn, "/data/"),
+            vec!["/data/a.txt", "/data/sub/b.txt", "/data/z.zip"]
+        );
+        assert_eq!(listed(&conn, "/data/sub"), vec!["/data/sub/b.txt"]);
+        assert_eq!(
+            FileStamp::list_under_path(&conn, "/data").unwrap()["/data/z.zip"],
+            stamp(20, 200)
+        );
+    }
+
+    #[test]
+    fn leaves_out_deleted_documents_transient_failures_and_other_content_modes() {
+        let mut conn = StorageEngine::open_in_memory();
+        let data = container(&mut conn, "/data", ContainerType::Folder, None);
+
+        let mut other_mode = document(data, "/data/other_mode.txt");
+        other_mode
+            .document
+            .set_content_mode(match *EXTRACTOR_CONTENT_MODE {
+                ContentMode::Tokens => ContentMode::FullText,
+                ContentMode::FullText => ContentMode::Tokens,
+            });
+        let ids = save(
+            &mut conn,
+            vec![
+                document(data, "/data/deleted.txt"),
+                document(data, "/data/kept.txt"),
+                failed(data, "/data/permanent.pdf", FailureKind::InvalidFormat),
+                failed(data, "/data/transient.pdf", FailureKind::Io),
+                other_mode,
+            ],
+        );
+        Document::mark_deleted(&mut conn, &ids[..1]).expect("Failed to mark deleted");
+
+        assert_eq!(
+            listed(&conn, "/data"),
+            vec!["/data/kept.txt", "/data/permanent.pdf"]
+        );
+    }
+
+    #[test]
+    fn the_root_lists_everything() {
+        let mut conn = StorageEngine::open_in_memory();
+        let data = container(&mut conn, "/data", ContainerType::Folder, None);
+        save(&mut conn, vec![document(data, "/data/a.txt")]);
+
+        assert_eq!(listed(&conn, "/"), vec!["/data/a.txt"]);
+    }
+}
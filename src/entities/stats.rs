@@ -0,0 +1,78 @@
+use std::str::FromStr;
+
+use crate::entities::document::DocumentStatus;
+
+#[derive(Debug)]
+pub enum StatsError {
+    DatabaseError(rusqlite::Error),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct IndexStats {
+    documents: i64,
+    indexed_documents: i64,
+    folders: i64,
+    archives: i64,
+    documents_by_status: Vec<(DocumentStatus, i64)>,
+}
+
+impl IndexStats {
+    pub fn get_documents(&self) -> i64 {
+        self.documents
+    }
+
+    pub fn get_indexed_documents(&self) -> i64 {
+        self.indexed_documents
+    }
+
+    pub fn get_folders(&self) -> i64 {
+        self.folders
+    }
+
+    pub fn get_archives(&self) -> i64 {
+        self.archives
+    }
+
+    pub fn get_containers(&self) -> i64 {
+        self.folders + self.archives
+    }
+
+    pub fn get_documents_by_status(&self) -> &[(DocumentStatus, i64)] {
+        &self.documents_by_status
+    }
+
+    pub fn load(conn: &rusqlite::Connection) -> Result<IndexStats, StatsError> {
+        let count = |sql: &str| -> Result<i64, StatsError> {
+            conn.query_row(sql, [], |row| row.get(0))
+                .map_err(StatsError::DatabaseError)
+        };
+
+        let mut stats = IndexStats {
+            documents: count("SELECT COUNT(*) FROM documents")?,
+            indexed_documents: count("SELECT COUNT(*) FROM index_documents")?,
+            folders: count("SELECT COUNT(*) FROM containers WHERE type = 'Folder'")?,
+            archives: count("SELECT COUNT(*) FROM containers WHERE type = 'Archive'")?,
+            documents_by_status: Vec::new(),
+        };
+
+        let mut stmt = conn
+            .prepare("SELECT status, COUNT(*) FROM documents GROUP BY status ORDER BY status")
+            .map_err(StatsError::DatabaseError)?;
+
+        let rows = stmt
+            .query_map([], |row| {
+                let status_str: String = row.get(0)?;
+                let status =
+                    DocumentStatus::from_str(&status_str).expect("Invalid document status");
+
+                Ok((status, row.get(1)?))
+            })
+            .map_err(StatsError::DatabaseError)?;
+
+        stats.documents_by_status = rows
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(StatsError::DatabaseError)?;
+
+        Ok(stats)
+    }
+}
@@ -0,0 +1,358 @@
+use std::{fmt::Display, io::Write, str::FromStr};
+
+use chrono::DateTime;
+use serde_json::{Map, Value, json};
+
+use crate::entities::{listing::DocumentListing, search::SearchHit};
+
+#[derive(Debug)]
+pub enum ExportError {
+    IoError(std::io::Error),
+    JsonError(serde_json::Error),
+    UnknownFormat(String),
+    UnknownColumn(String),
+}
+
+impl From<std::io::Error> for ExportError {
+    fn from(err: std::io::Error) -> Self {
+        ExportError::IoError(err)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ExportFormat {
+    /// Pretty-printed JSON array.
+    #[default]
+    Json,
+    /// One compact JSON object per line, flushed as it is written.
+    Ndjson,
+    /// RFC 4180 CSV with a header row.
+    Csv,
+}
+
+impl FromStr for ExportFormat {
+    type Err = ExportError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "json" => Ok(ExportFormat::Json),
+            "ndjson" | "jsonl" => Ok(ExportFormat::Ndjson),
+            "csv" => Ok(ExportFormat::Csv),
+            _ => Err(ExportError::UnknownFormat(s.to_string())),
+        }
+    }
+}
+
+impl ExportFormat {
+    pub fn to_str(&self) -> &str {
+        match self {
+            ExportFormat::Json => "json",
+            ExportFormat::Ndjson => "ndjson",
+            ExportFormat::Csv => "csv",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Column {
+    Id,
+    Path,
+    Filename,
+    Extension,
+    Status,
+    ContainerId,
+    ContainerType,
+    ContainerPath,
+    Size,
+    /// RFC 3339 timestamp (UTC).
+    Modified,
+    Rank,
+    Snippet,
+    /// SHA-256 of the content.
+    Hash,
+    /// Identical documents a collapsed hit stands for.
+    Copies,
+}
+
+impl FromStr for Column {
+    type Err = ExportError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Column::ALL
+            .iter()
+            .find(|column| column.to_str() == s.trim().to_lowercase())
+            .copied()
+            .ok_or_else(|| ExportError::UnknownColumn(s.to_string()))
+    }
+}
+
+impl Display for Column {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.to_str())
+    }
+}
+
+impl Column {
+    pub const ALL: [Column; 14] = [
+        Column::Id,
+        Column::Path,
+        Column::Filename,
+        Column::Extension,
+        Column::Status,
+        Column::ContainerId,
+        Column::ContainerType,
+        Column::ContainerPath,
+        Column::Size,
+        Column::Modified,
+        Column::Rank,
+        Column::Snippet,
+        Column::Hash,
+        Column::Copies,
+    ];
+
+    pub fn to_str(&self) -> &str {
+        match self {
+            Column::Id => "id",
+            Column::Path => "path",
+            Column::Filename => "filename",
+            Column::Extension => "extension",
+            Column::Status => "status",
+            Column::ContainerId => "container_id",
+            Column::ContainerType => "container_type",
+            Column::ContainerPath => "container_path",
+            Column::Size => "size",
+            Column::Modified => "modified",
+            Column::Rank => "rank",
+            Column::Snippet => "snippet",
+            Column::Hash => "hash",
+            Column::Copies => "copies",
+        }
+    }
+
+    /// Comma-separated column names, e.g. `id,path,size`.
+    pub fn parse_list(list: &str) -> Result<Vec<Column>, ExportError> {
+        list.split(',')
+            .filter(|name| !name.trim().is_empty())
+            .map(Column::from_str)
+            .collect()
+    }
+}
+
+/// Row type that can be exported. Columns a type does not carry are written
+/// as `null` (JSON) or an empty field (CSV).
+pub trait Exportable {
+    fn default_columns() -> &'static [Column];
+
+    fn column_value(&self, column: Column) -> Value;
+}
+
+impl Exportable for SearchHit {
+    fn default_columns() -> &'static [Column] {
+        &[
+            Column::Rank,
+            Column::Id,
+            Column::Path,
+            Column::ContainerType,
+            Column::Extension,
+            Column::Snippet,
+        ]
+    }
+
+    fn column_value(&self, column: Column) -> Value {
+        match column {
+            Column::Id => json!(self.get_document_id()),
+            Column::Path => json!(self.get_path()),
+            Column::Extension => json!(self.get_extension()),
+            Column::ContainerType => json!(self.get_container_type().to_str()),
+            Column::Rank => json!(self.get_rank()),
+            Column::Snippet => json!(self.get_snippet()),
+            Column::Hash => json!(self.get_hash()),
+            Column::Copies => json!(self.get_copies()),
+            _ => Value::Null,
+        }
+    }
+}
+
+impl Exportable for DocumentListing {
+    fn default_columns() -> &'static [Column] {
+        &[
+            Column::Id,
+            Column::Path,
+            Column::Extension,
+            Column::Status,
+            Column::ContainerType,
+            Column::Size,
+            Column::Modified,
+        ]
+    }
+
+    fn column_value(&self, column: Column) -> Value {
+        match column {
+            Column::Id => json!(self.get_id()),
+            Column::Path => json!(self.get_path()),
+            Column::Filename => json!(self.get_filename()),
+            Column::Extension => json!(self.get_extension()),
+            Column::Status => json!(self.get_status().to_str()),
+            Column::ContainerId => json!(self.get_container_id()),
+            Column::ContainerType => json!(self.get_container_type().to_str()),
+            Column::ContainerPath => json!(self.get_container_path()),
+            Column::Size => json!(self.get_size()),
+            Column::Modified => json!(
+                self.get_modified()
+                    .and_then(|secs| DateTime::from_timestamp(secs, 0))
+                    .map(|dt| dt.to_rfc3339())
+            ),
+            Column::Hash => json!(self.get_hash()),
+            Column::Rank | Column::Snippet | Column::Copies => Value::Null,
+        }
+    }
+}
+
+/// Serializes rows in one format and a fixed column order.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct Exporter {
+    format: ExportFormat,
+    columns: Vec<Column>,
+}
+
+impl Exporter {
+    /// An empty `columns` list selects the row type's default columns.
+    pub fn new(format: ExportFormat, columns: Vec<Column>) -> Self {
+        Exporter { format, columns }
+    }
+
+    pub fn get_format(&self) -> ExportFormat {
+        self.format
+    }
+
+    pub fn get_columns(&self) -> &[Column] {
+        &self.columns
+    }
+
+    pub fn export<T: Exportable, W: Write>(
+        &self,
+        writer: &mut W,
+        rows: &[T],
+    ) -> Result<(), ExportError> {
+        let mut export = self.writer::<T, W>(writer)?;
+        for row in rows {
+            export.write(row)?;
+        }
+        export.finish()
+    }
+
+    /// Incremental writer, for exports produced page by page.
+    pub fn writer<'a, T: Exportable, W: Write>(
+        &self,
+        writer: &'a mut W,
+    ) -> Result<ExportWriter<'a, W>, ExportError> {
+        let columns = if self.columns.is_empty() {
+            T::default_columns().to_vec()
+        } else {
+            self.columns.clone()
+        };
+
+        let mut export = ExportWriter {
+            format: self.format,
+            columns,
+            writer,
+            rows: 0,
+        };
+        export.begin()?;
+
+        Ok(export)
+    }
+}
+
+pub struct ExportWriter<'a, W: Write> {
+    format: ExportFormat,
+    columns: Vec<Column>,
+    writer: &'a mut W,
+    rows: usize,
+}
+
+impl<W: Write> ExportWriter<'_, W> {
+    pub fn get_rows(&self) -> usize {
+        self.rows
+    }
+
+    fn begin(&mut self) -> Result<(), ExportError> {
+        match self.format {
+            ExportFormat::Json => write!(self.writer, "[")?,
+            ExportFormat::Ndjson => {}
+            ExportFormat::Csv => {
+                let header: Vec<&str> = self.columns.iter().map(Column::to_str).collect();
+                writeln!(self.writer, "{}", header.join(","))?;
+            }
+        }
+        Ok(())
+    }
+
+    pub fn write<T: Exportable>(&mut self, row: &T) -> Result<(), ExportError> {
+        match self.format {
+            ExportFormat::Json => {
+                let object = serde_json::to_string_pretty(&self.object(row))
+                    .map_err(ExportError::JsonError)?;
+                let separator = if self.rows == 0 { "" } else { "," };
+                write!(
+                    self.writer,
+                    "{}\n  {}",
+                    separator,
+                    object.replace('\n', "\n  ")
+                )?;
+            }
+            ExportFormat::Ndjson => {
+                let object = self.object(row);
+                serde_json::to_writer(&mut *self.writer, &object)
+                    .map_err(ExportError::JsonError)?;
+                writeln!(self.writer)?;
+                self.writer.flush()?;
+            }
+            ExportFormat::Csv => {
+                let fields: Vec<String> = self
+                    .columns
+                    .iter()
+                    .map(|column| csv_field(&row.column_value(*column)))
+                    .collect();
+                writeln!(self.writer, "{}", fields.join(","))?;
+            }
+        }
+
+        self.rows += 1;
+        Ok(())
+    }
+
+    pub fn finish(self) -> Result<(), ExportError> {
+        if self.format == ExportFormat::Json {
+            if self.rows > 0 {
+                writeln!(self.writer)?;
+            }
+            writeln!(self.writer, "]")?;
+        }
+        self.writer.flush()?;
+        Ok(())
+    }
+
+    fn object<T: Exportable>(&self, row: &T) -> Value {
+        let object: Map<String, Value> = self
+            .columns
+            .iter()
+            .map(|column| (column.to_string(), row.column_value(*column)))
+            .collect();
+        Value::Object(object)
+    }
+}
+
+fn csv_field(value: &Value) -> String {
+    let text = match value {
+        Value::Null => return String::new(),
+        Value::String(s) => s.clone(),
+        other => other.to_string(),
+    };
+
+    if text.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", text.replace('"', "\"\""))
+    } else {
+        text
+    }
+}
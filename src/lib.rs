@@ -1,6 +1,8 @@
 pub mod api;
 pub mod engine;
 pub mod entities;
+pub mod export;
+pub mod metrics;
 pub mod storage;
 
 #[derive(Debug)]
@@ -18,6 +20,10 @@ use tracing_subscriber::{
 };
 
 pub fn init_logging() {
+    init_logging_with_level("info");
+}
+
+pub fn init_logging_with_level(level: &str) {
     // file logger (NO ANSI)
     let file_appender = RollingFileAppender::new(Rotation::HOURLY, "logs", "rsearch");
 
@@ -32,7 +38,7 @@ pub fn init_logging() {
         )
         .with_writer(file_appender);
 
-    // console logger (ANSI OK)
+    // console logger (ANSI OK), on stderr so exports can be piped
     let stdout_layer = fmt::layer()
         .with_ansi(true)
         .event_format(
@@ -42,9 +48,13 @@ pub fn init_logging() {
                 .with_thread_ids(true)
                 .with_thread_names(true),
         )
-        .with_writer(std::io::stdout);
+        .with_writer(std::io::stderr);
 
-    let filter = EnvFilter::from_default_env().add_directive("rsearch=info".parse().unwrap());
+    let filter = EnvFilter::from_default_env().add_directive(
+        format!("rsearch={}", level)
+            .parse()
+            .unwrap_or_else(|_| "rsearch=info".parse().unwrap()),
+    );
 
     tracing_subscriber::registry()
         .with(filter)
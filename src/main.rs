@@ -1,73 +1,748 @@
+use std::{path::PathBuf, str::FromStr, thread::JoinHandle};
+
+use clap::{Args, Parser, Subcommand};
 use rsearch::{
+    api::{API_SERVER_ADDRESS, server::Server},
     engine::{
-        EngineTask, PipelineStage,
-        extractor::{Extractor, commands::ExtractorCommand},
-        scanner::{FiltersMode, Scanner, filters::Filter},
-        unbounded_channel,
+        Engine,
+        builder::EngineBuilder,
+        extractor::isolation,
+        jobs::{ScanEvent, ScanJob},
+        scaling::AutoscalePolicy,
+        scanner::{FiltersMode, filters::Filter},
+    },
+    entities::{failure::FailureKind, listing::DocumentListing},
+    export::{Column, ExportFormat, Exporter},
+    init_logging_with_level,
+    storage::{
+        STORAGE_DATABASE_PATH,
+        query::{Query, SearchMode},
     },
-    init_logging,
-    storage::StorageEngine,
 };
 use tracing::warn;
 
-fn main() {
-    init_logging();
-    StorageEngine::initialize().expect("Failed to initialize storage engine");
+const LOG_TARGET: &str = "main";
+
+const LIST_PAGE_SIZE: usize = 1000;
+
+#[derive(Debug, Parser)]
+#[command(
+    name = "rsearch",
+    version,
+    about = "Document scanner, extractor and full-text search"
+)]
+struct Cli {
+    /// Path of the SQLite database
+    #[arg(long, global = true, default_value_t = STORAGE_DATABASE_PATH.to_string())]
+    db: String,
+
+    /// Number of extractor worker threads
+    #[arg(long, global = true, default_value_t = 16)]
+    extractor_workers: usize,
+
+    /// Number of decompressor worker threads, which read ZIP archives
+    #[arg(long, global = true, default_value_t = 2)]
+    decompressor_workers: usize,
+
+    /// Scale extractor workers between 1 and --extractor-workers by queue
+    /// depth and CPU load
+    #[arg(long, global = true)]
+    autoscale: bool,
+
+    /// Log level for the rsearch targets (error, warn, info, debug, trace)
+    #[arg(long, global = true, default_value = "info")]
+    log_level: String,
+
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Debug, Subcommand)]
+enum Command {
+    /// Scan and index the given folders
+    Index {
+        #[arg(required = true)]
+        paths: Vec<PathBuf>,
+
+        #[command(flatten)]
+        filters: FilterArgs,
+    },
+    /// Index the given folders, then keep the index up to date as files
+    /// change until interrupted
+    Watch {
+        #[arg(required = true)]
+        paths: Vec<PathBuf>,
+
+        #[command(flatten)]
+        filters: FilterArgs,
+    },
+    /// Full-text search over the index
+    Search {
+        query: String,
+
+        #[arg(long, default_value_t = 20)]
+        limit: usize,
+
+        #[arg(long, default_value_t = 0)]
+        offset: usize,
+
+        /// Print match counts per extension, container type, container and status
+        #[arg(long)]
+        facets: bool,
+
+        /// Tolerate typos and match parts of words (trigram index)
+        #[arg(long)]
+        fuzzy: bool,
+
+        /// Return identical files (same content hash) as one hit
+        #[arg(long)]
+        collapse: bool,
+
+        #[command(flatten)]
+        export: ExportArgs,
+    },
+    /// List indexed documents, optionally filtered by a query
+    List {
+        query: Option<String>,
+
+        /// Maximum number of documents (all when omitted)
+        #[arg(long)]
+        limit: Option<usize>,
+
+        #[arg(long, default_value_t = 0)]
+        offset: usize,
+
+        #[command(flatten)]
+        export: ExportArgs,
+    },
+    /// Locate documents by path or file name (substrings and globs)
+    Find {
+        pattern: String,
+
+        #[arg(long, default_value_t = 50)]
+        limit: usize,
+    },
+    /// List documents whose content could not be extracted, and why
+    Failures {
+        /// Only this kind: NotFound, PermissionDenied, Io, InvalidFormat, Unavailable,
+        /// Crashed, TimedOut or Other
+        #[arg(long)]
+        kind: Option<String>,
 
-    let (scanner_tx, scanner_rx) = unbounded_channel::<String>();
-    let (extractor_tx, extractor_rx) = unbounded_channel::<ExtractorCommand>();
+        #[arg(long, default_value_t = 50)]
+        limit: usize,
 
-    let mut storage = StorageEngine::default();
-    let storage_handle = storage.run();
+        #[arg(long, default_value_t = 0)]
+        offset: usize,
+    },
+    /// List groups of identical files across folders and archives, largest waste first
+    Duplicates {
+        #[arg(long, default_value_t = 50)]
+        limit: usize,
+
+        #[arg(long, default_value_t = 0)]
+        offset: usize,
+    },
+    /// Extract again the documents whose extraction failed
+    RetryFailed {
+        /// Also retry permanent failures, e.g. after installing a missing extractor
+        #[arg(long)]
+        all: bool,
+    },
+    /// Print index statistics
+    Stats,
+    /// Extract one document to stdout, run by workers in process isolation
+    #[command(name = isolation::CHILD_SUBCOMMAND, hide = true)]
+    ExtractChild { path: PathBuf },
+    /// Drop the indexed documents under the given folders and index them again
+    Reindex {
+        #[arg(required = true)]
+        paths: Vec<PathBuf>,
 
-    let mut scanner = Scanner::new(scanner_tx.clone(), scanner_rx, extractor_tx.clone());
-    let mut filter1 = Filter::default();
-    filter1.set_case_sensitive(false);
-    //filter1.set_filename_contains("report");
-    filter1.set_extension_is("zip");
+        #[command(flatten)]
+        filters: FilterArgs,
+    },
+    /// Mark deleted the documents whose file or archive no longer exists
+    Reconcile {
+        /// Indexed folders to check (the whole index when omitted)
+        paths: Vec<PathBuf>,
 
-    let mut filter2 = Filter::default();
-    filter2.set_extension_is("pdf");
+        /// Then remove deleted documents and empty containers
+        #[arg(long)]
+        prune: bool,
+    },
+    /// Remove deleted documents and empty containers from the index
+    Prune,
+    /// Run the local HTTP/JSON server
+    Serve {
+        #[arg(long, default_value_t = API_SERVER_ADDRESS.to_string())]
+        address: String,
 
-    let mut filter3 = Filter::default();
-    filter3.set_extension_is("txt");
+        /// Expose pipeline metrics on GET /metrics (Prometheus text format)
+        #[arg(long)]
+        metrics: bool,
 
-    scanner.set_filters_mode(FiltersMode::Or);
+        /// Keep the folders scanned through POST /scan up to date as files change
+        #[arg(long)]
+        watch: bool,
 
-    scanner.add_filter(filter1);
-    scanner.add_filter(filter2);
-    scanner.add_filter(filter3);
+        #[command(flatten)]
+        filters: FilterArgs,
+    },
+}
 
-    let mut extractor = Extractor::new(
-        storage.get_channel_tx().clone(),
-        scanner.clone(),
-        extractor_tx,
-        extractor_rx,
-    );
+#[derive(Debug, Args)]
+struct FilterArgs {
+    /// File extensions to index (repeatable)
+    #[arg(long = "ext", default_values = ["pdf", "docx", "txt", "zip"])]
+    extensions: Vec<String>,
 
-    let scanner_handle = scanner.init().expect("Failed to start scanner");
-    let extractor_handles = extractor.init(16).expect("Failed to initialize extractor");
+    /// Only index files whose name contains this substring
+    #[arg(long)]
+    name_contains: Option<String>,
 
-    // let mut classifier = Classifier::default();
-    // let classifier_handles = classifier.init(1).expect("Failed to initialize classifier");
+    /// Skip files whose name contains this substring
+    #[arg(long)]
+    name_not_contains: Option<String>,
 
-    let api = rsearch::api::Api::new(scanner_tx.clone());
+    /// Only index files whose directory contains this substring
+    #[arg(long)]
+    dir_contains: Option<String>,
 
-    api.scan_path("/home/roothunter/Documents".to_string())
-        .expect("Failed to send scan command");
+    /// Skip files whose directory contains this substring
+    #[arg(long)]
+    dir_not_contains: Option<String>,
 
-    for handle in scanner_handle {
-        handle.join().expect("Scanner thread panicked");
-        warn!(target: "main", "Scanner thread has finished");
+    /// Only index files whose name matches this regex
+    #[arg(long)]
+    name_regex: Option<String>,
+
+    /// Match name and directory conditions case-insensitively
+    #[arg(long)]
+    ignore_case: bool,
+}
+
+impl FilterArgs {
+    fn build_filter(&self) -> Filter {
+        let mut filter = Filter::default();
+        filter.set_case_sensitive(!self.ignore_case);
+
+        if let Some(ref substring) = self.name_contains {
+            filter.set_filename_contains(substring);
+        }
+        if let Some(ref substring) = self.name_not_contains {
+            filter.set_filename_not_contains(substring);
+        }
+        if let Some(ref substring) = self.dir_contains {
+            filter.set_dir_contains(substring);
+        }
+        if let Some(ref substring) = self.dir_not_contains {
+            filter.set_dir_not_contains(substring);
+        }
+        if let Some(ref pattern) = self.name_regex {
+            filter
+                .set_filename_regex(pattern)
+                .expect("Invalid filename regex");
+        }
+
+        filter
     }
 
-    for handle in extractor_handles {
-        handle.join().expect("Extractor thread panicked");
-        warn!(target: "main", "Extractor thread has finished");
+    /// One filter per extension, each carrying the shared name/dir conditions,
+    /// combined with `FiltersMode::Or`.
+    fn apply(&self, builder: EngineBuilder) -> EngineBuilder {
+        let mut builder = builder.filters_mode(FiltersMode::Or);
+
+        for extension in &self.extensions {
+            let mut filter = self.build_filter();
+            filter.set_extension_is(extension);
+            builder = builder.filter(filter);
+        }
+
+        if self.extensions.is_empty() {
+            builder = builder.filter(self.build_filter());
+        }
+
+        builder
+    }
+}
+
+#[derive(Debug, Args)]
+struct ExportArgs {
+    /// Output format: json, ndjson or csv
+    #[arg(long)]
+    format: Option<String>,
+
+    /// Comma-separated columns (id, path, filename, extension, status,
+    /// container_id, container_type, container_path, size, modified, rank, snippet)
+    #[arg(long)]
+    columns: Option<String>,
+}
+
+impl ExportArgs {
+    fn exporter(&self) -> Exporter {
+        let format = self
+            .format
+            .as_deref()
+            .map(ExportFormat::from_str)
+            .transpose()
+            .unwrap_or_else(|e| exit_with_error("Invalid format", e));
+        let columns = self
+            .columns
+            .as_deref()
+            .map(Column::parse_list)
+            .transpose()
+            .unwrap_or_else(|e| exit_with_error("Invalid columns", e))
+            .unwrap_or_default();
+
+        Exporter::new(format.unwrap_or_default(), columns)
+    }
+
+    fn is_set(&self) -> bool {
+        self.format.is_some() || self.columns.is_some()
     }
+}
+
+/// Engine for a command: scanning with `filters`, or storage only.
+fn start_engine(cli: &Cli, filters: Option<&FilterArgs>) -> Engine {
+    let builder = engine_builder(cli).extraction(filters.is_some());
+    let builder = match filters {
+        Some(filters) => filters.apply(builder),
+        None => builder,
+    };
+
+    builder.start().expect("Failed to start the pipeline")
+}
+
+/// Scanning engine that watches the folders it scans.
+fn start_engine_watching(cli: &Cli, filters: &FilterArgs) -> Engine {
+    filters
+        .apply(engine_builder(cli))
+        .watch(true)
+        .start()
+        .expect("Failed to start the pipeline")
+}
+
+/// Scanner and extractor without filters, for rescans of known files.
+fn start_engine_unfiltered(cli: &Cli) -> Engine {
+    engine_builder(cli)
+        .start()
+        .expect("Failed to start the pipeline")
+}
+
+fn engine_builder(cli: &Cli) -> EngineBuilder {
+    let builder = Engine::builder()
+        .database_path(&cli.db)
+        .extractor_workers(cli.extractor_workers)
+        .decompressor_workers(cli.decompressor_workers);
+
+    if cli.autoscale {
+        builder.autoscale(AutoscalePolicy::new(1, cli.extractor_workers))
+    } else {
+        builder
+    }
+}
+
+fn shutdown(engine: Engine) {
+    engine
+        .shutdown()
+        .unwrap_or_else(|e| panic!("Failed to shut down the pipeline: {:?}", e));
+    warn!(target: LOG_TARGET, "Pipeline has finished");
+}
+
+/// Prints the job's progress to stderr as batches are committed, then its
+/// summary once it completes.
+fn report_progress(job: &ScanJob) -> JoinHandle<()> {
+    let events = job.subscribe();
+
+    std::thread::spawn(move || {
+        for event in events {
+            match event {
+                ScanEvent::Progress(progress) => eprintln!(
+                    "indexing {}: {:.0}% done, {} failures",
+                    progress.get_path(),
+                    progress.get_percent(),
+                    progress.get_failed()
+                ),
+                ScanEvent::Completed(progress) => eprintln!(
+                    "indexed {}: {} documents stored, {} failed, {} skipped, {} unchanged",
+                    progress.get_path(),
+                    progress.get_committed(),
+                    progress.get_failed(),
+                    progress.get_skipped(),
+                    progress.get_unchanged()
+                ),
+            }
+        }
+    })
+}
+
+fn exit_with_error(message: &str, err: impl std::fmt::Debug) -> ! {
+    eprintln!("{}: {:?}", message, err);
+    std::process::exit(1);
+}
+
+fn canonical_path(path: &PathBuf) -> String {
+    std::fs::canonicalize(path)
+        .unwrap_or_else(|e| panic!("Invalid path {}: {}", path.display(), e))
+        .to_string_lossy()
+        .to_string()
+}
+
+/// Like `canonical_path`, for folders that may no longer exist.
+fn absolute_path(path: &PathBuf) -> String {
+    std::fs::canonicalize(path)
+        .or_else(|_| std::path::absolute(path))
+        .unwrap_or_else(|e| panic!("Invalid path {}: {}", path.display(), e))
+        .to_string_lossy()
+        .to_string()
+}
+
+fn main() {
+    let cli = Cli::parse();
+
+    // Its stdout and stderr are read by the parent worker.
+    if let Command::ExtractChild { path } = &cli.command {
+        std::process::exit(isolation::child_main(path));
+    }
+
+    init_logging_with_level(&cli.log_level);
+
+    match &cli.command {
+        Command::Index { paths, filters } => {
+            let engine = start_engine(&cli, Some(filters));
+            let mut reporters = Vec::new();
+
+            for path in paths {
+                let job = engine
+                    .get_api()
+                    .scan_path(canonical_path(path))
+                    .expect("Failed to send scan command");
+                reporters.push(report_progress(&job));
+            }
+
+            shutdown(engine);
+            for reporter in reporters {
+                let _ = reporter.join();
+            }
+        }
+        Command::Watch { paths, filters } => {
+            let engine = start_engine_watching(&cli, filters);
+
+            for path in paths {
+                let job = engine
+                    .get_api()
+                    .scan_path(canonical_path(path))
+                    .expect("Failed to send scan command");
+                report_progress(&job);
+            }
+
+            // Runs until the process is interrupted.
+            loop {
+                std::thread::park();
+            }
+        }
+        Command::Reindex { paths, filters } => {
+            let engine = start_engine(&cli, Some(filters));
+            let mut reporters = Vec::new();
+
+            for path in paths {
+                let path = canonical_path(path);
+                let purged = engine
+                    .get_api()
+                    .purge_path(&path)
+                    .expect("Failed to purge indexed documents");
+                println!("Purged {} documents under {}", purged, path);
+
+                let job = engine
+                    .get_api()
+                    .scan_path(path)
+                    .expect("Failed to send scan command");
+                reporters.push(report_progress(&job));
+            }
+
+            shutdown(engine);
+            for reporter in reporters {
+                let _ = reporter.join();
+            }
+        }
+        Command::Search {
+            query,
+            limit,
+            offset,
+            facets,
+            fuzzy,
+            collapse,
+            export,
+        } => {
+            let mode = if *fuzzy {
+                SearchMode::Fuzzy
+            } else {
+                SearchMode::Exact
+            };
+
+            let engine = start_engine(&cli, None);
+            let results = engine
+                .get_api()
+                .search_results(query, *limit, *offset, *facets, mode, *collapse)
+                .unwrap_or_else(|e| exit_with_error("Search failed", e));
+
+            if export.is_set() {
+                export
+                    .exporter()
+                    .export(&mut std::io::stdout().lock(), results.get_hits())
+                    .unwrap_or_else(|e| exit_with_error("Export failed", e));
+                return;
+            }
+
+            for hit in results.get_hits() {
+                if hit.get_copies() > 1 {
+                    println!(
+                        "{:.4}\t{}\t{}\t(+{} copies)",
+                        hit.get_rank(),
+                        hit.get_document_id(),
+                        hit.get_path(),
+                        hit.get_copies() - 1
+                    );
+                } else {
+                    println!(
+                        "{:.4}\t{}\t{}",
+                        hit.get_rank(),
+                        hit.get_document_id(),
+                        hit.get_path()
+                    );
+                }
+
+                if let Some(snippet) = hit.get_snippet().filter(|s| !s.is_empty()) {
+                    println!("\t{}", snippet.replace('\n', " "));
+                }
+            }
+
+            if let Some(facets) = results.get_facets() {
+                println!("\n{} matching documents", results.get_total());
+
+                println!("\nextension:");
+                for (extension, count) in facets.get_extensions() {
+                    println!("  {:<40} {}", extension.as_deref().unwrap_or("-"), count);
+                }
+                println!("container type:");
+                for (container_type, count) in facets.get_container_types() {
+                    println!("  {:<40} {}", container_type.to_str(), count);
+                }
+                println!("container:");
+                for (path, count) in facets.get_container_paths() {
+                    println!("  {:<40} {}", path, count);
+                }
+                println!("status:");
+                for (status, count) in facets.get_statuses() {
+                    println!("  {:<40} {}", status.to_str(), count);
+                }
+            }
+        }
+        Command::Find { pattern, limit } => {
+            let engine = start_engine(&cli, None);
+            let hits = engine
+                .get_api()
+                .find(pattern, *limit)
+                .unwrap_or_else(|e| exit_with_error("Find failed", e));
+
+            for hit in hits {
+                println!("{}\t{}", hit.get_document_id(), hit.get_path());
+            }
+        }
+        Command::Failures {
+            kind,
+            limit,
+            offset,
+        } => {
+            let kind = kind.as_deref().map(|kind| {
+                FailureKind::from_str(kind)
+                    .unwrap_or_else(|_| exit_with_error("Invalid kind", kind))
+            });
+
+            let engine = start_engine(&cli, None);
+            let failures = engine
+                .get_api()
+                .extraction_failures(kind, *limit, *offset)
+                .unwrap_or_else(|e| exit_with_error("Listing failures failed", e));
+
+            for failed in failures {
+                let failure = failed.get_failure();
+                println!(
+                    "{}\t{}\t{}\t{}\t{}\t{}",
+                    failed.get_document_id(),
+                    failure.get_kind().to_str(),
+                    failure.get_attempts(),
+                    failure.get_extractor(),
+                    failed.get_path(),
+                    failure.get_message()
+                );
+            }
+        }
+        Command::Duplicates { limit, offset } => {
+            let engine = start_engine(&cli, None);
+            let report = engine
+                .get_api()
+                .duplicates(*limit, *offset)
+                .unwrap_or_else(|e| exit_with_error("Listing duplicates failed", e));
+
+            for group in report.get_groups() {
+                println!(
+                    "{}\t{} copies\t{} bytes\t{} bytes wasted",
+                    group.get_hash(),
+                    group.get_copies().len(),
+                    group.get_size(),
+                    group.get_wasted_bytes()
+                );
+
+                for copy in group.get_copies() {
+                    println!("\t{}\t{}", copy.get_document_id(), copy.get_path());
+                }
+            }
+
+            println!(
+                "{} duplicate groups, {} bytes wasted",
+                report.get_total_groups(),
+                report.get_wasted_bytes()
+            );
+        }
+        Command::RetryFailed { all } => {
+            let engine = start_engine_unfiltered(&cli);
+            let job = engine
+                .get_api()
+                .retry_failed(*all)
+                .unwrap_or_else(|e| exit_with_error("Retrying failures failed", e));
+            let reporter = report_progress(&job);
+
+            shutdown(engine);
+            let _ = reporter.join();
+        }
+        Command::List {
+            query,
+            limit,
+            offset,
+            export,
+        } => {
+            // Reject a bad query before the export header is written.
+            if let Some(query) = query {
+                Query::parse(query).unwrap_or_else(|e| exit_with_error("Invalid query", e));
+            }
+
+            let engine = start_engine(&cli, None);
+            let mut stdout = std::io::stdout().lock();
+            let mut writer = export
+                .exporter()
+                .writer::<DocumentListing, _>(&mut stdout)
+                .unwrap_or_else(|e| exit_with_error("Export failed", e));
+
+            // Page through the index so large listings stream with bounded memory.
+            let mut offset = *offset;
+            let mut remaining = *limit;
+            while remaining != Some(0) {
+                let page = remaining.map_or(LIST_PAGE_SIZE, |n| n.min(LIST_PAGE_SIZE));
+                let documents = engine
+                    .get_api()
+                    .list_documents(query.as_deref(), Some(page), offset)
+                    .unwrap_or_else(|e| exit_with_error("Listing failed", e));
+
+                for document in &documents {
+                    writer
+                        .write(document)
+                        .unwrap_or_else(|e| exit_with_error("Export failed", e));
+                }
+
+                if documents.len() < page {
+                    break;
+                }
+                offset += documents.len();
+                remaining = remaining.map(|n| n - documents.len());
+            }
+
+            writer
+                .finish()
+                .unwrap_or_else(|e| exit_with_error("Export failed", e));
+        }
+        // Handled before logging is set up.
+        Command::ExtractChild { .. } => {}
+        Command::Stats => {
+            let engine = start_engine(&cli, None);
+            let stats = engine.get_api().stats().expect("Failed to load stats");
+
+            println!("documents:         {}", stats.get_documents());
+            println!("indexed documents: {}", stats.get_indexed_documents());
+            println!("folders:           {}", stats.get_folders());
+            println!("archives:          {}", stats.get_archives());
+            for (status, count) in stats.get_documents_by_status() {
+                println!("  {:<16} {}", status.to_str(), count);
+            }
+        }
+        Command::Reconcile { paths, prune } => {
+            let engine = start_engine(&cli, None);
+            let roots: Vec<Option<String>> = if paths.is_empty() {
+                vec![None]
+            } else {
+                paths.iter().map(|path| Some(absolute_path(path))).collect()
+            };
+
+            for root in roots {
+                let summary = engine
+                    .get_api()
+                    .reconcile(root.as_deref())
+                    .unwrap_or_else(|e| exit_with_error("Reconciliation failed", e));
+
+                println!(
+                    "{}: checked {} documents, {} deleted, {} containers vanished",
+                    root.as_deref().unwrap_or("index"),
+                    summary.get_checked(),
+                    summary.get_deleted(),
+                    summary.get_vanished_containers()
+                );
+
+                if *prune {
+                    let summary = engine
+                        .get_api()
+                        .prune(root.as_deref())
+                        .unwrap_or_else(|e| exit_with_error("Pruning failed", e));
+
+                    println!(
+                        "{}: removed {} documents and {} containers",
+                        root.as_deref().unwrap_or("index"),
+                        summary.get_documents(),
+                        summary.get_containers()
+                    );
+                }
+            }
+        }
+        Command::Prune => {
+            let engine = start_engine(&cli, None);
+            let summary = engine
+                .get_api()
+                .prune(None)
+                .unwrap_or_else(|e| exit_with_error("Pruning failed", e));
+
+            println!(
+                "Removed {} documents and {} containers",
+                summary.get_documents(),
+                summary.get_containers()
+            );
+        }
+        Command::Serve {
+            address,
+            metrics,
+            watch,
+            filters,
+        } => {
+            let engine = if *watch {
+                start_engine_watching(&cli, filters)
+            } else {
+                start_engine(&cli, Some(filters))
+            };
+
+            let mut server = Server::new(engine.get_api().clone(), address.clone());
+            server.set_metrics_enabled(*metrics);
+            server.run().expect("Failed to run API server");
 
-    if let Ok(handle) = storage_handle {
-        handle.join().expect("Storage thread panicked");
-        warn!(target: "main", "Storage thread has finished");
+            shutdown(engine);
+        }
     }
 }
@@ -0,0 +1,449 @@
+use std::{
+    collections::BTreeMap,
+    fmt::Write,
+    sync::{
+        Arc, Mutex,
+        atomic::{AtomicI64, AtomicU64, Ordering},
+    },
+    time::Duration,
+};
+
+use once_cell::sync::Lazy;
+
+static METRICS: Lazy<Metrics> = Lazy::new(Metrics::new);
+
+/// Seconds.
+const DURATION_BUCKETS: &[f64] = &[
+    0.001, 0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0, 30.0,
+];
+
+/// Documents per batch.
+const BATCH_SIZE_BUCKETS: &[f64] = &[1.0, 5.0, 10.0, 25.0, 50.0, 100.0, 250.0, 500.0, 1000.0];
+
+/// Process-wide registry, fed by the scanner, the extractor workers, the
+/// decompressor and the storage engine.
+pub fn metrics() -> &'static Metrics {
+    &METRICS
+}
+
+#[derive(Debug, Default)]
+pub struct Counter {
+    value: AtomicU64,
+}
+
+impl Counter {
+    pub fn inc(&self) {
+        self.add(1);
+    }
+
+    pub fn add(&self, count: u64) {
+        self.value.fetch_add(count, Ordering::Relaxed);
+    }
+
+    pub fn get(&self) -> u64 {
+        self.value.load(Ordering::Relaxed)
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct Gauge {
+    value: AtomicI64,
+}
+
+impl Gauge {
+    pub fn set(&self, value: i64) {
+        self.value.store(value, Ordering::Relaxed);
+    }
+
+    pub fn get(&self) -> i64 {
+        self.value.load(Ordering::Relaxed)
+    }
+}
+
+#[derive(Debug)]
+pub struct Histogram {
+    bounds: &'static [f64],
+    /// One count per bound plus `+Inf`, not cumulative.
+    buckets: Vec<AtomicU64>,
+    count: AtomicU64,
+    sum: Mutex<f64>,
+}
+
+impl Histogram {
+    fn new(bounds: &'static [f64]) -> Self {
+        Histogram {
+            bounds,
+            buckets: (0..=bounds.len()).map(|_| AtomicU64::new(0)).collect(),
+            count: AtomicU64::new(0),
+            sum: Mutex::new(0.0),
+        }
+    }
+
+    pub fn observe(&self, value: f64) {
+        let index = self
+            .bounds
+            .iter()
+            .position(|bound| value <= *bound)
+            .unwrap_or(self.bounds.len());
+
+        self.buckets[index].fetch_add(1, Ordering::Relaxed);
+        self.count.fetch_add(1, Ordering::Relaxed);
+        *self.sum.lock().unwrap() += value;
+    }
+
+    pub fn observe_duration(&self, duration: Duration) {
+        self.observe(duration.as_secs_f64());
+    }
+
+    pub fn get_count(&self) -> u64 {
+        self.count.load(Ordering::Relaxed)
+    }
+
+    pub fn get_sum(&self) -> f64 {
+        *self.sum.lock().unwrap()
+    }
+
+    /// Cumulative counts per upper bound, the last one being `+Inf`.
+    pub fn get_buckets(&self) -> Vec<(f64, u64)> {
+        let mut total = 0;
+        self.bounds
+            .iter()
+            .copied()
+            .chain(std::iter::once(f64::INFINITY))
+            .zip(&self.buckets)
+            .map(|(bound, count)| {
+                total += count.load(Ordering::Relaxed);
+                (bound, total)
+            })
+            .collect()
+    }
+}
+
+/// Metrics of one kind keyed by the value of a single label.
+#[derive(Debug)]
+pub struct Labeled<M> {
+    label: &'static str,
+    metrics: Mutex<BTreeMap<String, Arc<M>>>,
+    make: fn() -> M,
+}
+
+impl<M> Labeled<M> {
+    fn new(label: &'static str, make: fn() -> M) -> Self {
+        Labeled {
+            label,
+            metrics: Mutex::new(BTreeMap::new()),
+            make,
+        }
+    }
+
+    pub fn get_label(&self) -> &str {
+        self.label
+    }
+
+    pub fn with(&self, value: &str) -> Arc<M> {
+        self.metrics
+            .lock()
+            .unwrap()
+            .entry(value.to_string())
+            .or_insert_with(|| Arc::new((self.make)()))
+            .clone()
+    }
+
+    /// Every label value seen so far, in order.
+    pub fn get_all(&self) -> Vec<(String, Arc<M>)> {
+        self.metrics
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|(value, metric)| (value.clone(), metric.clone()))
+            .collect()
+    }
+}
+
+#[derive(Debug)]
+pub struct Metrics {
+    files_seen: Counter,
+    files_filtered: Counter,
+    files_unchanged: Counter,
+    watch_changed: Counter,
+    watch_removed: Counter,
+    documents_extracted: Labeled<Counter>,
+    documents_failed: Labeled<Counter>,
+    extraction_seconds: Labeled<Histogram>,
+    extraction_retries: Counter,
+    archives_decompressed: Counter,
+    archives_failed: Counter,
+    batch_size: Histogram,
+    commit_seconds: Histogram,
+    queue_depth: Labeled<Gauge>,
+}
+
+impl Metrics {
+    fn new() -> Self {
+        Metrics {
+            files_seen: Counter::default(),
+            files_filtered: Counter::default(),
+            files_unchanged: Counter::default(),
+            watch_changed: Counter::default(),
+            watch_removed: Counter::default(),
+            documents_extracted: Labeled::new("format", Counter::default),
+            documents_failed: Labeled::new("format", Counter::default),
+            extraction_seconds: Labeled::new("format", || Histogram::new(DURATION_BUCKETS)),
+            extraction_retries: Counter::default(),
+            archives_decompressed: Counter::default(),
+            archives_failed: Counter::default(),
+            batch_size: Histogram::new(BATCH_SIZE_BUCKETS),
+            commit_seconds: Histogram::new(DURATION_BUCKETS),
+            queue_depth: Labeled::new("stage", Gauge::default),
+        }
+    }
+
+    /// Files walked by the scanner.
+    pub fn get_files_seen(&self) -> &Counter {
+        &self.files_seen
+    }
+
+    /// Files rejected by the scanner's filters.
+    pub fn get_files_filtered(&self) -> &Counter {
+        &self.files_filtered
+    }
+
+    /// Files skipped because they did not change since they were indexed.
+    pub fn get_files_unchanged(&self) -> &Counter {
+        &self.files_unchanged
+    }
+
+    /// Paths created or modified under watched roots, after coalescing.
+    pub fn get_watch_changed(&self) -> &Counter {
+        &self.watch_changed
+    }
+
+    /// Paths removed from watched roots, after coalescing.
+    pub fn get_watch_removed(&self) -> &Counter {
+        &self.watch_removed
+    }
+
+    /// Per `FormatType`.
+    pub fn get_documents_extracted(&self) -> &Labeled<Counter> {
+        &self.documents_extracted
+    }
+
+    /// Per `FormatType`.
+    pub fn get_documents_failed(&self) -> &Labeled<Counter> {
+        &self.documents_failed
+    }
+
+    /// Per `FormatType`.
+    pub fn get_extraction_seconds(&self) -> &Labeled<Histogram> {
+        &self.extraction_seconds
+    }
+
+    /// Extraction attempts scheduled again after a transient failure.
+    pub fn get_extraction_retries(&self) -> &Counter {
+        &self.extraction_retries
+    }
+
+    pub fn get_archives_decompressed(&self) -> &Counter {
+        &self.archives_decompressed
+    }
+
+    pub fn get_archives_failed(&self) -> &Counter {
+        &self.archives_failed
+    }
+
+    /// Documents per storage batch.
+    pub fn get_batch_size(&self) -> &Histogram {
+        &self.batch_size
+    }
+
+    /// Time to commit a storage batch.
+    pub fn get_commit_seconds(&self) -> &Histogram {
+        &self.commit_seconds
+    }
+
+    /// Messages waiting in each stage's channel, sampled by its consumers.
+    pub fn get_queue_depth(&self) -> &Labeled<Gauge> {
+        &self.queue_depth
+    }
+
+    /// Prometheus text exposition format.
+    pub fn render(&self) -> String {
+        let mut out = String::new();
+
+        counter(
+            &mut out,
+            "rsearch_scanner_files_seen_total",
+            "Files walked by the scanner.",
+            &self.files_seen,
+        );
+        counter(
+            &mut out,
+            "rsearch_scanner_files_filtered_total",
+            "Files rejected by the scanner filters.",
+            &self.files_filtered,
+        );
+        counter(
+            &mut out,
+            "rsearch_scanner_files_unchanged_total",
+            "Files skipped because they did not change since they were indexed.",
+            &self.files_unchanged,
+        );
+        counter(
+            &mut out,
+            "rsearch_watcher_paths_changed_total",
+            "Paths created or modified under watched roots.",
+            &self.watch_changed,
+        );
+        counter(
+            &mut out,
+            "rsearch_watcher_paths_removed_total",
+            "Paths removed from watched roots.",
+            &self.watch_removed,
+        );
+        labeled_counter(
+            &mut out,
+            "rsearch_extractor_documents_extracted_total",
+            "Documents extracted, per format.",
+            &self.documents_extracted,
+        );
+        labeled_counter(
+            &mut out,
+            "rsearch_extractor_documents_failed_total",
+            "Documents whose extraction failed, per format.",
+            &self.documents_failed,
+        );
+        header(
+            &mut out,
+            "rsearch_extractor_extraction_seconds",
+            "Extraction time, per format.",
+            "histogram",
+        );
+        for (value, histogram) in self.extraction_seconds.get_all() {
+            histogram_samples(
+                &mut out,
+                "rsearch_extractor_extraction_seconds",
+                Some((self.extraction_seconds.label, &value)),
+                &histogram,
+            );
+        }
+        counter(
+            &mut out,
+            "rsearch_extractor_retries_total",
+            "Extractions retried after a transient failure.",
+            &self.extraction_retries,
+        );
+        counter(
+            &mut out,
+            "rsearch_decompressor_archives_total",
+            "Archives decompressed.",
+            &self.archives_decompressed,
+        );
+        counter(
+            &mut out,
+            "rsearch_decompressor_archives_failed_total",
+            "Archives that could not be read.",
+            &self.archives_failed,
+        );
+        header(
+            &mut out,
+            "rsearch_storage_batch_size",
+            "Documents per storage batch.",
+            "histogram",
+        );
+        histogram_samples(
+            &mut out,
+            "rsearch_storage_batch_size",
+            None,
+            &self.batch_size,
+        );
+        header(
+            &mut out,
+            "rsearch_storage_commit_seconds",
+            "Time to commit a storage batch.",
+            "histogram",
+        );
+        histogram_samples(
+            &mut out,
+            "rsearch_storage_commit_seconds",
+            None,
+            &self.commit_seconds,
+        );
+        header(
+            &mut out,
+            "rsearch_queue_depth",
+            "Messages waiting in each stage's channel.",
+            "gauge",
+        );
+        for (value, gauge) in self.queue_depth.get_all() {
+            let _ = writeln!(
+                out,
+                "rsearch_queue_depth{{{}=\"{}\"}} {}",
+                self.queue_depth.label,
+                escape(&value),
+                gauge.get()
+            );
+        }
+
+        out
+    }
+}
+
+fn header(out: &mut String, name: &str, help: &str, kind: &str) {
+    let _ = writeln!(out, "# HELP {} {}", name, help);
+    let _ = writeln!(out, "# TYPE {} {}", name, kind);
+}
+
+fn counter(out: &mut String, name: &str, help: &str, counter: &Counter) {
+    header(out, name, help, "counter");
+    let _ = writeln!(out, "{} {}", name, counter.get());
+}
+
+fn labeled_counter(out: &mut String, name: &str, help: &str, counters: &Labeled<Counter>) {
+    header(out, name, help, "counter");
+    for (value, counter) in counters.get_all() {
+        let _ = writeln!(
+            out,
+            "{}{{{}=\"{}\"}} {}",
+            name,
+            counters.label,
+            escape(&value),
+            counter.get()
+        );
+    }
+}
+
+fn histogram_samples(
+    out: &mut String,
+    name: &str,
+    label: Option<(&str, &str)>,
+    histogram: &Histogram,
+) {
+    let label = label
+        .map(|(key, value)| format!("{}=\"{}\",", key, escape(value)))
+        .unwrap_or_default();
+
+    for (bound, count) in histogram.get_buckets() {
+        let le = if bound.is_infinite() {
+            "+Inf".to_string()
+        } else {
+            bound.to_string()
+        };
+        let _ = writeln!(out, "{}_bucket{{{}le=\"{}\"}} {}", name, label, le, count);
+    }
+
+    let label = label.trim_end_matches(',');
+    let label = if label.is_empty() {
+        String::new()
+    } else {
+        format!("{{{}}}", label)
+    };
+    let _ = writeln!(out, "{}_sum{} {}", name, label, histogram.get_sum());
+    let _ = writeln!(out, "{}_count{} {}", name, label, histogram.get_count());
+}
+
+fn escape(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
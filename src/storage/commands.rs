@@ -1,21 +1,132 @@
+use std::collections::HashMap;
+
 use crate::{
     engine::{Sender, scanner::ScannedDocument},
-    entities::{container::Container, document::Document},
-    storage::StorageError,
+    entities::{
+        container::Container,
+        document::{Document, SaveOutcome, SavedDocument},
+        duplicates::DuplicateReport,
+        failure::{FailedDocument, FailureKind},
+        find::PathHit,
+        listing::DocumentListing,
+        reconcile::ReconcileCandidate,
+        search::SearchResults,
+        stamp::FileStamp,
+        stats::IndexStats,
+    },
+    storage::{PruneSummary, StorageError, query::SearchMode},
 };
 
 #[derive(Debug, Clone)]
 pub enum StorageCommand {
     SaveDocument {
         document: Document,
-        resp_tx: Option<Sender<Result<(), StorageError>>>,
+        resp_tx: Option<Sender<Result<SaveOutcome, StorageError>>>,
     },
+    /// Saves the archive and removes its previous members. Its size and
+    /// modification time are only written once `members` documents of it are
+    /// committed, so an interrupted extraction is done again by the next scan.
     SaveArchive {
         archive: Container,
+        members: usize,
         resp_tx: Option<Sender<Result<Container, StorageError>>>,
     },
+    /// Upserts the batch, see `Document::save_bulk`.
     SaveBulkDocuments {
         documents: Vec<ScannedDocument>,
+        resp_tx: Option<Sender<Result<Vec<SavedDocument>, StorageError>>>,
+    },
+    Search {
+        query: String,
+        limit: usize,
+        offset: usize,
+        facets: bool,
+        mode: SearchMode,
+        /// One hit per content hash, see `Query::set_collapse`.
+        collapse: bool,
+        resp_tx: Sender<Result<SearchResults, StorageError>>,
+    },
+    Find {
+        pattern: String,
+        limit: usize,
+        resp_tx: Sender<Result<Vec<PathHit>, StorageError>>,
+    },
+    /// Stamps of the files indexed under a folder, see
+    /// `FileStamp::list_under_path`.
+    ListFileStamps {
+        path: String,
+        resp_tx: Sender<Result<HashMap<String, FileStamp>, StorageError>>,
+    },
+    /// Marks the documents at or under each path deleted, see
+    /// `Document::mark_deleted_under`.
+    MarkDeleted {
+        paths: Vec<String>,
+        resp_tx: Sender<Result<usize, StorageError>>,
+    },
+    Duplicates {
+        limit: usize,
+        offset: usize,
+        resp_tx: Sender<Result<DuplicateReport, StorageError>>,
+    },
+    ListFailures {
+        kind: Option<FailureKind>,
+        limit: usize,
+        offset: usize,
+        resp_tx: Sender<Result<Vec<FailedDocument>, StorageError>>,
+    },
+    /// Paths of the failed documents that can be retried, see
+    /// `FailedDocument::list_retryable`.
+    ListRetryableFailures {
+        include_permanent: bool,
+        resp_tx: Sender<Result<Vec<String>, StorageError>>,
+    },
+    GetDocument {
+        id: i64,
+        resp_tx: Sender<Result<Document, StorageError>>,
+    },
+    DeleteDocument {
+        id: i64,
+        resp_tx: Sender<Result<(), StorageError>>,
+    },
+    ListDocuments {
+        query: Option<String>,
+        limit: Option<usize>,
+        offset: usize,
+        resp_tx: Sender<Result<Vec<DocumentListing>, StorageError>>,
+    },
+    ListContainers {
+        resp_tx: Sender<Result<Vec<Container>, StorageError>>,
+    },
+    Stats {
+        resp_tx: Sender<Result<IndexStats, StorageError>>,
+    },
+    PurgePath {
+        path: String,
+        resp_tx: Sender<Result<usize, StorageError>>,
+    },
+    /// Documents a reconciliation pass looks for, under `root` or in the
+    /// whole index, see `ReconcileCandidate::list`.
+    ListReconcileCandidates {
+        root: Option<String>,
+        resp_tx: Sender<Result<Vec<ReconcileCandidate>, StorageError>>,
+    },
+    /// Marks these documents deleted, see `Document::mark_deleted`, and
+    /// clears the stamps of the vanished containers so that an archive that
+    /// comes back is extracted again.
+    MarkDocumentsDeleted {
+        ids: Vec<i64>,
+        vanished_containers: Vec<i64>,
+        resp_tx: Sender<Result<usize, StorageError>>,
+    },
+    /// Removes the deleted documents and the empty containers under `root`,
+    /// or in the whole index.
+    Prune {
+        root: Option<String>,
+        resp_tx: Sender<Result<PruneSummary, StorageError>>,
+    },
+    /// Processed in order, so every command sent before it is persisted
+    /// first; the storage thread then checkpoints the WAL and returns.
+    Shutdown {
         resp_tx: Option<Sender<Result<(), StorageError>>>,
     },
 }
@@ -1,4 +1,5 @@
 pub mod commands;
+pub mod query;
 pub mod constants {
     include!(concat!(env!("OUT_DIR"), "/storage_constants.rs"));
 }
@@ -10,45 +11,63 @@ use rusqlite::Result;
 use tracing::{error, info, warn};
 
 use crate::{
-    engine::{EngineError, EngineTask, Receiver, Sender, unbounded_channel},
+    engine::{EngineError, EngineTask, Receiver, Sender, bounded_channel, jobs::ScanJob},
     entities::{
-        container::{self, Container},
-        document::Document,
+        container::{self, Container, ContainerType},
+        document::{Document, SaveOutcome},
+        duplicates::{DuplicateError, DuplicateReport},
+        failure::{FailedDocument, FailureError},
+        find::{FindError, PathHit},
+        listing::{DocumentListing, ListingError},
+        reconcile::{ReconcileCandidate, ReconcileError},
+        search::{SearchError, SearchResults},
+        stamp::{FileStamp, StampError},
+        stats::{IndexStats, StatsError},
     },
-    storage::commands::StorageCommand,
+    metrics::metrics,
+    storage::{commands::StorageCommand, query::Query},
 };
 
 const LOG_TARGET: &str = "storage";
 
-const STORAGE_WORKER_RECEIVE_TIMEOUT_MS: Lazy<u64> = Lazy::new(|| {
+static STORAGE_WORKER_RECEIVE_TIMEOUT_MS: Lazy<u64> = Lazy::new(|| {
     env::var("STORAGE_WORKER_RECEIVE_TIMEOUT_MS")
         .ok()
         .and_then(|s| s.parse().ok())
         .unwrap_or(constants::DEFAULT_STORAGE_WORKER_RECEIVE_TIMEOUT_MS)
 });
 
-const STORAGE_DB_JOURNAL_MODE: Lazy<&'static str> = Lazy::new(|| {
+/// Capacity of the storage channel, 0 for unbounded. Extractor workers
+/// block while it is full.
+static STORAGE_CHANNEL_CAPACITY: Lazy<usize> = Lazy::new(|| {
+    env::var("STORAGE_CHANNEL_CAPACITY")
+        .ok()
+        .and_then(|s| s.parse().ok())
+        .unwrap_or(constants::DEFAULT_STORAGE_CHANNEL_CAPACITY)
+});
+
+static STORAGE_DB_JOURNAL_MODE: Lazy<&'static str> = Lazy::new(|| {
     Box::leak(
         env::var("STORAGE_DB_JOURNAL_MODE")
             .unwrap_or_else(|_| constants::DEFAULT_STORAGE_DB_JOURNAL_MODE.into())
             .into_boxed_str(),
     )
 });
-const STORAGE_DB_CACHE_SIZE: Lazy<&'static str> = Lazy::new(|| {
+static STORAGE_DB_CACHE_SIZE: Lazy<&'static str> = Lazy::new(|| {
     Box::leak(
         env::var("STORAGE_DB_CACHE_SIZE")
             .unwrap_or_else(|_| constants::DEFAULT_STORAGE_DB_CACHE_SIZE.into())
             .into_boxed_str(),
     )
 });
-const STORAGE_DB_TEMP_STORE: Lazy<&'static str> = Lazy::new(|| {
+static STORAGE_DB_TEMP_STORE: Lazy<&'static str> = Lazy::new(|| {
     Box::leak(
         env::var("STORAGE_DB_TEMP_STORE")
             .unwrap_or_else(|_| constants::DEFAULT_STORAGE_DB_TEMP_STORE.into())
             .into_boxed_str(),
     )
 });
-const STORAGE_DB_LOCKING_MODE: Lazy<&'static str> = Lazy::new(|| {
+static STORAGE_DB_LOCKING_MODE: Lazy<&'static str> = Lazy::new(|| {
     Box::leak(
         env::var("STORAGE_DB_LOCKING_MODE")
             .unwrap_or_else(|_| constants::DEFAULT_STORAGE_DB_LOCKING_MODE.into())
@@ -64,12 +83,62 @@ pub static STORAGE_DATABASE_PATH: Lazy<&'static str> = Lazy::new(|| {
     )
 });
 
+/// Maintain the trigram-tokenized `index_documents_trigram` table used by
+/// fuzzy search.
+pub static STORAGE_TRIGRAM_INDEX: Lazy<bool> = Lazy::new(|| {
+    env::var("STORAGE_TRIGRAM_INDEX")
+        .ok()
+        .and_then(|s| s.parse().ok())
+        .unwrap_or(constants::DEFAULT_STORAGE_TRIGRAM_INDEX)
+});
+
+/// Share of a term's trigrams a document must contain to match in fuzzy mode.
+pub static STORAGE_FUZZY_MIN_SIMILARITY: Lazy<f64> = Lazy::new(|| {
+    env::var("STORAGE_FUZZY_MIN_SIMILARITY")
+        .ok()
+        .and_then(|s| s.parse().ok())
+        .unwrap_or(constants::DEFAULT_STORAGE_FUZZY_MIN_SIMILARITY)
+});
+
 #[derive(Debug)]
 pub enum StorageError {
     InitializationError(rusqlite::Error),
     ExecutionError(rusqlite::Error),
     ContainerError(container::ContainerError),
     DocumentError(crate::entities::document::DocumentError),
+    SearchError(SearchError),
+    StatsError(StatsError),
+    FindError(FindError),
+    StampError(StampError),
+    DuplicateError(DuplicateError),
+    ListingError(ListingError),
+    ReconcileError(ReconcileError),
+    FailureError(FailureError),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct PruneSummary {
+    documents: usize,
+    containers: usize,
+}
+
+impl PruneSummary {
+    pub fn get_documents(&self) -> usize {
+        self.documents
+    }
+
+    pub fn get_containers(&self) -> usize {
+        self.containers
+    }
+}
+
+/// Archive whose members are not all committed yet, see
+/// `StorageCommand::SaveArchive`.
+#[derive(Debug, Clone, Copy)]
+struct PendingArchive {
+    remaining: usize,
+    size: Option<i64>,
+    modified: Option<i64>,
 }
 
 pub type StorageChannelTx = Sender<StorageCommand>;
@@ -77,24 +146,177 @@ pub type StorageChannelRx = Receiver<StorageCommand>;
 
 #[derive(Debug)]
 pub struct StorageEngine {
+    database_path: String,
     channel_tx: StorageChannelTx,
     channel_rx: StorageChannelRx,
 }
 
 impl Default for StorageEngine {
     fn default() -> Self {
-        let (tx, rx) = unbounded_channel::<StorageCommand>();
+        Self::new(STORAGE_DATABASE_PATH.to_string())
+    }
+}
+
+impl StorageEngine {
+    pub fn new(database_path: String) -> Self {
+        let (tx, rx) = bounded_channel::<StorageCommand>(*STORAGE_CHANNEL_CAPACITY);
 
         Self {
+            database_path,
             channel_tx: tx,
             channel_rx: rx,
         }
     }
-}
 
-impl StorageEngine {
-    pub fn initialize() -> Result<(), StorageError> {
-        let conn = rusqlite::Connection::open(*STORAGE_DATABASE_PATH)
+    pub fn get_database_path(&self) -> &str {
+        &self.database_path
+    }
+
+    /// Counts `count` more committed members of a pending archive, and
+    /// writes its stamp once none remain.
+    fn archive_members_committed(
+        conn: &rusqlite::Connection,
+        pending_archives: &mut HashMap<i64, PendingArchive>,
+        id: i64,
+        count: usize,
+    ) {
+        let Some(pending) = pending_archives.get_mut(&id) else {
+            return;
+        };
+
+        pending.remaining = pending.remaining.saturating_sub(count);
+        if pending.remaining > 0 {
+            return;
+        }
+
+        let (size, modified) = (pending.size, pending.modified);
+        pending_archives.remove(&id);
+        if let Err(e) = Container::stamp(conn, id, size, modified) {
+            error!(target: LOG_TARGET, "Failed to stamp archive {}: {:?}", id, e);
+        }
+    }
+
+    /// Schema migration for databases created before `column` existed.
+    fn add_column_if_missing(
+        conn: &rusqlite::Connection,
+        table: &str,
+        column: &str,
+        definition: &str,
+    ) -> Result<(), StorageError> {
+        let exists: bool = conn
+            .query_row(
+                &format!(
+                    "SELECT COUNT(*) > 0 FROM pragma_table_info('{}') WHERE name = ?1",
+                    table
+                ),
+                [column],
+                |row| row.get(0),
+            )
+            .map_err(StorageError::InitializationError)?;
+
+        if !exists {
+            info!(target: LOG_TARGET, "Adding column {}.{}", table, column);
+
+            conn.execute(
+                &format!("ALTER TABLE {} ADD COLUMN {} {}", table, column, definition),
+                [],
+            )
+            .map_err(StorageError::InitializationError)?;
+        }
+
+        Ok(())
+    }
+
+    /// Creates the trigram table and backfills it from `index_documents` the
+    /// first time it is enabled; drops it when disabled so it cannot go stale.
+    fn initialize_trigram_index(conn: &rusqlite::Connection) -> Result<(), StorageError> {
+        if !*STORAGE_TRIGRAM_INDEX {
+            conn.execute("DROP TABLE IF EXISTS index_documents_trigram", [])
+                .map_err(StorageError::InitializationError)?;
+            return Ok(());
+        }
+
+        let exists: bool = conn
+            .query_row(
+                "SELECT COUNT(*) > 0 FROM sqlite_master WHERE name = 'index_documents_trigram'",
+                [],
+                |row| row.get(0),
+            )
+            .map_err(StorageError::InitializationError)?;
+
+        if exists {
+            return Ok(());
+        }
+
+        info!(target: LOG_TARGET, "Create trigram index for fuzzy search");
+
+        conn.execute(
+            "CREATE VIRTUAL TABLE index_documents_trigram USING fts5 (
+                document_id UNINDEXED,
+                content,
+                description,
+                tokenize = 'trigram'
+            )",
+            [],
+        )
+        .map_err(StorageError::InitializationError)?;
+
+        let backfilled = conn
+            .execute(
+                "INSERT INTO index_documents_trigram (document_id, content, description)
+                SELECT document_id, content, description FROM index_documents",
+                [],
+            )
+            .map_err(StorageError::InitializationError)?;
+
+        info!(target: LOG_TARGET, "Backfilled {} documents into trigram index", backfilled);
+
+        Ok(())
+    }
+
+    /// Creates the trigram index over full paths and file names used by
+    /// `find`, backfilling it from `documents_view` on databases that predate it.
+    fn initialize_path_index(conn: &rusqlite::Connection) -> Result<(), StorageError> {
+        let exists: bool = conn
+            .query_row(
+                "SELECT COUNT(*) > 0 FROM sqlite_master WHERE name = 'index_paths'",
+                [],
+                |row| row.get(0),
+            )
+            .map_err(StorageError::InitializationError)?;
+
+        if exists {
+            return Ok(());
+        }
+
+        info!(target: LOG_TARGET, "Create path index");
+
+        conn.execute(
+            "CREATE VIRTUAL TABLE index_paths USING fts5 (
+                document_id UNINDEXED,
+                path,
+                filename,
+                tokenize = 'trigram'
+            )",
+            [],
+        )
+        .map_err(StorageError::InitializationError)?;
+
+        let backfilled = conn
+            .execute(
+                "INSERT INTO index_paths (document_id, path, filename)
+                SELECT id, path, filename FROM documents_view",
+                [],
+            )
+            .map_err(StorageError::InitializationError)?;
+
+        info!(target: LOG_TARGET, "Backfilled {} documents into path index", backfilled);
+
+        Ok(())
+    }
+
+    pub fn initialize(&self) -> Result<(), StorageError> {
+        let conn = rusqlite::Connection::open(&self.database_path)
             .map_err(StorageError::InitializationError)?;
 
         info!(target: LOG_TARGET, "Initializing storage engine");
@@ -111,17 +333,31 @@ impl StorageEngine {
         conn.pragma_update(None, "locking_mode", *STORAGE_DB_LOCKING_MODE)
             .map_err(StorageError::InitializationError)?;
 
+        Self::create_schema(&conn)?;
+
+        info!(target: LOG_TARGET, "Storage engine initialized successfully");
+
+        Ok(())
+    }
+
+    /// Creates the tables, indexes and views, migrating older databases.
+    pub(crate) fn create_schema(conn: &rusqlite::Connection) -> Result<(), StorageError> {
         info!(target: LOG_TARGET, "Creating necessary tables and indexes");
         conn.execute(
             "CREATE TABLE IF NOT EXISTS containers (
                 id INTEGER PRIMARY KEY,
                 path TEXT NOT NULL UNIQUE,
-                type TEXT NOT NULL
+                type TEXT NOT NULL,
+                size INTEGER,
+                modified INTEGER
             )",
             [],
         )
         .map_err(StorageError::InitializationError)?;
 
+        Self::add_column_if_missing(conn, "containers", "size", "INTEGER")?;
+        Self::add_column_if_missing(conn, "containers", "modified", "INTEGER")?;
+
         conn.execute(
             "CREATE UNIQUE INDEX IF NOT EXISTS idx_containers_path ON containers(path)",
             [],
@@ -135,6 +371,11 @@ impl StorageEngine {
                 extension TEXT,
                 status TEXT NOT NULL DEFAULT 'New',
                 container_id INTEGER NOT NULL,
+                size INTEGER,
+                modified INTEGER,
+                hash TEXT,
+                truncated INTEGER NOT NULL DEFAULT 0,
+                content_mode TEXT,
                 UNIQUE(filename, container_id),
                 FOREIGN KEY(container_id) REFERENCES containers(id)
             )",
@@ -142,6 +383,46 @@ impl StorageEngine {
         )
         .map_err(StorageError::InitializationError)?;
 
+        Self::add_column_if_missing(conn, "documents", "size", "INTEGER")?;
+        Self::add_column_if_missing(conn, "documents", "modified", "INTEGER")?;
+        Self::add_column_if_missing(conn, "documents", "hash", "TEXT")?;
+        Self::add_column_if_missing(conn, "documents", "truncated", "INTEGER NOT NULL DEFAULT 0")?;
+        Self::add_column_if_missing(conn, "documents", "content_mode", "TEXT")?;
+
+        conn.execute(
+            "CREATE INDEX IF NOT EXISTS idx_documents_hash ON documents(hash)",
+            [],
+        )
+        .map_err(StorageError::InitializationError)?;
+
+        conn.execute(
+            "CREATE TABLE IF NOT EXISTS extraction_errors (
+                document_id INTEGER PRIMARY KEY,
+                kind TEXT NOT NULL,
+                message TEXT NOT NULL,
+                extractor TEXT NOT NULL,
+                failed_at INTEGER NOT NULL,
+                attempts INTEGER NOT NULL DEFAULT 1,
+                permanent INTEGER NOT NULL DEFAULT 0,
+                FOREIGN KEY(document_id) REFERENCES documents(id)
+            )",
+            [],
+        )
+        .map_err(StorageError::InitializationError)?;
+
+        Self::add_column_if_missing(
+            conn,
+            "extraction_errors",
+            "attempts",
+            "INTEGER NOT NULL DEFAULT 1",
+        )?;
+        Self::add_column_if_missing(
+            conn,
+            "extraction_errors",
+            "permanent",
+            "INTEGER NOT NULL DEFAULT 0",
+        )?;
+
         conn.execute(
             "CREATE VIRTUAL TABLE IF NOT EXISTS index_documents USING fts5 (
                 document_id UNINDEXED,
@@ -152,11 +433,16 @@ impl StorageEngine {
         )
         .map_err(StorageError::InitializationError)?;
 
+        Self::initialize_trigram_index(conn)?;
+
         info!(target: LOG_TARGET, "Create view for full document info");
 
+        conn.execute("DROP VIEW IF EXISTS documents_view", [])
+            .map_err(StorageError::InitializationError)?;
+
         conn.execute(
             &format!(
-                "CREATE VIEW IF NOT EXISTS documents_view AS 
+                "CREATE VIEW documents_view AS 
                     SELECT 
                     c.id as container_id,
                     d.id as id,
@@ -165,7 +451,12 @@ impl StorageEngine {
                     c.path || '{}' || d.filename as path,
                     c.path as container_path,
                     d.filename as filename,
-                    d.extension as extension
+                    d.extension as extension,
+                    d.size as size,
+                    d.modified as modified,
+                    d.hash as hash,
+                    d.truncated as truncated,
+                    d.content_mode as content_mode
                     FROM documents d
                     INNER JOIN containers c ON c.id = d.container_id
                     ORDER BY container_id, id",
@@ -175,10 +466,18 @@ impl StorageEngine {
         )
         .map_err(StorageError::InitializationError)?;
 
-        info!(target: LOG_TARGET, "Storage engine initialized successfully");
+        Self::initialize_path_index(conn)?;
 
         Ok(())
     }
+
+    /// In-memory database with the full schema, for the storage tests.
+    #[cfg(test)]
+    pub(crate) fn open_in_memory() -> rusqlite::Connection {
+        let conn = rusqlite::Connection::open_in_memory().expect("Failed to open database");
+        Self::create_schema(&conn).expect("Failed to create schema");
+        conn
+    }
 }
 
 impl EngineTask<StorageChannelTx, StorageChannelRx> for StorageEngine {
@@ -191,7 +490,7 @@ impl EngineTask<StorageChannelTx, StorageChannelRx> for StorageEngine {
     }
 
     fn run(&mut self) -> Result<JoinHandle<()>, EngineError> {
-        let conn = rusqlite::Connection::open(*STORAGE_DATABASE_PATH)
+        let conn = rusqlite::Connection::open(&self.database_path)
             .map_err(StorageError::InitializationError);
 
         if let Err(e) = conn {
@@ -207,11 +506,20 @@ impl EngineTask<StorageChannelTx, StorageChannelRx> for StorageEngine {
             info!(target: LOG_TARGET, "StorageEngine worker started");
 
             let mut container_cache: HashMap<String, Container> = HashMap::new();
+            let mut pending_archives: HashMap<i64, PendingArchive> = HashMap::new();
+
+            let metrics = metrics();
 
             loop {
-                if let Ok(command) = receiver
-                    .recv_timeout(std::time::Duration::from_millis(*STORAGE_WORKER_RECEIVE_TIMEOUT_MS))
-                {
+                let received = receiver.recv_timeout(std::time::Duration::from_millis(
+                    *STORAGE_WORKER_RECEIVE_TIMEOUT_MS,
+                ));
+                metrics
+                    .get_queue_depth()
+                    .with(LOG_TARGET)
+                    .set(receiver.len() as i64);
+
+                if let Ok(command) = received {
                     match command {
                         StorageCommand::SaveDocument {
                             mut document,
@@ -219,12 +527,14 @@ impl EngineTask<StorageChannelTx, StorageChannelRx> for StorageEngine {
                         } => {
                             info!(target: LOG_TARGET, "Saving document: {:?}", document);
 
-                            if let Err(e) = document.save(&conn) {
+                            let result = document.save(&conn).map_err(StorageError::DocumentError);
+
+                            if let Err(e) = &result {
                                 error!(target: LOG_TARGET, "Failed to save document: {:?}", e);
                             }
 
                             if let Some(resp_tx) = resp_tx {
-                                let _ = resp_tx.send(Ok(()));
+                                let _ = resp_tx.send(result);
                             } else {
                                 warn!(target: LOG_TARGET, "No response channel provided for SaveDocument command");
                             }
@@ -240,24 +550,105 @@ impl EngineTask<StorageChannelTx, StorageChannelRx> for StorageEngine {
                                 error!(target: LOG_TARGET, "Failed to update container cache from documents: {:?}", e);
                             }
 
-                            if let Err(e) =
+                            // The job and pending archive of each document, in
+                            // batch order like the outcomes.
+                            let rows: Vec<(Option<ScanJob>, Option<i64>)> = documents
+                                .iter()
+                                .map(|d| {
+                                    let archive = d
+                                        .document
+                                        .get_container_id()
+                                        .filter(|_| d.container_type == ContainerType::Archive)
+                                        .filter(|id| pending_archives.contains_key(id));
+                                    (d.job.clone(), archive)
+                                })
+                                .collect();
+
+                            metrics.get_batch_size().observe(documents.len() as f64);
+                            let started = std::time::Instant::now();
+                            let saved =
                                 Document::save_bulk(&mut conn, documents, &mut container_cache)
-                            {
-                                error!(target: LOG_TARGET, "Failed to save bulk documents: {:?}", e);
+                                    .map_err(StorageError::DocumentError);
+                            metrics
+                                .get_commit_seconds()
+                                .observe_duration(started.elapsed());
+
+                            let mut jobs: HashMap<String, (ScanJob, usize, usize)> = HashMap::new();
+                            let mut archive_members: HashMap<i64, usize> = HashMap::new();
+                            let outcomes: Vec<bool> = match &saved {
+                                Ok(saved) => saved
+                                    .iter()
+                                    .map(|s| s.get_outcome() != SaveOutcome::Failed)
+                                    .collect(),
+                                Err(e) => {
+                                    error!(target: LOG_TARGET, "Failed to save bulk documents: {:?}", e);
+                                    vec![false; rows.len()]
+                                }
+                            };
+
+                            for ((job, archive), committed) in rows.into_iter().zip(outcomes) {
+                                if let Some(job) = job {
+                                    let entry = jobs
+                                        .entry(job.get_id().to_string())
+                                        .or_insert_with(|| (job.clone(), 0, 0));
+                                    if committed {
+                                        entry.1 += 1;
+                                    } else {
+                                        entry.2 += 1;
+                                    }
+                                }
+
+                                match archive {
+                                    Some(id) if committed => {
+                                        *archive_members.entry(id).or_default() += 1;
+                                    }
+                                    // Never stamped, so the next scan extracts
+                                    // the archive again.
+                                    Some(id) => {
+                                        pending_archives.remove(&id);
+                                    }
+                                    None => {}
+                                }
+                            }
+
+                            for (id, count) in archive_members {
+                                Self::archive_members_committed(
+                                    &conn,
+                                    &mut pending_archives,
+                                    id,
+                                    count,
+                                );
+                            }
+
+                            for (job, committed, failed) in jobs.into_values() {
+                                if committed > 0 {
+                                    job.committed(committed);
+                                }
+                                if failed > 0 {
+                                    job.failed(failed);
+                                }
                             }
 
                             if let Some(resp_tx) = resp_tx {
-                                let _ = resp_tx.send(Ok(()));
+                                let _ = resp_tx.send(saved);
                             } else {
                                 warn!(target: LOG_TARGET, "No response channel provided for SaveBulkDocuments command");
                             }
                         }
                         StorageCommand::SaveArchive {
                             mut archive,
+                            members,
                             resp_tx,
                         } => {
                             info!(target: LOG_TARGET, "Saving archive: {:?}", archive);
 
+                            let pending = PendingArchive {
+                                remaining: members,
+                                size: archive.get_size(),
+                                modified: archive.get_modified(),
+                            };
+                            archive.set_stamp(None, None);
+
                             if let Err(e) = archive.save(&mut conn) {
                                 error!(target: LOG_TARGET, "Failed to save archive: {:?}", e);
 
@@ -267,12 +658,310 @@ impl EngineTask<StorageChannelTx, StorageChannelRx> for StorageEngine {
                                 continue;
                             }
 
+                            // The archive is extracted again when it changed, its
+                            // members are replaced as a whole.
+                            if let Err(e) = Document::purge_container(&mut conn, archive.get_id()) {
+                                error!(target: LOG_TARGET, "Failed to purge archive members: {:?}", e);
+
+                                if let Some(resp_tx) = resp_tx {
+                                    let _ = resp_tx.send(Err(StorageError::DocumentError(e)));
+                                }
+                                continue;
+                            }
+
+                            pending_archives.insert(archive.get_id(), pending);
+                            Self::archive_members_committed(
+                                &conn,
+                                &mut pending_archives,
+                                archive.get_id(),
+                                0,
+                            );
+
                             if let Some(resp_tx) = resp_tx {
                                 let _ = resp_tx.send(Ok(archive));
                             } else {
                                 warn!(target: LOG_TARGET, "No response channel provided for SaveArchive command");
                             }
                         }
+                        StorageCommand::Search {
+                            query,
+                            limit,
+                            offset,
+                            facets,
+                            mode,
+                            collapse,
+                            resp_tx,
+                        } => {
+                            info!(target: LOG_TARGET, "Searching documents ({}): {}", mode.to_str(), query);
+
+                            let result = Query::parse(&query)
+                                .map_err(SearchError::QueryError)
+                                .and_then(|mut query| {
+                                    query.set_mode(mode);
+                                    query.set_collapse(collapse);
+                                    SearchResults::load(&conn, &query, limit, offset, facets)
+                                })
+                                .map_err(StorageError::SearchError);
+
+                            if let Err(e) = &result {
+                                error!(target: LOG_TARGET, "Failed to search documents: {:?}", e);
+                            }
+
+                            let _ = resp_tx.send(result);
+                        }
+                        StorageCommand::GetDocument { id, resp_tx } => {
+                            info!(target: LOG_TARGET, "Loading document: {}", id);
+
+                            let result =
+                                Document::get_by_id(&conn, id).map_err(StorageError::DocumentError);
+
+                            let _ = resp_tx.send(result);
+                        }
+                        StorageCommand::DeleteDocument { id, resp_tx } => {
+                            info!(target: LOG_TARGET, "Deleting document: {}", id);
+
+                            let result = Document::delete(&mut conn, id)
+                                .map_err(StorageError::DocumentError);
+
+                            if let Err(e) = &result {
+                                error!(target: LOG_TARGET, "Failed to delete document: {:?}", e);
+                            }
+
+                            let _ = resp_tx.send(result);
+                        }
+                        StorageCommand::ListContainers { resp_tx } => {
+                            info!(target: LOG_TARGET, "Listing containers");
+
+                            let result =
+                                Container::list(&conn).map_err(StorageError::ContainerError);
+
+                            let _ = resp_tx.send(result);
+                        }
+                        StorageCommand::Find {
+                            pattern,
+                            limit,
+                            resp_tx,
+                        } => {
+                            info!(target: LOG_TARGET, "Finding paths: {}", pattern);
+
+                            let result = PathHit::find(&conn, &pattern, limit)
+                                .map_err(StorageError::FindError);
+
+                            if let Err(e) = &result {
+                                error!(target: LOG_TARGET, "Failed to find paths: {:?}", e);
+                            }
+
+                            let _ = resp_tx.send(result);
+                        }
+                        StorageCommand::ListFileStamps { path, resp_tx } => {
+                            info!(target: LOG_TARGET, "Loading file stamps under {}", path);
+
+                            let result = FileStamp::list_under_path(&conn, &path)
+                                .map_err(StorageError::StampError);
+
+                            if let Err(e) = &result {
+                                error!(target: LOG_TARGET, "Failed to load file stamps: {:?}", e);
+                            }
+
+                            let _ = resp_tx.send(result);
+                        }
+                        StorageCommand::MarkDeleted { paths, resp_tx } => {
+                            info!(target: LOG_TARGET, "Marking documents deleted under {} paths", paths.len());
+
+                            let result = Document::mark_deleted_under(&mut conn, &paths)
+                                .map_err(StorageError::DocumentError);
+
+                            if let Err(e) = &result {
+                                error!(target: LOG_TARGET, "Failed to mark documents deleted: {:?}", e);
+                            }
+
+                            let _ = resp_tx.send(result);
+                        }
+                        StorageCommand::Duplicates {
+                            limit,
+                            offset,
+                            resp_tx,
+                        } => {
+                            info!(target: LOG_TARGET, "Listing duplicates");
+
+                            let result = DuplicateReport::load(&conn, limit, offset)
+                                .map_err(StorageError::DuplicateError);
+
+                            if let Err(e) = &result {
+                                error!(target: LOG_TARGET, "Failed to list duplicates: {:?}", e);
+                            }
+
+                            let _ = resp_tx.send(result);
+                        }
+                        StorageCommand::ListFailures {
+                            kind,
+                            limit,
+                            offset,
+                            resp_tx,
+                        } => {
+                            info!(target: LOG_TARGET, "Listing extraction failures");
+
+                            let result = FailedDocument::list(&conn, kind, limit, offset)
+                                .map_err(StorageError::FailureError);
+
+                            if let Err(e) = &result {
+                                error!(target: LOG_TARGET, "Failed to list extraction failures: {:?}", e);
+                            }
+
+                            let _ = resp_tx.send(result);
+                        }
+                        StorageCommand::ListRetryableFailures {
+                            include_permanent,
+                            resp_tx,
+                        } => {
+                            info!(target: LOG_TARGET, "Listing failed documents to retry");
+
+                            // The rows stay: the rescan updates them in place,
+                            // adding to their failure's attempts.
+                            let result = FailedDocument::list_retryable(&conn, include_permanent)
+                                .map(|failed| {
+                                    failed
+                                        .iter()
+                                        .map(|failed| failed.get_path().to_string())
+                                        .collect()
+                                })
+                                .map_err(StorageError::FailureError);
+
+                            if let Err(e) = &result {
+                                error!(target: LOG_TARGET, "Failed to list failed documents: {:?}", e);
+                            }
+
+                            let _ = resp_tx.send(result);
+                        }
+                        StorageCommand::ListDocuments {
+                            query,
+                            limit,
+                            offset,
+                            resp_tx,
+                        } => {
+                            info!(target: LOG_TARGET, "Listing documents: {:?}", query);
+
+                            let result = query
+                                .as_deref()
+                                .map(Query::parse)
+                                .transpose()
+                                .map_err(ListingError::QueryError)
+                                .and_then(|query| {
+                                    DocumentListing::list(&conn, query.as_ref(), limit, offset)
+                                })
+                                .map_err(StorageError::ListingError);
+
+                            if let Err(e) = &result {
+                                error!(target: LOG_TARGET, "Failed to list documents: {:?}", e);
+                            }
+
+                            let _ = resp_tx.send(result);
+                        }
+                        StorageCommand::Stats { resp_tx } => {
+                            info!(target: LOG_TARGET, "Loading index stats");
+
+                            let result = IndexStats::load(&conn).map_err(StorageError::StatsError);
+
+                            let _ = resp_tx.send(result);
+                        }
+                        StorageCommand::PurgePath { path, resp_tx } => {
+                            info!(target: LOG_TARGET, "Purging documents under: {}", path);
+
+                            let result = Document::purge_under_path(&mut conn, &path)
+                                .map_err(StorageError::DocumentError);
+
+                            if let Err(e) = &result {
+                                error!(target: LOG_TARGET, "Failed to purge documents: {:?}", e);
+                            }
+
+                            container_cache.clear();
+                            if let Err(e) = Container::delete_empty(
+                                &conn,
+                                None,
+                                &pending_archives.keys().copied().collect::<Vec<_>>(),
+                            ) {
+                                error!(target: LOG_TARGET, "Failed to delete empty containers: {:?}", e);
+                            }
+
+                            let _ = resp_tx.send(result);
+                        }
+                        StorageCommand::ListReconcileCandidates { root, resp_tx } => {
+                            info!(target: LOG_TARGET, "Listing documents to reconcile under {}", root.as_deref().unwrap_or("index"));
+
+                            let result = ReconcileCandidate::list(&conn, root.as_deref())
+                                .map_err(StorageError::ReconcileError);
+
+                            if let Err(e) = &result {
+                                error!(target: LOG_TARGET, "Failed to list documents to reconcile: {:?}", e);
+                            }
+
+                            let _ = resp_tx.send(result);
+                        }
+                        StorageCommand::MarkDocumentsDeleted {
+                            ids,
+                            vanished_containers,
+                            resp_tx,
+                        } => {
+                            info!(target: LOG_TARGET, "Marking {} documents deleted", ids.len());
+
+                            let result = Document::mark_deleted(&mut conn, &ids)
+                                .map_err(StorageError::DocumentError)
+                                .and_then(|marked| {
+                                    vanished_containers
+                                        .iter()
+                                        .try_for_each(|id| Container::stamp(&conn, *id, None, None))
+                                        .map(|_| marked)
+                                        .map_err(StorageError::ContainerError)
+                                });
+
+                            if let Err(e) = &result {
+                                error!(target: LOG_TARGET, "Failed to mark documents deleted: {:?}", e);
+                            }
+
+                            let _ = resp_tx.send(result);
+                        }
+                        StorageCommand::Prune { root, resp_tx } => {
+                            info!(target: LOG_TARGET, "Pruning deleted documents under {}", root.as_deref().unwrap_or("index"));
+
+                            let result = Document::purge_deleted(&mut conn, root.as_deref())
+                                .map_err(StorageError::DocumentError)
+                                .and_then(|documents| {
+                                    container_cache.clear();
+
+                                    Container::delete_empty(
+                                        &conn,
+                                        root.as_deref(),
+                                        &pending_archives.keys().copied().collect::<Vec<_>>(),
+                                    )
+                                    .map(|containers| PruneSummary {
+                                        documents,
+                                        containers,
+                                    })
+                                    .map_err(StorageError::ContainerError)
+                                });
+
+                            if let Err(e) = &result {
+                                error!(target: LOG_TARGET, "Failed to prune documents: {:?}", e);
+                            }
+
+                            let _ = resp_tx.send(result);
+                        }
+                        StorageCommand::Shutdown { resp_tx } => {
+                            info!(target: LOG_TARGET, "StorageEngine shutting down");
+
+                            let result = conn
+                                .query_row("PRAGMA wal_checkpoint(TRUNCATE)", [], |_| Ok(()))
+                                .map_err(StorageError::ExecutionError);
+
+                            if let Err(e) = &result {
+                                error!(target: LOG_TARGET, "Failed to checkpoint database: {:?}", e);
+                            }
+
+                            if let Some(resp_tx) = resp_tx {
+                                let _ = resp_tx.send(result);
+                            }
+                            break;
+                        }
                     }
                 }
             }
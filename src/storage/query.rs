@@ -0,0 +1,997 @@
+use std::str::FromStr;
+
+use chrono::NaiveDate;
+use rusqlite::types::Value;
+
+use crate::{
+    entities::{container::ContainerType, document::DocumentStatus},
+    storage::STORAGE_FUZZY_MIN_SIMILARITY,
+};
+
+const TRIGRAM_LENGTH: usize = 3;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum QueryError {
+    EmptyQuery,
+    UnexpectedToken(String),
+    UnbalancedParenthesis,
+    InvalidValue { key: String, value: String },
+}
+
+/// How free-text terms are matched.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum SearchMode {
+    /// Whole tokens through the default `unicode61` tokenizer.
+    #[default]
+    Exact,
+    /// Trigram overlap against `index_documents_trigram`: tolerates typos and
+    /// matches parts of words. Terms shorter than three characters are matched
+    /// exactly.
+    Fuzzy,
+}
+
+impl FromStr for SearchMode {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "exact" => Ok(SearchMode::Exact),
+            "fuzzy" => Ok(SearchMode::Fuzzy),
+            _ => Err(()),
+        }
+    }
+}
+
+impl SearchMode {
+    pub fn to_str(&self) -> &str {
+        match self {
+            SearchMode::Exact => "exact",
+            SearchMode::Fuzzy => "fuzzy",
+        }
+    }
+}
+
+/// Half-open range `[min, max)` over an integer column.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Range {
+    min: Option<i64>,
+    max: Option<i64>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Qualifier {
+    Extension(Vec<String>),
+    Path(String),
+    Name(String),
+    In(ContainerType),
+    Status(DocumentStatus),
+    Size(Range),
+    Modified(Range),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Expr {
+    And(Box<Expr>, Box<Expr>),
+    Or(Box<Expr>, Box<Expr>),
+    Not(Box<Expr>),
+    /// FTS5 expression matched against `index_documents`.
+    Text(String),
+    Qualifier(Qualifier),
+}
+
+/// Parsed search query.
+///
+/// Free-text terms, `"phrases"`, `prefix*` and `NEAR(...)` groups are matched
+/// with FTS5; `key:value` qualifiers filter on `documents_view` columns:
+///
+/// - `ext:pdf` / `ext:pdf,docx`
+/// - `path:/projects/` (substring of the full path)
+/// - `name:report` (case-insensitive substring of the file name)
+/// - `in:archive` / `in:folder`
+/// - `status:Extracted`
+/// - `size:>10MB`, `size:<=1KB`, `size:1MB..5MB`
+/// - `modified:2025-01..2025-06`, `modified:>=2025-03-01`, `modified:2024`
+///
+/// Terms combine with `AND` (implicit), `OR`, `NOT` / `-term` and parentheses.
+///
+/// In [`SearchMode::Fuzzy`] negated text terms stay exact, so `-draft` still
+/// excludes only documents containing the word.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Query {
+    expr: Expr,
+    mode: SearchMode,
+    collapse: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Token {
+    LParen,
+    RParen,
+    And,
+    Or,
+    Not,
+    Term(String),
+    Phrase(String),
+    Near(String),
+    Qualifier(String, String),
+}
+
+const QUALIFIER_KEYS: [&str; 7] = ["ext", "path", "name", "in", "status", "size", "modified"];
+
+impl FromStr for Query {
+    type Err = QueryError;
+
+    fn from_str(input: &str) -> Result<Self, Self::Err> {
+        Query::parse(input)
+    }
+}
+
+impl Query {
+    pub fn parse(input: &str) -> Result<Query, QueryError> {
+        let tokens = tokenize(input)?;
+        if tokens.is_empty() {
+            return Err(QueryError::EmptyQuery);
+        }
+
+        let mut parser = Parser { tokens, pos: 0 };
+        let expr = parser.parse_or()?;
+
+        match parser.peek() {
+            None => Ok(Query {
+                expr,
+                mode: SearchMode::default(),
+                collapse: false,
+            }),
+            Some(Token::RParen) => Err(QueryError::UnbalancedParenthesis),
+            Some(token) => Err(QueryError::UnexpectedToken(format!("{:?}", token))),
+        }
+    }
+
+    pub fn get_expr(&self) -> &Expr {
+        &self.expr
+    }
+
+    pub fn get_mode(&self) -> SearchMode {
+        self.mode
+    }
+
+    pub fn set_mode(&mut self, mode: SearchMode) {
+        self.mode = mode;
+    }
+
+    /// Whether documents with the same content hash are returned as one hit.
+    pub fn is_collapsed(&self) -> bool {
+        self.collapse
+    }
+
+    pub fn set_collapse(&mut self, collapse: bool) {
+        self.collapse = collapse;
+    }
+
+    /// SQL predicate over the `documents_view` alias `d`, with its parameters.
+    pub fn to_sql(&self) -> (String, Vec<Value>) {
+        let mut params = Vec::new();
+        let sql = self.expr.to_sql(self.mode, false, &mut params);
+        (sql, params)
+    }
+
+    /// FTS5 expression used for ranking and snippets: every text term that is
+    /// not negated, OR-ed together. `None` for qualifier-only queries.
+    pub fn rank_expression(&self) -> Option<String> {
+        let mut terms = Vec::new();
+        self.expr.positive_text(false, &mut terms);
+
+        if terms.is_empty() {
+            None
+        } else {
+            Some(terms.join(" OR "))
+        }
+    }
+
+    /// Positive text words OR-ed together as substrings, matched against
+    /// `index_documents_trigram` for fuzzy-mode snippets. Near-misses that
+    /// contain none of the words get no snippet.
+    pub fn fuzzy_snippet_expression(&self) -> Option<String> {
+        let mut terms = Vec::new();
+        self.expr.positive_text(false, &mut terms);
+
+        let words: Vec<String> = terms
+            .iter()
+            .flat_map(|text| text_words(text).0)
+            .map(|word| quote_fts(&word))
+            .collect();
+
+        if words.is_empty() {
+            None
+        } else {
+            Some(words.join(" OR "))
+        }
+    }
+
+    /// Distinct trigrams of every text term that is not negated, used to rank
+    /// fuzzy matches by overlap.
+    pub fn fuzzy_trigrams(&self) -> Vec<String> {
+        let mut terms = Vec::new();
+        self.expr.positive_text(false, &mut terms);
+
+        let mut trigrams = Vec::new();
+        for trigram in terms.iter().flat_map(|text| text_trigrams(text)) {
+            if !trigrams.contains(&trigram) {
+                trigrams.push(trigram);
+            }
+        }
+        trigrams
+    }
+}
+
+impl Expr {
+    fn to_sql(&self, mode: SearchMode, negated: bool, params: &mut Vec<Value>) -> String {
+        match self {
+            Expr::And(left, right) => format!(
+                "({} AND {})",
+                left.to_sql(mode, negated, params),
+                right.to_sql(mode, negated, params)
+            ),
+            Expr::Or(left, right) => format!(
+                "({} OR {})",
+                left.to_sql(mode, negated, params),
+                right.to_sql(mode, negated, params)
+            ),
+            Expr::Not(inner) => format!("(NOT {})", inner.to_sql(mode, !negated, params)),
+            Expr::Text(text) if mode == SearchMode::Fuzzy && !negated => {
+                let trigrams = text_trigrams(text);
+                if trigrams.is_empty() {
+                    return Expr::Text(text.clone()).to_sql(SearchMode::Exact, negated, params);
+                }
+
+                let min_matches =
+                    ((trigrams.len() as f64 * *STORAGE_FUZZY_MIN_SIMILARITY).ceil() as i64).max(1);
+                let sql = format!(
+                    "d.id IN (SELECT document_id FROM ({}) GROUP BY document_id HAVING COUNT(*) >= ?)",
+                    trigram_union(trigrams.len())
+                );
+
+                params.extend(trigrams.into_iter().map(Value::Text));
+                params.push(Value::Integer(min_matches));
+                sql
+            }
+            Expr::Text(text) => {
+                params.push(Value::Text(text.clone()));
+                "d.id IN (SELECT document_id FROM index_documents WHERE index_documents MATCH ?)"
+                    .to_string()
+            }
+            Expr::Qualifier(qualifier) => qualifier.to_sql(params),
+        }
+    }
+
+    fn positive_text(&self, negated: bool, terms: &mut Vec<String>) {
+        match self {
+            Expr::And(left, right) | Expr::Or(left, right) => {
+                left.positive_text(negated, terms);
+                right.positive_text(negated, terms);
+            }
+            Expr::Not(inner) => inner.positive_text(!negated, terms),
+            Expr::Text(text) if !negated => terms.push(text.clone()),
+            _ => {}
+        }
+    }
+}
+
+impl Qualifier {
+    fn to_sql(&self, params: &mut Vec<Value>) -> String {
+        match self {
+            Qualifier::Extension(extensions) => {
+                let placeholders = vec!["?"; extensions.len()].join(", ");
+                for extension in extensions {
+                    params.push(Value::Text(extension.to_lowercase()));
+                }
+                format!("lower(d.extension) IN ({})", placeholders)
+            }
+            Qualifier::Path(path) => {
+                params.push(Value::Text(path.clone()));
+                "instr(d.path, ?) > 0".to_string()
+            }
+            Qualifier::Name(name) => {
+                params.push(Value::Text(name.to_lowercase()));
+                "instr(lower(d.filename), ?) > 0".to_string()
+            }
+            Qualifier::In(container_type) => {
+                params.push(Value::Text(container_type.to_str().to_string()));
+                "d.container_type = ?".to_string()
+            }
+            Qualifier::Status(status) => {
+                params.push(Value::Text(status.to_str().to_string()));
+                "d.status = ?".to_string()
+            }
+            Qualifier::Size(range) => range.to_sql("d.size", params),
+            Qualifier::Modified(range) => range.to_sql("d.modified", params),
+        }
+    }
+
+    fn parse(key: &str, value: &str) -> Result<Qualifier, QueryError> {
+        let invalid = || QueryError::InvalidValue {
+            key: key.to_string(),
+            value: value.to_string(),
+        };
+
+        if value.is_empty() {
+            return Err(invalid());
+        }
+
+        match key {
+            "ext" => {
+                let extensions: Vec<String> = value
+                    .split(',')
+                    .map(|ext| ext.trim().trim_start_matches('.').to_string())
+                    .filter(|ext| !ext.is_empty())
+                    .collect();
+
+                if extensions.is_empty() {
+                    return Err(invalid());
+                }
+                Ok(Qualifier::Extension(extensions))
+            }
+            "path" => Ok(Qualifier::Path(value.to_string())),
+            "name" => Ok(Qualifier::Name(value.to_string())),
+            "in" => match value.to_lowercase().as_str() {
+                "archive" | "zip" => Ok(Qualifier::In(ContainerType::Archive)),
+                "folder" | "dir" => Ok(Qualifier::In(ContainerType::Folder)),
+                _ => Err(invalid()),
+            },
+            "status" => {
+                let mut chars = value.chars();
+                let normalized = match chars.next() {
+                    Some(first) => first
+                        .to_uppercase()
+                        .chain(chars.flat_map(char::to_lowercase))
+                        .collect::<String>(),
+                    None => String::new(),
+                };
+                DocumentStatus::from_str(&normalized)
+                    .map(Qualifier::Status)
+                    .map_err(|_| invalid())
+            }
+            "size" => Range::parse(value, parse_size)
+                .map(Qualifier::Size)
+                .ok_or_else(invalid),
+            "modified" => Range::parse(value, parse_date)
+                .map(Qualifier::Modified)
+                .ok_or_else(invalid),
+            _ => Err(invalid()),
+        }
+    }
+}
+
+impl Range {
+    pub fn get_min(&self) -> Option<i64> {
+        self.min
+    }
+
+    pub fn get_max(&self) -> Option<i64> {
+        self.max
+    }
+
+    fn to_sql(self, column: &str, params: &mut Vec<Value>) -> String {
+        let mut conditions = Vec::new();
+
+        if let Some(min) = self.min {
+            params.push(Value::Integer(min));
+            conditions.push(format!("{} >= ?", column));
+        }
+        if let Some(max) = self.max {
+            params.push(Value::Integer(max));
+            conditions.push(format!("{} < ?", column));
+        }
+
+        if conditions.is_empty() {
+            format!("{} IS NOT NULL", column)
+        } else {
+            format!("({})", conditions.join(" AND "))
+        }
+    }
+
+    /// `parse_bound` returns the half-open interval `[start, end)` covered by a
+    /// single value, e.g. a whole month for `2025-03`.
+    fn parse(value: &str, parse_bound: fn(&str) -> Option<(i64, i64)>) -> Option<Range> {
+        if let Some((from, to)) = value.split_once("..") {
+            let min = if from.is_empty() {
+                None
+            } else {
+                Some(parse_bound(from)?.0)
+            };
+            let max = if to.is_empty() {
+                None
+            } else {
+                Some(parse_bound(to)?.1)
+            };
+            return Some(Range { min, max });
+        }
+
+        if let Some(rest) = value.strip_prefix(">=") {
+            return Some(Range {
+                min: Some(parse_bound(rest)?.0),
+                max: None,
+            });
+        }
+        if let Some(rest) = value.strip_prefix("<=") {
+            return Some(Range {
+                min: None,
+                max: Some(parse_bound(rest)?.1),
+            });
+        }
+        if let Some(rest) = value.strip_prefix('>') {
+            return Some(Range {
+                min: Some(parse_bound(rest)?.1),
+                max: None,
+            });
+        }
+        if let Some(rest) = value.strip_prefix('<') {
+            return Some(Range {
+                min: None,
+                max: Some(parse_bound(rest)?.0),
+            });
+        }
+
+        let (start, end) = parse_bound(value)?;
+        Some(Range {
+            min: Some(start),
+            max: Some(end),
+        })
+    }
+}
+
+/// `10MB`, `1.5gb`, `512` (bytes). Units are binary (1KB = 1024 bytes).
+fn parse_size(value: &str) -> Option<(i64, i64)> {
+    let value = value.trim().to_uppercase();
+    let split = value
+        .find(|c: char| !(c.is_ascii_digit() || c == '.'))
+        .unwrap_or(value.len());
+    let (number, unit) = value.split_at(split);
+
+    let multiplier: f64 = match unit.trim() {
+        "" | "B" => 1.0,
+        "K" | "KB" => 1024.0,
+        "M" | "MB" => 1024.0 * 1024.0,
+        "G" | "GB" => 1024.0 * 1024.0 * 1024.0,
+        "T" | "TB" => 1024.0 * 1024.0 * 1024.0 * 1024.0,
+        _ => return None,
+    };
+
+    let bytes = (number.parse::<f64>().ok()? * multiplier) as i64;
+    Some((bytes, bytes + 1))
+}
+
+/// `2025`, `2025-03` or `2025-03-14`, as unix seconds (UTC).
+fn parse_date(value: &str) -> Option<(i64, i64)> {
+    let parts: Vec<&str> = value.trim().split('-').collect();
+    let year: i32 = parts.first()?.parse().ok()?;
+
+    let (start, end) = match parts.len() {
+        1 => (
+            NaiveDate::from_ymd_opt(year, 1, 1)?,
+            NaiveDate::from_ymd_opt(year + 1, 1, 1)?,
+        ),
+        2 => {
+            let month: u32 = parts[1].parse().ok()?;
+            let start = NaiveDate::from_ymd_opt(year, month, 1)?;
+            let end = if month == 12 {
+                NaiveDate::from_ymd_opt(year + 1, 1, 1)?
+            } else {
+                NaiveDate::from_ymd_opt(year, month + 1, 1)?
+            };
+            (start, end)
+        }
+        3 => {
+            let start =
+                NaiveDate::from_ymd_opt(year, parts[1].parse().ok()?, parts[2].parse().ok()?)?;
+            (start, start.succ_opt()?)
+        }
+        _ => return None,
+    };
+
+    let timestamp = |date: NaiveDate| date.and_hms_opt(0, 0, 0).map(|dt| dt.and_utc().timestamp());
+
+    Some((timestamp(start)?, timestamp(end)?))
+}
+
+fn quote_fts(text: &str) -> String {
+    format!("\"{}\"", text.replace('"', "\"\""))
+}
+
+/// Lowercased words of a text term that are long enough to have trigrams,
+/// and whether the term is a `prefix*`. `NEAR` groups contribute their words;
+/// distances and operators are ignored.
+fn text_words(text: &str) -> (Vec<String>, bool) {
+    // Drop the distance of `NEAR(a b, 5)`.
+    let words = match text.rsplit_once(',') {
+        Some((words, distance))
+            if text.starts_with("NEAR(")
+                && distance.trim_end_matches(')').trim().parse::<u32>().is_ok() =>
+        {
+            words
+        }
+        _ => text,
+    };
+
+    let words = words
+        .split(|c: char| !c.is_alphanumeric())
+        .filter(|word| *word != "NEAR" && word.chars().count() >= TRIGRAM_LENGTH)
+        .map(str::to_lowercase)
+        .collect();
+
+    (words, text.ends_with('*'))
+}
+
+/// Quoted FTS5 trigram phrases for the words of a text term. Words are padded
+/// with a space on both sides, as in `pg_trgm`, so that word boundaries count
+/// and a misspelt letter still leaves a few trigrams in common; `prefix*`
+/// terms are only padded in front.
+fn text_trigrams(text: &str) -> Vec<String> {
+    let (words, prefix) = text_words(text);
+    let mut trigrams = Vec::new();
+
+    for word in words {
+        let mut chars: Vec<char> = std::iter::once(' ').chain(word.chars()).collect();
+        if !prefix {
+            chars.push(' ');
+        }
+
+        for window in chars.windows(TRIGRAM_LENGTH) {
+            let trigram = quote_fts(&window.iter().collect::<String>());
+            if !trigrams.contains(&trigram) {
+                trigrams.push(trigram);
+            }
+        }
+    }
+
+    trigrams
+}
+
+/// `count` single-trigram lookups on `index_documents_trigram`, one row per
+/// matching document and trigram.
+pub fn trigram_union(count: usize) -> String {
+    vec![
+        "SELECT document_id FROM index_documents_trigram WHERE index_documents_trigram MATCH ?";
+        count
+    ]
+    .join(" UNION ALL ")
+}
+
+fn tokenize(input: &str) -> Result<Vec<Token>, QueryError> {
+    let chars: Vec<char> = input.chars().collect();
+    let mut tokens = Vec::new();
+    let mut i = 0;
+
+    while i < chars.len() {
+        let c = chars[i];
+
+        if c.is_whitespace() {
+            i += 1;
+            continue;
+        }
+
+        match c {
+            '(' => {
+                tokens.push(Token::LParen);
+                i += 1;
+            }
+            ')' => {
+                tokens.push(Token::RParen);
+                i += 1;
+            }
+            '"' => {
+                let (text, next) = read_quoted(&chars, i)?;
+                tokens.push(Token::Phrase(text));
+                i = next;
+            }
+            '-' if i + 1 < chars.len() && !chars[i + 1].is_whitespace() => {
+                tokens.push(Token::Not);
+                i += 1;
+            }
+            _ => {
+                let start = i;
+                while i < chars.len()
+                    && !chars[i].is_whitespace()
+                    && chars[i] != '('
+                    && chars[i] != ')'
+                    && chars[i] != '"'
+                {
+                    i += 1;
+                }
+                let word: String = chars[start..i].iter().collect();
+
+                // `NEAR(a b, 5)` is passed through to FTS5 untouched.
+                if word == "NEAR" && i < chars.len() && chars[i] == '(' {
+                    let close = chars[i..]
+                        .iter()
+                        .position(|&c| c == ')')
+                        .ok_or(QueryError::UnbalancedParenthesis)?;
+                    let group: String = chars[start..i + close + 1].iter().collect();
+                    tokens.push(Token::Near(group));
+                    i += close + 1;
+                    continue;
+                }
+
+                match word.as_str() {
+                    "AND" => tokens.push(Token::And),
+                    "OR" => tokens.push(Token::Or),
+                    "NOT" => tokens.push(Token::Not),
+                    _ => match word.split_once(':') {
+                        Some((key, value))
+                            if QUALIFIER_KEYS.contains(&key.to_lowercase().as_str()) =>
+                        {
+                            let value = if value.is_empty() && i < chars.len() && chars[i] == '"' {
+                                let (text, next) = read_quoted(&chars, i)?;
+                                i = next;
+                                text
+                            } else {
+                                value.to_string()
+                            };
+                            tokens.push(Token::Qualifier(key.to_lowercase(), value));
+                        }
+                        _ => tokens.push(Token::Term(word)),
+                    },
+                }
+            }
+        }
+    }
+
+    Ok(tokens)
+}
+
+fn read_quoted(chars: &[char], start: usize) -> Result<(String, usize), QueryError> {
+    let end = chars[start + 1..]
+        .iter()
+        .position(|&c| c == '"')
+        .map(|offset| start + 1 + offset)
+        .ok_or_else(|| QueryError::UnexpectedToken("unterminated \"".to_string()))?;
+
+    Ok((chars[start + 1..end].iter().collect(), end + 1))
+}
+
+struct Parser {
+    tokens: Vec<Token>,
+    pos: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.pos)
+    }
+
+    fn next(&mut self) -> Option<Token> {
+        let token = self.tokens.get(self.pos).cloned();
+        self.pos += 1;
+        token
+    }
+
+    fn parse_or(&mut self) -> Result<Expr, QueryError> {
+        let mut left = self.parse_and()?;
+
+        while self.peek() == Some(&Token::Or) {
+            self.next();
+            let right = self.parse_and()?;
+            left = Expr::Or(Box::new(left), Box::new(right));
+        }
+
+        Ok(left)
+    }
+
+    fn parse_and(&mut self) -> Result<Expr, QueryError> {
+        let mut left = self.parse_unary()?;
+
+        loop {
+            match self.peek() {
+                Some(Token::And) => {
+                    self.next();
+                }
+                Some(Token::Or) | Some(Token::RParen) | None => break,
+                Some(_) => {}
+            }
+
+            let right = self.parse_unary()?;
+            left = Expr::And(Box::new(left), Box::new(right));
+        }
+
+        Ok(left)
+    }
+
+    fn parse_unary(&mut self) -> Result<Expr, QueryError> {
+        if self.peek() == Some(&Token::Not) {
+            self.next();
+            return Ok(Expr::Not(Box::new(self.parse_unary()?)));
+        }
+
+        self.parse_primary()
+    }
+
+    fn parse_primary(&mut self) -> Result<Expr, QueryError> {
+        match self.next() {
+            Some(Token::LParen) => {
+                let expr = self.parse_or()?;
+                match self.next() {
+                    Some(Token::RParen) => Ok(expr),
+                    _ => Err(QueryError::UnbalancedParenthesis),
+                }
+            }
+            Some(Token::Term(term)) => Ok(Expr::Text(match term.strip_suffix('*') {
+                Some(prefix) if !prefix.is_empty() => format!("{}*", quote_fts(prefix)),
+                _ => quote_fts(&term),
+            })),
+            Some(Token::Phrase(phrase)) => Ok(Expr::Text(quote_fts(&phrase))),
+            Some(Token::Near(group)) => Ok(Expr::Text(group)),
+            Some(Token::Qualifier(key, value)) => {
+                Qualifier::parse(&key, &value).map(Expr::Qualifier)
+            }
+            Some(Token::RParen) => Err(QueryError::UnbalancedParenthesis),
+            Some(token) => Err(QueryError::UnexpectedToken(format!("{:?}", token))),
+            None => Err(QueryError::EmptyQuery),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(input: &str) -> Expr {
+        Query::parse(input)
+            .unwrap_or_else(|e| panic!("failed to parse {:?}: {:?}", input, e))
+            .expr
+    }
+
+    fn text(fts: &str) -> Expr {
+        Expr::Text(fts.to_string())
+    }
+
+    fn and(left: Expr, right: Expr) -> Expr {
+        Expr::And(Box::new(left), Box::new(right))
+    }
+
+    fn or(left: Expr, right: Expr) -> Expr {
+        Expr::Or(Box::new(left), Box::new(right))
+    }
+
+    fn not(inner: Expr) -> Expr {
+        Expr::Not(Box::new(inner))
+    }
+
+    fn qualifier(input: &str) -> Qualifier {
+        match parse(input) {
+            Expr::Qualifier(qualifier) => qualifier,
+            expr => panic!("{:?} is not a qualifier: {:?}", input, expr),
+        }
+    }
+
+    #[test]
+    fn terms_are_quoted() {
+        assert_eq!(parse("budget"), text("\"budget\""));
+        assert_eq!(parse("fore*"), text("\"fore\"*"));
+        assert_eq!(parse("*"), text("\"*\""));
+    }
+
+    #[test]
+    fn phrases_are_quoted_as_one_term() {
+        assert_eq!(parse("\"annual report\""), text("\"annual report\""));
+        assert_eq!(
+            parse("\"annual report\" 2025"),
+            and(text("\"annual report\""), text("\"2025\""))
+        );
+    }
+
+    #[test]
+    fn fts_syntax_in_terms_is_escaped() {
+        assert_eq!(parse("a:b"), text("\"a:b\""));
+        assert_eq!(parse("c++"), text("\"c++\""));
+        assert_eq!(parse("x^y"), text("\"x^y\""));
+        assert_eq!(parse("and"), text("\"and\""));
+        assert_eq!(quote_fts("say \"hi\""), "\"say \"\"hi\"\"\"");
+    }
+
+    #[test]
+    fn near_groups_are_passed_through() {
+        assert_eq!(parse("NEAR(alpha beta, 5)"), text("NEAR(alpha beta, 5)"));
+    }
+
+    #[test]
+    fn operators_and_precedence() {
+        assert_eq!(
+            parse("a b OR c"),
+            or(and(text("\"a\""), text("\"b\"")), text("\"c\""))
+        );
+        assert_eq!(parse("a AND b"), and(text("\"a\""), text("\"b\"")));
+        assert_eq!(
+            parse("a (b OR c)"),
+            and(text("\"a\""), or(text("\"b\""), text("\"c\"")))
+        );
+    }
+
+    #[test]
+    fn negation() {
+        assert_eq!(parse("-draft"), not(text("\"draft\"")));
+        assert_eq!(parse("NOT draft"), not(text("\"draft\"")));
+        assert_eq!(
+            parse("budget -draft"),
+            and(text("\"budget\""), not(text("\"draft\"")))
+        );
+        assert_eq!(parse("-(a OR b)"), not(or(text("\"a\""), text("\"b\""))));
+        // A lone dash is a term, not an operator.
+        assert_eq!(
+            parse("a - b"),
+            and(and(text("\"a\""), text("\"-\"")), text("\"b\""))
+        );
+    }
+
+    #[test]
+    fn ext_qualifier() {
+        assert_eq!(
+            qualifier("ext:pdf,.DOCX"),
+            Qualifier::Extension(vec!["pdf".to_string(), "DOCX".to_string()])
+        );
+        assert_eq!(
+            qualifier("EXT:txt"),
+            Qualifier::Extension(vec!["txt".to_string()])
+        );
+    }
+
+    #[test]
+    fn path_and_name_qualifiers() {
+        assert_eq!(
+            qualifier("path:/projects/"),
+            Qualifier::Path("/projects/".to_string())
+        );
+        assert_eq!(
+            qualifier("path:\"/my documents/\""),
+            Qualifier::Path("/my documents/".to_string())
+        );
+        assert_eq!(
+            qualifier("name:Report"),
+            Qualifier::Name("Report".to_string())
+        );
+    }
+
+    #[test]
+    fn in_and_status_qualifiers() {
+        assert_eq!(
+            qualifier("in:archive"),
+            Qualifier::In(ContainerType::Archive)
+        );
+        assert_eq!(qualifier("in:zip"), Qualifier::In(ContainerType::Archive));
+        assert_eq!(qualifier("in:dir"), Qualifier::In(ContainerType::Folder));
+        assert_eq!(
+            qualifier("status:extracted"),
+            Qualifier::Status(DocumentStatus::Extracted)
+        );
+        assert_eq!(
+            qualifier("status:FAILED"),
+            Qualifier::Status(DocumentStatus::Failed)
+        );
+    }
+
+    #[test]
+    fn size_qualifier() {
+        const MB: i64 = 1024 * 1024;
+
+        let range = |min, max| Qualifier::Size(Range { min, max });
+        assert_eq!(qualifier("size:>10MB"), range(Some(10 * MB + 1), None));
+        assert_eq!(qualifier("size:>=10mb"), range(Some(10 * MB), None));
+        assert_eq!(qualifier("size:<1KB"), range(None, Some(1024)));
+        assert_eq!(qualifier("size:<=1KB"), range(None, Some(1025)));
+        assert_eq!(
+            qualifier("size:1MB..5MB"),
+            range(Some(MB), Some(5 * MB + 1))
+        );
+        assert_eq!(qualifier("size:512"), range(Some(512), Some(513)));
+        assert_eq!(qualifier("size:1.5k"), range(Some(1536), Some(1537)));
+    }
+
+    #[test]
+    fn modified_qualifier() {
+        const MARCH_2025: i64 = 1_740_787_200;
+        const APRIL_2025: i64 = 1_743_465_600;
+        const DAY: i64 = 24 * 60 * 60;
+
+        let range = |min, max| Qualifier::Modified(Range { min, max });
+        assert_eq!(
+            qualifier("modified:2025-03"),
+            range(Some(MARCH_2025), Some(APRIL_2025))
+        );
+        assert_eq!(
+            qualifier("modified:>=2025-03-01"),
+            range(Some(MARCH_2025), None)
+        );
+        assert_eq!(
+            qualifier("modified:<2025-04"),
+            range(None, Some(APRIL_2025))
+        );
+        assert_eq!(
+            qualifier("modified:2025-03-01"),
+            range(Some(MARCH_2025), Some(MARCH_2025 + DAY))
+        );
+        assert_eq!(
+            qualifier("modified:2025-03..2025-03"),
+            range(Some(MARCH_2025), Some(APRIL_2025))
+        );
+        assert_eq!(
+            qualifier("modified:..2025-03"),
+            range(None, Some(APRIL_2025))
+        );
+    }
+
+    #[test]
+    fn unknown_keys_are_terms() {
+        assert_eq!(parse("http://example"), text("\"http://example\""));
+    }
+
+    #[test]
+    fn malformed_queries() {
+        let invalid = |key: &str, value: &str| QueryError::InvalidValue {
+            key: key.to_string(),
+            value: value.to_string(),
+        };
+
+        assert_eq!(Query::parse(""), Err(QueryError::EmptyQuery));
+        assert_eq!(Query::parse("   "), Err(QueryError::EmptyQuery));
+        assert_eq!(Query::parse("a OR"), Err(QueryError::EmptyQuery));
+        assert_eq!(Query::parse("(a"), Err(QueryError::UnbalancedParenthesis));
+        assert_eq!(Query::parse("a)"), Err(QueryError::UnbalancedParenthesis));
+        assert_eq!(Query::parse("()"), Err(QueryError::UnbalancedParenthesis));
+        assert_eq!(
+            Query::parse("NEAR(a b"),
+            Err(QueryError::UnbalancedParenthesis)
+        );
+        assert!(matches!(
+            Query::parse("\"unterminated"),
+            Err(QueryError::UnexpectedToken(_))
+        ));
+        assert!(matches!(
+            Query::parse("AND a"),
+            Err(QueryError::UnexpectedToken(_))
+        ));
+        assert_eq!(Query::parse("ext:"), Err(invalid("ext", "")));
+        assert_eq!(Query::parse("ext:,"), Err(invalid("ext", ",")));
+        assert_eq!(Query::parse("in:cloud"), Err(invalid("in", "cloud")));
+        assert_eq!(
+            Query::parse("status:bogus"),
+            Err(invalid("status", "bogus"))
+        );
+        assert_eq!(Query::parse("size:huge"), Err(invalid("size", "huge")));
+        assert_eq!(Query::parse("size:10XB"), Err(invalid("size", "10XB")));
+        assert_eq!(
+            Query::parse("modified:2025-13"),
+            Err(invalid("modified", "2025-13"))
+        );
+        assert_eq!(
+            Query::parse("modified:yesterday"),
+            Err(invalid("modified", "yesterday"))
+        );
+    }
+
+    #[test]
+    fn to_sql_binds_every_value() {
+        let query = Query::parse("ext:PDF -draft").unwrap();
+        let (sql, params) = query.to_sql();
+
+        assert_eq!(
+            sql,
+            "(lower(d.extension) IN (?) AND (NOT d.id IN (SELECT document_id FROM index_documents WHERE index_documents MATCH ?)))"
+        );
+        assert_eq!(
+            params,
+            vec![
+                Value::Text("pdf".to_string()),
+                Value::Text("\"draft\"".to_string())
+            ]
+        );
+    }
+
+    #[test]
+    fn rank_expression_skips_negated_terms() {
+        let query = Query::parse("budget -draft \"q1 plan\" ext:pdf").unwrap();
+        assert_eq!(
+            query.rank_expression().as_deref(),
+            Some("\"budget\" OR \"q1 plan\"")
+        );
+        assert_eq!(Query::parse("ext:pdf").unwrap().rank_expression(), None);
+    }
+}